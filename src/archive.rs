@@ -0,0 +1,279 @@
+//! Packages a downloaded pool into an archive in-process: zip and cbz, stored
+//! or deflated, and tar.zst. Entries are written in pool order, so readers
+//! that go by archive order page through it correctly. Only 7z still needs
+//! the external `7z` executable (see [`crate::commands::zip_downloads`]).
+//!
+//! [`PoolArchive`] builds a zip while the pool is still downloading, for
+//! `d-pool --archive`.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use indicatif::ProgressBar;
+use tracing::warn;
+use zip::CompressionMethod;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+/// A file to put into an archive, under its file name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+}
+
+/// The files of `dir` to archive, in pool order: by their index prefix
+/// (`0001-`), then by name, with unnumbered files last. Subdirectories,
+/// e-cli's own state files (named with a leading `.`) and unfinished `.part`
+/// downloads are left out.
+pub fn entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let name = item.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name.ends_with(".part") {
+            continue;
+        }
+        let metadata = item.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        entries.push(Entry {
+            path: item.path(),
+            name,
+            size: metadata.len(),
+        });
+    }
+    entries.sort_by(|a, b| {
+        let key = |entry: &Entry| pool_index(&entry.name).unwrap_or(u64::MAX);
+        key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(entries)
+}
+
+/// The position a pool download's file name starts with, e.g. 12 for
+/// `0012-artist-123.png`.
+pub(crate) fn pool_index(name: &str) -> Option<u64> {
+    let (index, _) = name.split_once('-')?;
+    index.parse().ok()
+}
+
+/// Writes `entries` into a zip archive at `out`, deflating them unless
+/// `store` is set. Files are added with a fixed timestamp, so the same files
+/// always make the same archive.
+pub fn write_zip(
+    entries: &[Entry],
+    out: &Path,
+    store: bool,
+    progress: &ProgressBar,
+) -> Result<(), String> {
+    let method = if store {
+        CompressionMethod::Stored
+    } else {
+        CompressionMethod::Deflated
+    };
+    write_replacing(out, |file| {
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        for entry in entries {
+            zip.start_file(entry.name.as_str(), zip_options(method, entry.size))
+                .map_err(io::Error::other)?;
+            io::copy(&mut progress.wrap_read(File::open(&entry.path)?), &mut zip)?;
+        }
+        let buffer = zip.finish().map_err(io::Error::other)?;
+        buffer.into_inner().map_err(io::IntoInnerError::into_error)
+    })
+}
+
+/// Options for a zip entry of `size` bytes. Entries keep the default fixed
+/// timestamp, so the same files always make the same archive.
+fn zip_options(method: CompressionMethod, size: u64) -> SimpleFileOptions {
+    SimpleFileOptions::default()
+        .compression_method(method)
+        .large_file(size > u64::from(u32::MAX))
+}
+
+/// Writes `entries` into a zstd-compressed tar archive at `out`, with
+/// normalized timestamps and permissions like [`write_zip`].
+pub fn write_tar_zst(entries: &[Entry], out: &Path, progress: &ProgressBar) -> Result<(), String> {
+    write_replacing(out, |file| {
+        let encoder = zstd::Encoder::new(BufWriter::new(file), 0)?;
+        let mut tar = tar::Builder::new(encoder);
+        for entry in entries {
+            let source = File::open(&entry.path)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata_in_mode(&source.metadata()?, tar::HeaderMode::Deterministic);
+            tar.append_data(&mut header, &entry.name, progress.wrap_read(source))?;
+        }
+        let buffer = tar.into_inner()?.finish()?;
+        buffer.into_inner().map_err(io::IntoInnerError::into_error)
+    })
+}
+
+/// Writes an archive to a temporary file next to `out` with `write`, then
+/// renames it over `out`, so a failed or interrupted run never leaves a
+/// truncated archive behind.
+pub(crate) fn write_replacing(
+    out: &Path,
+    write: impl FnOnce(File) -> io::Result<File>,
+) -> Result<(), String> {
+    let mut temp_name = out.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = out.with_file_name(temp_name);
+    let result = File::create(&temp)
+        .and_then(write)
+        .and_then(|file| file.sync_all())
+        .and_then(|()| fs::rename(&temp, out));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(format!("Failed to write {}: {e}", out.display()));
+    }
+    Ok(())
+}
+
+/// A zip archive of a pool, filled page by page as the pages finish
+/// downloading, in any order. A page is appended once every page before it
+/// has been appended or left out; until then it waits where it was
+/// downloaded.
+///
+/// The archive is kept at a path of its own until [`PoolArchive::complete`]
+/// moves it into place. [`PoolArchive::suspend`] closes it so that
+/// [`PoolArchive::open`] can carry on from the same page later.
+pub struct PoolArchive {
+    zip: ZipWriter<File>,
+    path: PathBuf,
+    /// The pool index of the next page to append.
+    next: u64,
+    /// Pages finished out of order, waiting for the ones before them:
+    /// `None` for a page that's left out.
+    waiting: BTreeMap<u64, Option<PathBuf>>,
+    /// Entries added so far, not counting left-out pages.
+    pages: usize,
+}
+
+impl PoolArchive {
+    /// Opens the unfinished archive at `path`, or starts a new one there. An
+    /// archive that wasn't closed with [`PoolArchive::suspend`] (say, after a
+    /// crash) can't be carried on from and is started over.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("Failed to open {}: {e}", path.display());
+        let resumed = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => Self::resume(file)
+                .inspect_err(|e| {
+                    warn!("Starting {} over, it can't be resumed: {e}", path.display())
+                })
+                .ok(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(error(&e)),
+        };
+        let (zip, next, pages) = match resumed {
+            Some(resumed) => resumed,
+            None => (
+                ZipWriter::new(File::create(path).map_err(|e| error(&e))?),
+                1,
+                0,
+            ),
+        };
+        Ok(Self {
+            zip,
+            path: path.to_path_buf(),
+            next,
+            waiting: BTreeMap::new(),
+            pages,
+        })
+    }
+
+    /// Reopens a suspended archive for appending, with the page to carry on
+    /// from and the number of pages in it.
+    fn resume(mut file: File) -> zip::result::ZipResult<(ZipWriter<File>, u64, usize)> {
+        let (last, pages) = {
+            let archive = zip::ZipArchive::new(&mut file)?;
+            let last = archive.file_names().filter_map(pool_index).max();
+            (last.unwrap_or(0), archive.len())
+        };
+        Ok((ZipWriter::new_append(file)?, last + 1, pages))
+    }
+
+    /// The pool index of the first page not in the archive yet. Pages before
+    /// it don't need downloading again.
+    pub fn next_index(&self) -> u64 {
+        self.next
+    }
+
+    /// Takes in the page at pool `index`: the downloaded file at `page`, or
+    /// `None` to leave the page out. Appends it, and any waiting pages after
+    /// it, if it's the next one due; appended files are deleted.
+    pub fn add(&mut self, index: u64, page: Option<PathBuf>) -> Result<(), String> {
+        if index < self.next {
+            return Ok(());
+        }
+        self.waiting.insert(index, page);
+        while let Some(page) = self.waiting.remove(&self.next) {
+            if let Some(path) = page {
+                self.append(&path).map_err(|e| {
+                    format!(
+                        "Failed to add {} to {}: {e}",
+                        path.display(),
+                        self.path.display()
+                    )
+                })?;
+                self.pages += 1;
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, path: &Path) -> io::Result<()> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let size = fs::metadata(path)?.len();
+        self.zip
+            .start_file(name, zip_options(CompressionMethod::Deflated, size))
+            .map_err(io::Error::other)?;
+        io::copy(&mut File::open(path)?, &mut self.zip)?;
+        fs::remove_file(path)
+    }
+
+    /// Whether every page up to `last` has been appended or left out.
+    pub fn is_complete(&self, last: u64) -> bool {
+        self.next > last
+    }
+
+    /// Number of pages in the archive.
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Closes the archive where it is, to carry on from with
+    /// [`PoolArchive::open`]. Pages still waiting stay where they were
+    /// downloaded.
+    pub fn suspend(self) -> Result<(), String> {
+        self.zip
+            .finish()
+            .and_then(|file| file.sync_all().map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}: {e}", self.path.display()))
+    }
+
+    /// Adds `extra` files (name and contents, e.g. a `ComicInfo.xml`) after
+    /// the pages, closes the archive and moves it to `out`.
+    pub fn complete(mut self, extra: &[(&str, &[u8])], out: &Path) -> Result<(), String> {
+        let error = |e: &dyn std::fmt::Display| format!("Failed to write {}: {e}", out.display());
+        for (name, contents) in extra {
+            self.zip
+                .start_file(
+                    *name,
+                    zip_options(CompressionMethod::Deflated, contents.len() as u64),
+                )
+                .map_err(|e| error(&e))?;
+            io::Write::write_all(&mut self.zip, contents).map_err(|e| error(&e))?;
+        }
+        let file = self.zip.finish().map_err(|e| error(&e))?;
+        file.sync_all().map_err(|e| error(&e))?;
+        fs::rename(&self.path, out).map_err(|e| error(&e))
+    }
+}
+
+#[cfg(test)]
+#[path = "archive_tests.rs"]
+mod tests;
@@ -0,0 +1,157 @@
+use super::*;
+
+fn pool_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for (name, contents) in [
+        ("0010-artist-3.png", "ten"),
+        ("0002-artist-2.png", "two"),
+        ("0001-artist-1.jpg", "one"),
+        ("cover.png", "cover"),
+        ("0003-artist-4.png.part", "partial"),
+        (".e-cli.lock", "123"),
+        (".e-cli-md5.json", "{}"),
+    ] {
+        fs::write(dir.path().join(name), contents).expect("write");
+    }
+    fs::create_dir(dir.path().join("extra")).expect("mkdir");
+    dir
+}
+
+fn names(entries: &[Entry]) -> Vec<&str> {
+    entries.iter().map(|entry| entry.name.as_str()).collect()
+}
+
+#[test]
+fn entries_follow_the_pool_index_and_skip_state_files() {
+    let dir = pool_dir();
+    let entries = entries(dir.path()).expect("entries");
+    assert_eq!(
+        names(&entries),
+        vec![
+            "0001-artist-1.jpg",
+            "0002-artist-2.png",
+            "0010-artist-3.png",
+            "cover.png"
+        ]
+    );
+    assert_eq!(entries[0].size, 3);
+}
+
+#[test]
+fn zip_archives_keep_entry_order_and_contents() {
+    let dir = pool_dir();
+    let entries = entries(dir.path()).expect("entries");
+    let out_dir = tempfile::tempdir().expect("tempdir");
+
+    for store in [true, false] {
+        let out = out_dir.path().join(format!("pool-{store}.cbz"));
+        write_zip(&entries, &out, store, &ProgressBar::hidden()).expect("write zip");
+
+        let mut archive = zip::ZipArchive::new(File::open(&out).expect("open")).expect("read zip");
+        let archived = (0..archive.len())
+            .map(|i| archive.by_index(i).expect("entry").name().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(archived, names(&entries));
+        let mut first = String::new();
+        io::Read::read_to_string(&mut archive.by_index(0).expect("entry"), &mut first)
+            .expect("read entry");
+        assert_eq!(first, "one");
+    }
+
+    // The same files make the same archive.
+    let again = out_dir.path().join("again.cbz");
+    write_zip(&entries, &again, true, &ProgressBar::hidden()).expect("write zip");
+    assert_eq!(
+        fs::read(&again).expect("read"),
+        fs::read(out_dir.path().join("pool-true.cbz")).expect("read")
+    );
+    assert_eq!(fs::read_dir(out_dir.path()).expect("read_dir").count(), 3);
+}
+
+#[test]
+fn tar_zst_archives_keep_entry_order_and_contents() {
+    let dir = pool_dir();
+    let entries = entries(dir.path()).expect("entries");
+    let out_dir = tempfile::tempdir().expect("tempdir");
+    let out = out_dir.path().join("pool.tar.zst");
+
+    write_tar_zst(&entries, &out, &ProgressBar::hidden()).expect("write tar.zst");
+
+    let decoder = zstd::Decoder::new(File::open(&out).expect("open")).expect("decoder");
+    let mut archive = tar::Archive::new(decoder);
+    let mut archived = Vec::new();
+    for entry in archive.entries().expect("entries") {
+        let mut entry = entry.expect("entry");
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut entry, &mut contents).expect("read entry");
+        archived.push((
+            entry.path().expect("path").to_string_lossy().into_owned(),
+            contents,
+        ));
+    }
+    assert_eq!(archived.len(), 4);
+    assert_eq!(
+        archived[0],
+        ("0001-artist-1.jpg".to_owned(), "one".to_owned())
+    );
+    assert_eq!(archived[2].0, "0010-artist-3.png");
+}
+
+#[test]
+fn pool_archives_append_in_order_and_resume() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let page = |index: u64| {
+        let path = dir.path().join(format!("{index:04}-artist-{index}.png"));
+        fs::write(&path, format!("page {index}")).expect("write page");
+        Some(path)
+    };
+    let partial = dir.path().join(".e-cli-archive.zip");
+
+    let mut archive = PoolArchive::open(&partial).expect("open");
+    archive.add(2, page(2)).expect("add");
+    archive.add(4, page(4)).expect("add");
+    assert_eq!(archive.next_index(), 1);
+    archive.add(1, page(1)).expect("add");
+    // Page 3 never finished; 4 waits for it.
+    assert_eq!(archive.next_index(), 3);
+    assert!(!dir.path().join("0001-artist-1.png").exists());
+    assert!(dir.path().join("0004-artist-4.png").exists());
+    archive.suspend().expect("suspend");
+
+    let mut archive = PoolArchive::open(&partial).expect("reopen");
+    assert_eq!(archive.next_index(), 3);
+    assert_eq!(archive.pages(), 2);
+    archive.add(3, None).expect("leave out");
+    archive.add(4, page(4)).expect("add");
+    assert!(archive.is_complete(4));
+
+    let out = dir.path().join("pool.cbz");
+    archive
+        .complete(&[("ComicInfo.xml", b"<ComicInfo/>")], &out)
+        .expect("complete");
+    assert!(!partial.exists());
+    let mut zip = zip::ZipArchive::new(File::open(&out).expect("open")).expect("read zip");
+    let names = (0..zip.len())
+        .map(|i| zip.by_index(i).expect("entry").name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        vec![
+            "0001-artist-1.png",
+            "0002-artist-2.png",
+            "0004-artist-4.png",
+            "ComicInfo.xml"
+        ]
+    );
+}
+
+#[test]
+fn unfinished_pool_archives_start_over() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let partial = dir.path().join(".e-cli-archive.zip");
+    fs::write(&partial, "PK\u{3}\u{4}truncated").expect("write");
+
+    let archive = PoolArchive::open(&partial).expect("open");
+    assert_eq!(archive.next_index(), 1);
+    assert_eq!(archive.pages(), 0);
+}
@@ -0,0 +1,162 @@
+//! Blocking versions of the download operations in [`crate::commands`], for
+//! callers without an async runtime of their own (the CLI and the TUI). Each
+//! takes the same arguments as its `async` counterpart and runs it to
+//! completion on the shared runtime (see [`crate::runtime::block_on`]), so it
+//! must not be called from inside an async context.
+//!
+//! The synchronous helpers in [`crate::commands`] (`get_client`,
+//! `uploader_query`, `zip_downloads`) are used from there directly.
+
+use std::path::Path;
+
+use indicatif::MultiProgress;
+
+use crate::cli::ArchiveFormat;
+use crate::commands;
+use crate::config::PresetConfig;
+use crate::runtime::block_on;
+use crate::tracker::Tracker;
+use crate::type_defs::api_defs::PoolData;
+use crate::{CliContext, DownloadStatistics, Login};
+
+/// See [`commands::download_favourites`].
+#[allow(clippy::too_many_arguments)]
+pub fn download_favourites(
+    context: &CliContext,
+    login: &Login,
+    username: &str,
+    count: &u32,
+    random: &bool,
+    tags: &str,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_favourites(
+        context, login, username, count, random, tags, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::download_uploads`].
+#[allow(clippy::too_many_arguments)]
+pub fn download_uploads(
+    context: &CliContext,
+    login: &Login,
+    user: &str,
+    by_id: &bool,
+    count: &u32,
+    random: &bool,
+    tags: &str,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_uploads(
+        context, login, user, by_id, count, random, tags, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::download_search`].
+#[allow(clippy::too_many_arguments)]
+pub fn download_search(
+    context: &CliContext,
+    login: &Login,
+    tags: &str,
+    page_count: &u32,
+    random: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_search(
+        context, login, tags, page_count, random, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::download_artist`].
+#[allow(clippy::too_many_arguments)]
+pub fn download_artist(
+    context: &CliContext,
+    login: &Login,
+    name: &str,
+    count: &u32,
+    write_urls: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_artist(
+        context, login, name, count, write_urls, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::download_pool`].
+pub fn download_pool(
+    context: &CliContext,
+    login: &Login,
+    pool_id: &u64,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_pool(
+        context, login, pool_id, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::download_pools`].
+pub fn download_pools(
+    context: &CliContext,
+    login: &Login,
+    pools: &[PoolData],
+    archive: Option<ArchiveFormat>,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_pools(
+        context, login, pools, archive, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::resolve_pools`].
+pub fn resolve_pools(
+    context: &CliContext,
+    login: &Login,
+    queries: &[String],
+    series: bool,
+) -> Result<Vec<PoolData>, String> {
+    block_on(commands::resolve_pools(context, login, queries, series))
+}
+
+/// See [`commands::download_set`].
+pub fn download_set(
+    context: &CliContext,
+    login: &Login,
+    set: &str,
+    indexed: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_set(
+        context, login, set, indexed, mp, output_dir, tracker,
+    ))
+}
+
+/// See [`commands::download_preset`].
+#[allow(clippy::too_many_arguments)]
+pub fn download_preset(
+    context: &CliContext,
+    login: &Login,
+    preset: &PresetConfig,
+    count: &u32,
+    random: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    block_on(commands::download_preset(
+        context, login, preset, count, random, mp, output_dir, tracker,
+    ))
+}
@@ -0,0 +1,243 @@
+//! Bandwidth limiting, download budgets (`--limit-rate`, `--max-bytes`,
+//! `--max-files`) and the free disk space reserve (`--min-free`).
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// The error recorded for posts left undownloaded by a [`DiskReserve`].
+pub const LOW_SPACE_ERROR: &str = "not enough disk space";
+
+/// A token bucket shared by every transfer in a run, capping their combined
+/// speed at `bytes_per_sec`. Transfers take tokens for each chunk they
+/// receive, and wait whenever the bucket is in debt, so bursts of at most one
+/// second's worth of data get through before throttling kicks in.
+pub struct RateLimiter {
+    bytes_per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        Self {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_sec,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Records `bytes` as transferred, waiting as long as needed to keep the
+    /// overall rate under the limit.
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = self.take(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes `bytes` tokens at time `now`, returning how long the caller has
+    /// to wait for the bucket to be out of debt again.
+    fn take(&self, bytes: u64, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(bucket.refilled);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.bytes_per_sec).min(self.bytes_per_sec);
+        bucket.refilled = now;
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens < 0.0 {
+            Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Caps how much a run downloads, by total bytes and/or number of files.
+/// Every download reserves room before it starts; once a download doesn't fit,
+/// the budget is reached and no further downloads start, so the run stops
+/// between files rather than cutting one short. Posts that are skipped or
+/// already present don't count.
+pub struct Budget {
+    max_bytes: Option<u64>,
+    max_files: Option<u64>,
+    used: Mutex<Used>,
+}
+
+#[derive(Default)]
+struct Used {
+    bytes: u64,
+    files: u64,
+    reached: bool,
+}
+
+impl Budget {
+    pub fn new(max_bytes: Option<u64>, max_files: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            max_files,
+            used: Mutex::default(),
+        }
+    }
+
+    /// Reserves room for one more file of `size` bytes (`None` if the size
+    /// isn't known up front, in which case only the bytes already used are
+    /// checked). Returns `false`, and marks the budget as reached, when there
+    /// isn't room.
+    pub fn try_reserve(&self, size: Option<u64>) -> bool {
+        let mut used = self.used.lock().unwrap();
+        if used.reached {
+            return false;
+        }
+        let size = size.unwrap_or(0);
+        let files_fit = self.max_files.is_none_or(|max| used.files < max);
+        let bytes_fit = self
+            .max_bytes
+            .is_none_or(|max| used.bytes < max && used.bytes + size <= max);
+        if files_fit && bytes_fit {
+            used.files += 1;
+            used.bytes += size;
+            true
+        } else {
+            used.reached = true;
+            false
+        }
+    }
+
+    /// Settles a reservation made by [`try_reserve`](Self::try_reserve) with
+    /// the same `size`: a completed download counts the `written` bytes it
+    /// actually took, and a failed one (`written == None`) gives its
+    /// reservation back.
+    pub fn settle(&self, size: Option<u64>, written: Option<u64>) {
+        let mut used = self.used.lock().unwrap();
+        used.bytes = used.bytes - size.unwrap_or(0) + written.unwrap_or(0);
+        if written.is_none() {
+            used.files -= 1;
+        }
+    }
+
+    /// Whether a download has been turned away for lack of room.
+    pub fn is_reached(&self) -> bool {
+        self.used.lock().unwrap().reached
+    }
+}
+
+/// Keeps at least `reserve` bytes free on the disk a run downloads to. Every
+/// download checks for room first; once free space runs low, no further
+/// downloads start, as with a reached [`Budget`].
+pub struct DiskReserve {
+    dir: PathBuf,
+    reserve: u64,
+    warn_only: bool,
+    low: AtomicBool,
+}
+
+impl DiskReserve {
+    /// A reserve on the disk holding `dir`. With `warn_only`,
+    /// [`check_plan`](Self::check_plan) doesn't stop a run that looks too big,
+    /// but the reserve is still kept while it runs.
+    pub fn new(dir: &Path, reserve: u64, warn_only: bool) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            reserve,
+            warn_only,
+            low: AtomicBool::new(false),
+        }
+    }
+
+    /// Checks, before a run starts, that `bytes` of downloads fit while
+    /// leaving the reserve free. If not, returns the shortfall as an error
+    /// and, unless warning only, marks the disk as low so nothing starts.
+    pub fn check_plan(&self, bytes: u64) -> Result<(), String> {
+        let Ok(free) = available_space(&self.dir) else {
+            return Ok(());
+        };
+        if free >= bytes.saturating_add(self.reserve) {
+            return Ok(());
+        }
+        if !self.warn_only {
+            self.low.store(true, Ordering::Relaxed);
+        }
+        Err(format!(
+            "Not enough disk space in {}: about {:.2} MB to download plus {:.2} MB to keep free, but only {:.2} MB available.",
+            self.dir.display(),
+            bytes as f64 / 1024.0 / 1024.0,
+            self.reserve as f64 / 1024.0 / 1024.0,
+            free as f64 / 1024.0 / 1024.0
+        ))
+    }
+
+    /// Whether one more file of `size` bytes (`None` if unknown) fits while
+    /// leaving the reserve free. Returns `false`, and marks the disk as low,
+    /// when it doesn't. If free space can't be read, the file is let through.
+    pub fn has_room(&self, size: Option<u64>) -> bool {
+        if self.is_low() {
+            return false;
+        }
+        match available_space(&self.dir) {
+            Ok(free) if free < self.reserve.saturating_add(size.unwrap_or(0)) => {
+                self.low.store(true, Ordering::Relaxed);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether downloads have stopped for lack of disk space.
+    pub fn is_low(&self) -> bool {
+        self.low.load(Ordering::Relaxed)
+    }
+}
+
+/// Free space available to this user on the disk holding `dir`, which may
+/// not exist yet (its nearest existing ancestor is checked instead).
+pub fn available_space(dir: &Path) -> io::Result<u64> {
+    let existing = dir
+        .ancestors()
+        .find(|path| path.exists())
+        .unwrap_or(Path::new("."));
+    fs4::available_space(existing)
+}
+
+/// Parses a byte size such as `500K`, `2M`, `1.5G` or `750` (plain bytes).
+/// Suffixes are binary (`K` = 1024) and case-insensitive, and may be followed
+/// by `B`/`iB` (`2MB`, `2MiB`).
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let lower = trimmed.to_ascii_lowercase();
+    let unit_start = lower
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(unit_start);
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err(format!("Invalid size '{trimmed}': unknown unit '{unit}'.")),
+    };
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid size '{trimmed}': expected a number like 2M or 500K."))?;
+    let bytes = (number * multiplier as f64).round();
+    if bytes < 1.0 {
+        return Err(format!(
+            "Invalid size '{trimmed}': must be at least 1 byte."
+        ));
+    }
+    Ok(bytes as u64)
+}
+
+#[cfg(test)]
+#[path = "budget_tests.rs"]
+mod tests;
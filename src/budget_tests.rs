@@ -0,0 +1,105 @@
+use super::*;
+
+#[test]
+fn parse_size_accepts_units_and_fractions() {
+    assert_eq!(parse_size("750"), Ok(750));
+    assert_eq!(parse_size("500K"), Ok(500 * 1024));
+    assert_eq!(parse_size("2m"), Ok(2 * 1024 * 1024));
+    assert_eq!(parse_size("2MiB"), Ok(2 * 1024 * 1024));
+    assert_eq!(parse_size("1.5G"), Ok(3 * 512 * 1024 * 1024));
+    assert_eq!(parse_size(" 5GB "), Ok(5 * 1024 * 1024 * 1024));
+}
+
+#[test]
+fn parse_size_rejects_garbage() {
+    assert!(parse_size("").is_err());
+    assert!(parse_size("fast").is_err());
+    assert!(parse_size("2X").is_err());
+    assert!(parse_size("0").is_err());
+}
+
+#[test]
+fn budget_stops_at_file_limit_and_stays_reached() {
+    let budget = Budget::new(None, Some(2));
+    assert!(budget.try_reserve(Some(10)));
+    assert!(budget.try_reserve(Some(10)));
+    assert!(!budget.try_reserve(Some(10)));
+    assert!(budget.is_reached());
+    // Room freed by a failed download doesn't reopen a reached budget.
+    budget.settle(Some(10), None);
+    assert!(!budget.try_reserve(Some(10)));
+}
+
+#[test]
+fn budget_gives_failed_reservations_back() {
+    let budget = Budget::new(None, Some(1));
+    assert!(budget.try_reserve(None));
+    budget.settle(None, None);
+    assert!(budget.try_reserve(None));
+    assert!(!budget.is_reached());
+}
+
+#[test]
+fn budget_counts_actual_bytes_written() {
+    let budget = Budget::new(Some(100), None);
+    assert!(budget.try_reserve(Some(60)));
+    budget.settle(Some(60), Some(30));
+    assert!(budget.try_reserve(Some(70)));
+    assert!(!budget.try_reserve(Some(1)));
+}
+
+#[test]
+fn budget_checks_bytes_used_when_size_is_unknown() {
+    let budget = Budget::new(Some(100), None);
+    assert!(budget.try_reserve(None));
+    budget.settle(None, Some(150));
+    assert!(!budget.try_reserve(None));
+}
+
+#[test]
+fn rate_limiter_allows_a_burst_then_makes_callers_wait() {
+    let limiter = RateLimiter::new(1000);
+    let start = Instant::now();
+    assert_eq!(limiter.take(1000, start), Duration::ZERO);
+    assert_eq!(limiter.take(500, start), Duration::from_millis(500));
+    // Half a second later the debt is paid off, and the bucket refills from zero.
+    let later = start + Duration::from_millis(500);
+    assert_eq!(limiter.take(0, later), Duration::ZERO);
+    assert_eq!(limiter.take(250, later), Duration::from_millis(250));
+}
+
+#[test]
+fn disk_reserve_stops_runs_that_do_not_fit() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    // More than any disk has free.
+    let huge = u64::MAX / 2;
+
+    let reserve = DiskReserve::new(dir.path(), 1024, false);
+    assert!(reserve.check_plan(0).is_ok());
+    assert!(reserve.has_room(Some(1)));
+    assert!(!reserve.is_low());
+
+    let error = reserve.check_plan(huge).expect_err("too big");
+    assert!(error.contains("Not enough disk space"), "{error}");
+    assert!(reserve.is_low());
+    assert!(!reserve.has_room(None));
+}
+
+#[test]
+fn disk_reserve_can_only_warn_about_the_plan() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let huge = u64::MAX / 2;
+
+    let reserve = DiskReserve::new(dir.path(), 0, true);
+    assert!(reserve.check_plan(huge).is_err());
+    assert!(!reserve.is_low());
+    // The reserve itself still holds during the run.
+    assert!(!reserve.has_room(Some(huge)));
+    assert!(reserve.is_low());
+}
+
+#[test]
+fn available_space_checks_the_nearest_existing_directory() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    assert!(available_space(&dir.path().join("not/yet/created")).is_ok());
+}
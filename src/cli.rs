@@ -1,12 +1,29 @@
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 
 use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 
 use crate::config::Config;
+use crate::failure_manifest::FailureClass;
 
 /// Default directory that downloads, `zip`, and `clear-dl` operate on.
 pub const DL_DIR: &str = "./dl/";
 
+/// Upper bound for `-t/--num-threads`, the number of downloads in flight at
+/// once.
+pub const MAX_DOWNLOADS: usize = 64;
+
+/// Exit status when `--max-bytes`/`--max-files` stopped a run before every
+/// post was downloaded (distinct from `1`, which means some downloads failed).
+pub const EXIT_BUDGET_REACHED: i32 = 3;
+
+/// Exit status when free disk space dropped below `--min-free` (or the run
+/// wouldn't fit) before every post was downloaded.
+pub const EXIT_LOW_SPACE: i32 = 4;
+
+/// Exit status when Ctrl+C cancelled a run, as shells report for SIGINT.
+pub const EXIT_CANCELLED: i32 = 130;
+
 #[derive(Parser)]
 #[command(about = "A fast, multi-threaded downloader for e926/e621-style booru APIs.")]
 #[command(
@@ -18,10 +35,18 @@ pub const DL_DIR: &str = "./dl/";
 #[command(after_help = "EXAMPLES:\n  \
     e-cli d-tags \"scalie\" -c 250 -r -p 1        Download 250 random posts tagged 'scalie', 1 page\n  \
     e-cli d-favs someuser -c 100                 Download 100 favorites from 'someuser'\n  \
-    e-cli d-pool 22364                           Download a pool into ./dl/\n  \
-    e-cli d-pool 22364 -d ./pool/                Download a pool into ./pool/\n  \
+    e-cli d-pool 22364                           Download a pool into ./dl/<pool name>/\n  \
+    e-cli d-pool 22364 -d ./pool/                Download a pool into ./pool/<pool name>/\n  \
+     e-cli d-pool 22364 \"Cloud Jumping\" -s        Download pools by ID or name, following linked pools\n  \
+     e-cli pools search \"cloud\"                   List pools whose name contains 'cloud'\n  \
+     e-cli d-set my_set -i                        Download a post set, numbered by set order\n  \
+     e-cli d-artist someartist -u                 Download everything by an artist, saving their URLs\n  \
+     e-cli d-uploads someuser -c 100              Download 100 posts uploaded by 'someuser'\n  \
      e-cli d-favs someuser -c 100 -T seen.txt     Download favorites, skipping posts tracked in seen.txt\n  \
-     e-cli zip -n Cloudjumping -f cbz             Package ./dl/ into Cloudjumping.cbz\n  \
+     e-cli zip -n Cloudjumping -f cbz -d \"./dl/Cloud Jumping/\"  Package a downloaded pool into Cloudjumping.cbz\n  \
+     e-cli gallery -m run.json                    Make ./dl/index.html to browse the downloads\n  \
+     e-cli serve                                  Browse and manage ./dl/ at http://127.0.0.1:8080/\n  \
+     e-cli daemon --listen 127.0.0.1:8090         Serve the control API for frontends at http://127.0.0.1:8090/api/\n  \
      e-cli clear-dl                               Delete the ./dl/ output directory\n  \
      e-cli config                                 Create or edit the TOML configuration")]
 pub struct Args {
@@ -31,7 +56,7 @@ pub struct Args {
     #[arg(short = 'v', long, help = "Verbose Output.", action = ArgAction::SetTrue)]
     pub verbose: bool,
 
-    #[arg(short = 'L', long, help = "Ability to sign-in into the API for better fetching of posts.", action = ArgAction::SetTrue)]
+    #[arg(short = 'L', long, help = "Sign in to the API for better fetching of posts. Stored credentials (see login) are used without this flag; with it, you're asked for them if there are none.", action = ArgAction::SetTrue)]
     pub login: bool,
 
     #[arg(
@@ -48,7 +73,7 @@ pub struct Args {
     #[arg[short = 'p', long, help = "Number of pages to download, p = -1, gets all pages. p > 0, gets that amount of pages."]]
     pub pages: Option<i64>,
 
-    #[arg[short = 't', long, help = "The number of threads to use for downloads. Cannot set above 10."]]
+    #[arg[short = 't', long, help = "The number of files to download at once. Cannot set above 64."]]
     pub num_threads: Option<usize>,
 
     #[arg[short = 'd', long, global = true, help = "The directory to download files into (also used by zip and clear-dl)."]]
@@ -59,6 +84,19 @@ pub struct Args {
 
     #[arg(long, global = true, help = "Plan downloads and print a summary without writing files.", action = ArgAction::SetTrue)]
     pub dry_run: bool,
+    #[arg(long, global = true, help = "Show a progress bar for each file being downloaded.", action = ArgAction::SetTrue)]
+    pub file_bars: bool,
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text, help = "How to print results. json prints one JSON object per line to stdout, with logs on stderr.")]
+    pub output: OutputFormat,
+    #[arg(long, global = true, help = "If another e-cli run is using the download directory, wait for it to finish instead of exiting.", action = ArgAction::SetTrue)]
+    pub wait: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Sign in with a named account from the credentials file (see login --account)."
+    )]
+    pub account: Option<String>,
     #[arg(
         long,
         global = true,
@@ -84,6 +122,75 @@ pub struct Args {
         help = "Persistent failed-download manifest path."
     )]
     pub failure_manifest: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "RATE",
+        value_parser = crate::budget::parse_size,
+        help = "Limit the combined download speed, in bytes per second (e.g. 500K, 2M)."
+    )]
+    pub limit_rate: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "SIZE",
+        value_parser = crate::budget::parse_size,
+        help = "Stop starting new downloads once this much has been downloaded (e.g. 5G)."
+    )]
+    pub max_bytes: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Stop starting new downloads once this many files have been downloaded."
+    )]
+    pub max_files: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "SIZE",
+        value_parser = crate::budget::parse_size,
+        help = "Free disk space to keep on the destination, e.g. 1G. Runs that won't fit don't start, and downloads stop once free space drops below this."
+    )]
+    pub min_free: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        help = "With --min-free, start downloads even if they look too big for the free disk space (only warn).",
+        action = ArgAction::SetTrue
+    )]
+    pub allow_low_space: bool,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Also save a compact copy of each downloaded image in this format, without its metadata (see --convert-dir)."
+    )]
+    pub convert: Option<ConvertFormat>,
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 80,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        help = "Quality of --convert copies, 1-100."
+    )]
+    pub quality: u8,
+    #[arg(
+        long,
+        global = true,
+        value_name = "WxH",
+        value_parser = crate::postprocess::parse_dimensions,
+        help = "Scale --convert copies down to fit these dimensions, e.g. 1080x1920."
+    )]
+    pub max_dimensions: Option<(u32, u32)>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Where --convert copies go, at the same paths as in the download directory. Defaults to the download directory's name with -converted, e.g. ./dl-converted/."
+    )]
+    pub convert_dir: Option<PathBuf>,
 }
 
 #[derive(Subcommand, PartialEq, Eq)]
@@ -94,6 +201,10 @@ pub enum Commands {
     Config,
     #[command[about = "Checks whether a newer e-cli release is available on GitHub."]]
     CheckUpdate,
+    #[command(about = "Checks a username and API key, and stores them for later runs.")]
+    Login,
+    #[command(about = "Deletes the stored username and API key.")]
+    Logout,
     #[command[about = "Deletes the whole download directory (./dl/ by default, see -d) with it's contents."]]
     ClearDl,
     #[command[about = "Downloads the set amount of favourites from the username provided."]]
@@ -106,6 +217,18 @@ pub enum Commands {
         #[arg[short = 't', help = "Specify the search further with tags."]]
         tags: Option<String>,
     },
+    #[command[about = "Downloads the set amount of posts uploaded by the username provided."]]
+    DUploads {
+        username: Option<String>,
+        #[arg(long, help = "Treat the username as a numeric user ID.", action = ArgAction::SetTrue)]
+        id: bool,
+        #[arg[short = 'c', help = "The amount of posts to get. Max=250."]]
+        count: Option<u32>,
+        #[arg(short = 'r', help = "Adds the order:random in the search.", action = ArgAction::SetTrue)]
+        random: bool,
+        #[arg[short = 't', help = "Specify the search further with tags."]]
+        tags: Option<String>,
+    },
     #[command[about = "Downloads the set amount of posts with the tags provided."]]
     #[command[long_about = "Downloads the set amount of posts with the tags provided.\n\n\
         Requires the global -p/--pages flag to be set explicitly (e.g. -p 1), since there is \
@@ -117,22 +240,136 @@ pub enum Commands {
         #[arg(short = 'r', help = "Adds the order:random in the search.", action = ArgAction::SetTrue)]
         random: bool,
     },
-    #[command[about = "Downloads a pool with the indexes in the names of the files."]]
+    #[command[about = "Downloads pools with the indexes in the names of the files."]]
+    #[command[long_about = "Downloads pools with the indexes in the names of the files.\n\n\
+        Each pool is saved into its own subdirectory of the download directory, named after \
+        the pool. Pools can be given by ID or by name; a name has to match a single pool \
+        (see `pools search`)."]]
     DPool {
-        #[arg(help = "The Pool ID")]
-        pool_id: Option<u64>,
+        #[arg(help = "Pool IDs or names")]
+        pools: Vec<String>,
+        #[arg(short = 's', long, help = "Also download pools linked from each pool's description, e.g. the rest of a series.", action = ArgAction::SetTrue)]
+        series: bool,
+        #[arg(
+            long,
+            value_enum,
+            value_name = "FORMAT",
+            help = "Download each pool straight into <pool name>.<FORMAT> (zip or cbz) instead of a folder, resuming an unfinished one."
+        )]
+        archive: Option<ArchiveFormat>,
+    },
+    #[command[about = "Downloads everything by an artist, including posts under their aliases and other names."]]
+    DArtist {
+        #[arg(help = "The artist's name or one of their other names")]
+        name: String,
+        #[arg[short = 'c', help = "The amount of posts to get per page. Max=250, defaults to 250."]]
+        count: Option<u32>,
+        #[arg(short = 'u', long, help = "Write the artist's other-site URLs to <artist>.urls.txt.", action = ArgAction::SetTrue)]
+        urls: bool,
+    },
+    #[command(about = "Looks up pools.")]
+    Pools {
+        #[command(subcommand)]
+        command: PoolsCommand,
+    },
+    #[command[about = "Downloads a post set, optionally numbered by its order in the set."]]
+    DSet {
+        #[arg(help = "The set ID or shortname")]
+        set: Option<String>,
+        #[arg(short = 'i', long, help = "Prefix file names with their position in the set, like d-pool.", action = ArgAction::SetTrue)]
+        indexed: bool,
     },
-    #[command[about = "Packages a downloaded pool (./dl/) into an archive."]]
-    #[command[long_about = "Packages a downloaded pool (./dl/) into an archive.\n\n\
+    #[command[about = "Packages a downloaded pool (see -d) into an archive."]]
+    #[command[long_about = "Packages a downloaded pool (see -d) into an archive.\n\n\
+        Point -d at the pool's own folder, e.g. -d \"./dl/Cloud Jumping/\". \
         Intended for pools downloaded with d-pool, since the index-prefixed filenames \
         (1-, 2-, 3-, ...) are what makes the resulting archive readable in order — \
-        that's also why the cbz format exists. Requires the '7z' executable to be \
-        available on your PATH."]]
+        that's also why the cbz format exists. Files are added in pool order. zip, cbz \
+        and tar.zst archives are built in-process; only 7z requires the '7z' executable \
+        to be available on your PATH. epub and pdf make a book of the pool's images, one \
+        per page, for e-readers that don't read cbz."]]
     Zip {
         #[arg[short = 'n', long, help = "Name for the output archive, without extension."]]
         name: Option<String>,
         #[arg[short = 'f', long, value_enum, help = "Archive format to use."]]
         format: Option<ArchiveFormat>,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Where to write the archive: a directory to put it in, or its file path. Defaults to the current directory."
+        )]
+        to: Option<PathBuf>,
+        #[arg(long, help = "Store files in zip/cbz archives without compressing them (images barely compress).", action = ArgAction::SetTrue)]
+        store: bool,
+    },
+    #[command[about = "Makes a static HTML gallery of the download directory (see -d), with thumbnails."]]
+    #[command[long_about = "Makes a static HTML gallery of the download directory (see -d), with thumbnails.\n\n\
+        Writes index.html into the directory, with a thumbnail for each image (kept in \
+        .e-cli-thumbs), filters by tag and rating, and a link to each file's post. Tags and \
+        ratings are read from run manifests (see --manifest) given with --from, or else from \
+        the ComicInfo.xml d-pool writes into pool folders."]]
+    Gallery {
+        #[arg(
+            short = 'm',
+            long = "from",
+            value_name = "MANIFEST",
+            help = "Run manifests to read the posts' tags and ratings from. Can be given more than once."
+        )]
+        manifests: Vec<PathBuf>,
+    },
+    #[command[about = "Serves a local web interface to search, view and delete downloads (see -d) and start new ones."]]
+    #[command[long_about = "Serves a local web interface to search, view and delete downloads (see -d) and start new ones.\n\n\
+        Open http://127.0.0.1:8080/ in a browser. Tags and ratings are searched like with gallery, \
+        plus those of the downloads started from the interface. Deleting a file also removes it \
+        from the duplicate index and keeps its post in the tracking file (see -T), unless you ask \
+        for it to be downloaded again. With --token-file, deleting files and starting or cancelling         downloads need the token in that file, which the page asks for. Only listens on this computer         unless --bind says otherwise, which needs --token-file."]]
+    Serve {
+        #[arg(long, default_value_t = 8080, help = "The port to listen on.")]
+        port: u16,
+        #[arg(
+            long,
+            value_name = "ADDRESS",
+            default_value = "127.0.0.1",
+            help = "The address to listen on. Any other than 127.0.0.1 or ::1 lets others on your network browse and download, and needs --token-file."
+        )]
+        bind: IpAddr,
+        #[arg(
+            short = 'm',
+            long = "from",
+            value_name = "MANIFEST",
+            help = "Run manifests to read the posts' tags and ratings from. Can be given more than once."
+        )]
+        manifests: Vec<PathBuf>,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "A file holding the token to require for deleting files and starting or cancelling downloads. Needed to listen on other addresses than 127.0.0.1 or ::1."
+        )]
+        token_file: Option<PathBuf>,
+    },
+    #[command[about = "Serves a JSON API to start, follow and cancel downloads and edit config.toml, for frontends."]]
+    #[command[long_about = "Serves a JSON API to start, follow and cancel downloads (see -d) and edit config.toml, for frontends.\n\n\
+        Downloads are started with POST /api/jobs, listed with GET /api/jobs and cancelled with \
+        DELETE /api/jobs/ID. GET /api/events streams their progress as server-sent events, and \
+        GET and PUT /api/config read and replace the configuration, except credential_command and \
+        preset accounts. Every request needs an 'Authorization: Bearer TOKEN' header; the token is \
+        made anew each start and written to daemon-token next to config.toml, unless --token-file \
+        gives one. Only listens on this computer unless --listen says otherwise, which needs \
+        --token-file."]]
+    Daemon {
+        #[arg(
+            long,
+            value_name = "ADDRESS:PORT",
+            default_value = "127.0.0.1:8090",
+            help = "The address and port to listen on."
+        )]
+        listen: SocketAddr,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "A file holding the API token to require, instead of making a new one. Needed to listen on other addresses than 127.0.0.1 or ::1."
+        )]
+        token_file: Option<PathBuf>,
     },
     #[command(about = "Runs a named tag-search preset from config.toml.")]
     Preset {
@@ -143,7 +380,44 @@ pub enum Commands {
         random: bool,
     },
     #[command(about = "Retries posts recorded in the failed-download manifest.")]
-    RetryFailed,
+    RetryFailed {
+        #[arg(
+            long,
+            value_name = "CLASS",
+            value_delimiter = ',',
+            help = "Retry only failures of these classes: http, http-4xx, http-5xx, http-<status>, timeout, connection-reset, io, md5-mismatch, deleted, blacklisted, budget-reached, low-disk-space, cancelled or unknown."
+        )]
+        only: Vec<FailureClass>,
+        #[arg(
+            long,
+            value_name = "CLASS",
+            value_delimiter = ',',
+            help = "Leave failures of these classes in the manifest without retrying them."
+        )]
+        exclude: Vec<FailureClass>,
+    },
+}
+
+#[derive(Subcommand, PartialEq, Eq)]
+pub enum PoolsCommand {
+    #[command(about = "Lists pools whose name contains the query.")]
+    Search {
+        query: String,
+        #[arg(
+            short = 'c',
+            help = "The amount of pools to list.",
+            default_value_t = 25
+        )]
+        count: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[value(help = "Human-readable text and progress bars.")]
+    Text,
+    #[value(help = "JSON Lines events on stdout (see e_cli::output).")]
+    Json,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
@@ -152,8 +426,31 @@ pub enum ArchiveFormat {
     Zip,
     #[value(name = "7z", help = "Native .7z archive.")]
     SevenZip,
-    #[value(help = "A .zip renamed to .cbz, for comic/e-book readers to read pools in order.")]
+    #[value(help = "A .zip named .cbz, for comic/e-book readers to read pools in order.")]
     Cbz,
+    #[value(name = "tar.zst", help = "A zstd-compressed .tar archive.")]
+    TarZst,
+    #[value(help = "A fixed-layout EPUB book, one image per page, for e-readers.")]
+    Epub,
+    #[value(help = "A PDF, one image per page, each page sized to its image.")]
+    Pdf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ConvertFormat {
+    #[value(help = "AVIF, the smallest but slowest to make.")]
+    Avif,
+    #[value(help = "JPEG, flattened onto white where transparent.")]
+    Jpeg,
+}
+
+impl ConvertFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ConvertFormat::Avif => "avif",
+            ConvertFormat::Jpeg => "jpg",
+        }
+    }
 }
 
 impl ArchiveFormat {
@@ -162,6 +459,9 @@ impl ArchiveFormat {
             ArchiveFormat::Zip => "zip",
             ArchiveFormat::SevenZip => "7z",
             ArchiveFormat::Cbz => "cbz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Epub => "epub",
+            ArchiveFormat::Pdf => "pdf",
         }
     }
 }
@@ -213,6 +513,26 @@ pub fn apply_config(args: &mut Args, config: &Config) -> Result<(), String> {
                 *tags = config.d_favs.tags.clone();
             }
         }
+        Some(Commands::DUploads {
+            username,
+            count,
+            random,
+            tags,
+            ..
+        }) => {
+            if username.is_none() {
+                *username = config.d_uploads.username.clone();
+            }
+            if count.is_none() {
+                *count = config.d_uploads.count;
+            }
+            if !*random {
+                *random = config.d_uploads.random.unwrap_or(false);
+            }
+            if tags.is_none() {
+                *tags = config.d_uploads.tags.clone();
+            }
+        }
         Some(Commands::DTags {
             tags,
             count,
@@ -228,12 +548,25 @@ pub fn apply_config(args: &mut Args, config: &Config) -> Result<(), String> {
                 *random = config.d_tags.random.unwrap_or(false);
             }
         }
-        Some(Commands::DPool { pool_id }) => {
-            if pool_id.is_none() {
-                *pool_id = config.d_pool.pool_id;
+        Some(Commands::DPool { pools, series, .. }) => {
+            if pools.is_empty()
+                && let Some(pool_id) = config.d_pool.pool_id
+            {
+                pools.push(pool_id.to_string());
+            }
+            if !*series {
+                *series = config.d_pool.series.unwrap_or(false);
             }
         }
-        Some(Commands::Zip { name, format }) => {
+        Some(Commands::DSet { set, indexed }) => {
+            if set.is_none() {
+                *set = config.d_set.set.clone();
+            }
+            if !*indexed {
+                *indexed = config.d_set.indexed.unwrap_or(false);
+            }
+        }
+        Some(Commands::Zip { name, format, .. }) => {
             if name.is_none() {
                 *name = config.zip.name.clone();
             }
@@ -244,9 +577,12 @@ pub fn apply_config(args: &mut Args, config: &Config) -> Result<(), String> {
                     "zip" => ArchiveFormat::Zip,
                     "7z" => ArchiveFormat::SevenZip,
                     "cbz" => ArchiveFormat::Cbz,
+                    "tar.zst" => ArchiveFormat::TarZst,
+                    "epub" => ArchiveFormat::Epub,
+                    "pdf" => ArchiveFormat::Pdf,
                     _ => {
                         return Err(format!(
-                            "Invalid zip format '{value}' in the config; expected zip, 7z, or cbz."
+                            "Invalid zip format '{value}' in the config; expected zip, 7z, cbz, tar.zst, epub, or pdf."
                         ));
                     }
                 });
@@ -282,12 +618,22 @@ pub fn apply_config(args: &mut Args, config: &Config) -> Result<(), String> {
             if !*random {
                 *random = preset.random.unwrap_or(false);
             }
+            if args.account.is_none() {
+                args.account = preset.account.clone();
+            }
         }
         Some(Commands::Config)
         | Some(Commands::Tui)
         | Some(Commands::ClearDl)
         | Some(Commands::CheckUpdate)
-        | Some(Commands::RetryFailed)
+        | Some(Commands::Login)
+        | Some(Commands::Logout)
+        | Some(Commands::RetryFailed { .. })
+        | Some(Commands::Pools { .. })
+        | Some(Commands::DArtist { .. })
+        | Some(Commands::Gallery { .. })
+        | Some(Commands::Serve { .. })
+        | Some(Commands::Daemon { .. })
         | None => {}
     }
     Ok(())
@@ -299,13 +645,17 @@ pub fn fill_defaults(args: &mut Args) -> Result<(), String> {
     args.dir.get_or_insert_with(|| DL_DIR.to_owned());
 
     match &mut args.command {
-        Some(Commands::DFavs { count, tags, .. }) => {
+        Some(Commands::DFavs { count, tags, .. })
+        | Some(Commands::DUploads { count, tags, .. }) => {
             count.get_or_insert(5);
             tags.get_or_insert_with(String::new);
         }
         Some(Commands::DTags { count, .. }) => {
             count.get_or_insert(5);
         }
+        Some(Commands::DArtist { count, .. }) => {
+            count.get_or_insert(250);
+        }
         Some(Commands::Zip { format, .. }) => {
             format.get_or_insert(ArchiveFormat::Zip);
         }
@@ -319,10 +669,15 @@ pub fn validate_args(args: &Args) -> Result<(), String> {
     if args.num_threads.unwrap_or(5) == 0 {
         return Err("Must use at least 1 thread.".into());
     }
-    if args.num_threads.unwrap_or(5) > 10 {
-        return Err("Cannot go above 10 threads for downloads.".into());
+    if args.max_files == Some(0) {
+        return Err("--max-files must be at least 1.".into());
     }
-    if let Some(Commands::DFavs { count, .. }) = &args.command
+    if args.num_threads.unwrap_or(5) > MAX_DOWNLOADS {
+        return Err(format!(
+            "Cannot download more than {MAX_DOWNLOADS} files at once."
+        ));
+    }
+    if let Some(Commands::DFavs { count, .. } | Commands::DUploads { count, .. }) = &args.command
         && count.unwrap_or(5) > 250
     {
         return Err("Cannot go above 250 posts per page.".into());
@@ -332,6 +687,11 @@ pub fn validate_args(args: &Args) -> Result<(), String> {
     {
         return Err("Cannot go above 250 posts per page.".into());
     }
+    if let Some(Commands::DArtist { count, .. }) = &args.command
+        && count.unwrap_or(250) > 250
+    {
+        return Err("Cannot go above 250 posts per page.".into());
+    }
     if let Some(Commands::DTags { .. }) = &args.command
         && args.pages.unwrap_or(-1) == -1
     {
@@ -343,11 +703,39 @@ pub fn validate_args(args: &Args) -> Result<(), String> {
         Some(Commands::DFavs { username, .. }) if username.is_none() => {
             return Err("d-favs requires a username argument or a configured username.".into());
         }
+        Some(Commands::DUploads { username, .. }) if username.is_none() => {
+            return Err("d-uploads requires a username argument or a configured username.".into());
+        }
+        Some(Commands::DUploads {
+            username, id: true, ..
+        }) if username
+            .as_deref()
+            .is_some_and(|user| user.parse::<u64>().is_err()) =>
+        {
+            return Err("d-uploads --id requires a numeric user ID.".into());
+        }
         Some(Commands::DTags { tags, .. }) if tags.is_none() => {
             return Err("d-tags requires tags or a configured tags value.".into());
         }
-        Some(Commands::DPool { pool_id }) if pool_id.is_none() => {
-            return Err("d-pool requires a pool ID argument or a configured pool_id.".into());
+        Some(Commands::DPool { pools, .. }) if pools.is_empty() => {
+            return Err(
+                "d-pool requires a pool ID or name argument, or a configured pool_id.".into(),
+            );
+        }
+        Some(Commands::DPool {
+            archive:
+                Some(
+                    ArchiveFormat::SevenZip
+                    | ArchiveFormat::TarZst
+                    | ArchiveFormat::Epub
+                    | ArchiveFormat::Pdf,
+                ),
+            ..
+        }) => {
+            return Err("d-pool --archive only supports zip and cbz.".into());
+        }
+        Some(Commands::DSet { set, .. }) if set.is_none() => {
+            return Err("d-set requires a set ID or shortname, or a configured set.".into());
         }
         Some(Commands::Preset { name, .. }) if name.is_empty() => {
             return Err("preset requires a name.".into());
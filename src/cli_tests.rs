@@ -75,6 +75,10 @@ fn all_subcommands_parse() {
         parse(&["d-pool", "123"]).command,
         Some(Commands::DPool { .. })
     ));
+    assert!(matches!(
+        parse(&["d-set", "my_set"]).command,
+        Some(Commands::DSet { .. })
+    ));
     assert!(matches!(
         parse(&["zip", "-n", "test"]).command,
         Some(Commands::Zip { .. })
@@ -85,8 +89,39 @@ fn all_subcommands_parse() {
     ));
     assert!(matches!(
         parse(&["retry-failed"]).command,
-        Some(Commands::RetryFailed)
+        Some(Commands::RetryFailed { .. })
     ));
+    assert!(matches!(parse(&["login"]).command, Some(Commands::Login)));
+    assert!(matches!(parse(&["logout"]).command, Some(Commands::Logout)));
+}
+
+#[test]
+fn retry_failed_takes_lists_of_failure_classes() {
+    match parse(&[
+        "retry-failed",
+        "--only",
+        "http-5xx,timeout",
+        "--exclude",
+        "deleted",
+    ])
+    .command
+    {
+        Some(Commands::RetryFailed { only, exclude }) => {
+            assert_eq!(
+                only,
+                vec![
+                    FailureClass::HttpRange(5),
+                    FailureClass::Kind(crate::FailureKind::Timeout)
+                ]
+            );
+            assert_eq!(
+                exclude,
+                vec![FailureClass::Kind(crate::FailureKind::Deleted)]
+            );
+        }
+        _ => panic!("expected RetryFailed command"),
+    }
+    assert!(Args::try_parse_from(["e-cli", "retry-failed", "--only", "gone"]).is_err());
 }
 
 #[test]
@@ -126,6 +161,27 @@ fn cli_values_override_config_values() {
     assert_eq!(args.num_threads, Some(2));
 }
 
+#[test]
+fn preset_account_applies_unless_one_is_given() {
+    let mut config = Config::default();
+    config.presets.insert(
+        "team".to_owned(),
+        crate::config::PresetConfig {
+            tags: Some("dragon".to_owned()),
+            account: Some("archivist".to_owned()),
+            ..Default::default()
+        },
+    );
+
+    let mut args = parse(&["preset", "team"]);
+    apply_config(&mut args, &config).expect("config should apply");
+    assert_eq!(args.account.as_deref(), Some("archivist"));
+
+    let mut args = parse(&["--account", "me", "preset", "team"]);
+    apply_config(&mut args, &config).expect("config should apply");
+    assert_eq!(args.account.as_deref(), Some("me"));
+}
+
 #[test]
 fn zip_format_parses_each_variant() {
     for (flag, expect_seven, expect_cbz) in [
@@ -161,7 +217,8 @@ fn rejects_zero_threads() {
 
 #[test]
 fn rejects_too_many_threads() {
-    let args = parse(&["-t", "11", "d-pool", "1"]);
+    let over = (MAX_DOWNLOADS + 1).to_string();
+    let args = parse(&["-t", &over, "d-pool", "1"]);
     assert!(validate_args(&args).is_err());
 }
 
@@ -209,3 +266,225 @@ fn dpool_unaffected_by_count_and_page_rules() {
     let args = parse(&["d-pool", "1"]);
     assert!(validate_args(&args).is_ok());
 }
+
+#[test]
+fn dset_indexed_flag_and_config_fallback() {
+    match parse(&["d-set", "123", "-i"]).command {
+        Some(Commands::DSet { set, indexed }) => {
+            assert_eq!(set.as_deref(), Some("123"));
+            assert!(indexed);
+        }
+        _ => panic!("expected DSet command"),
+    }
+
+    let mut args = parse(&["d-set"]);
+    assert!(validate_args(&args).is_err());
+    let mut config = Config::default();
+    config.d_set.set = Some("my_set".to_owned());
+    config.d_set.indexed = Some(true);
+    apply_config(&mut args, &config).expect("config should apply");
+    assert!(matches!(
+        &args.command,
+        Some(Commands::DSet { set: Some(set), indexed: true }) if set == "my_set"
+    ));
+    assert!(validate_args(&args).is_ok());
+}
+
+#[test]
+fn dpool_accepts_multiple_ids_and_names() {
+    match parse(&["d-pool", "1", "Cloud Jumping", "--series"]).command {
+        Some(Commands::DPool {
+            pools,
+            series,
+            archive,
+        }) => {
+            assert_eq!(pools, vec!["1".to_owned(), "Cloud Jumping".to_owned()]);
+            assert!(series);
+            assert!(archive.is_none());
+        }
+        _ => panic!("expected DPool command"),
+    }
+}
+
+#[test]
+fn dpool_archives_only_into_zip_or_cbz() {
+    let args = parse(&["d-pool", "1", "--archive", "cbz"]);
+    assert!(matches!(
+        args.command,
+        Some(Commands::DPool {
+            archive: Some(ArchiveFormat::Cbz),
+            ..
+        })
+    ));
+    assert!(validate_args(&args).is_ok());
+
+    let error = validate_args(&parse(&["d-pool", "1", "--archive", "tar.zst"]))
+        .expect_err("tar.zst can't be streamed");
+    assert!(error.contains("zip and cbz"), "{error}");
+}
+
+#[test]
+fn dpool_requires_a_pool() {
+    let args = parse(&["d-pool"]);
+    assert!(validate_args(&args).is_err());
+}
+
+#[test]
+fn gallery_reads_several_manifests() {
+    let args = parse(&["gallery", "-m", "a.json", "--from", "b.json"]);
+    assert!(matches!(
+        args.command,
+        Some(Commands::Gallery { ref manifests })
+            if manifests == &[PathBuf::from("a.json"), PathBuf::from("b.json")]
+    ));
+}
+
+#[test]
+fn serve_defaults_to_localhost_8080() {
+    match parse(&["serve"]).command {
+        Some(Commands::Serve {
+            port,
+            bind,
+            manifests,
+            token_file,
+        }) => {
+            assert_eq!(port, 8080);
+            assert_eq!(bind, IpAddr::from([127, 0, 0, 1]));
+            assert!(manifests.is_empty());
+            assert_eq!(token_file, None);
+        }
+        _ => panic!("expected serve command"),
+    }
+}
+
+#[test]
+fn daemon_listens_on_localhost_by_default() {
+    assert!(matches!(
+        parse(&["daemon"]).command,
+        Some(Commands::Daemon { listen, token_file: None })
+            if listen == "127.0.0.1:8090".parse().unwrap()
+    ));
+    assert!(matches!(
+        parse(&["daemon", "--listen", "0.0.0.0:9000", "--token-file", "token"]).command,
+        Some(Commands::Daemon { listen, token_file: Some(ref path) })
+            if listen.port() == 9000 && path == &PathBuf::from("token")
+    ));
+}
+
+#[test]
+fn pools_search_parses_query_and_count() {
+    match parse(&["pools", "search", "cloud", "-c", "10"]).command {
+        Some(Commands::Pools {
+            command: PoolsCommand::Search { query, count },
+        }) => {
+            assert_eq!(query, "cloud");
+            assert_eq!(count, 10);
+        }
+        _ => panic!("expected pools search command"),
+    }
+}
+
+#[test]
+fn dartist_defaults_to_full_pages() {
+    let mut args = parse(&["d-artist", "someartist", "-u"]);
+    fill_defaults(&mut args).expect("defaults should fill");
+    match &args.command {
+        Some(Commands::DArtist { name, count, urls }) => {
+            assert_eq!(name, "someartist");
+            assert_eq!(*count, Some(250));
+            assert!(urls);
+        }
+        _ => panic!("expected DArtist command"),
+    }
+    assert!(validate_args(&args).is_ok());
+    assert!(validate_args(&parse(&["d-artist", "someartist", "-c", "251"])).is_err());
+}
+
+#[test]
+fn duploads_follows_favourites_rules() {
+    let mut args = parse(&["d-uploads", "someuser", "-r", "-t", "dragon"]);
+    fill_defaults(&mut args).expect("defaults should fill");
+    match &args.command {
+        Some(Commands::DUploads {
+            username,
+            id,
+            count,
+            random,
+            tags,
+        }) => {
+            assert_eq!(username.as_deref(), Some("someuser"));
+            assert!(!id);
+            assert_eq!(*count, Some(5));
+            assert!(random);
+            assert_eq!(tags.as_deref(), Some("dragon"));
+        }
+        _ => panic!("expected DUploads command"),
+    }
+    assert!(validate_args(&args).is_ok());
+    assert!(validate_args(&parse(&["d-uploads", "someuser", "-c", "251"])).is_err());
+    assert!(validate_args(&parse(&["d-uploads"])).is_err());
+    assert!(validate_args(&parse(&["d-uploads", "someuser", "--id"])).is_err());
+    assert!(validate_args(&parse(&["d-uploads", "1234", "--id"])).is_ok());
+}
+
+#[test]
+fn duploads_username_falls_back_to_config() {
+    let mut args = parse(&["d-uploads"]);
+    let mut config = Config::default();
+    config.d_uploads.username = Some("someuser".to_owned());
+    config.d_uploads.count = Some(50);
+    apply_config(&mut args, &config).expect("config should apply");
+    assert!(matches!(
+        &args.command,
+        Some(Commands::DUploads { username: Some(user), count: Some(50), .. }) if user == "someuser"
+    ));
+}
+
+#[test]
+fn allows_up_to_max_concurrent_downloads() {
+    let max = MAX_DOWNLOADS.to_string();
+    assert!(validate_args(&parse(&["-t", &max, "d-pool", "1"])).is_ok());
+}
+
+#[test]
+fn budget_flags_parse_sizes() {
+    let args = parse(&[
+        "--limit-rate",
+        "2M",
+        "--max-bytes",
+        "5G",
+        "--max-files",
+        "10",
+        "d-pool",
+        "1",
+    ]);
+    assert_eq!(args.limit_rate, Some(2 * 1024 * 1024));
+    assert_eq!(args.max_bytes, Some(5 * 1024 * 1024 * 1024));
+    assert_eq!(args.max_files, Some(10));
+    assert_eq!(args.min_free, None);
+    assert!(validate_args(&args).is_ok());
+    assert_eq!(
+        parse(&["--min-free", "10G", "d-pool", "1"]).min_free,
+        Some(10 << 30)
+    );
+}
+
+#[test]
+fn rejects_bad_budgets() {
+    let mut full = vec!["e-cli", "--limit-rate", "fast", "d-pool", "1"];
+    assert!(Args::try_parse_from(&full).is_err());
+    full[2] = "0";
+    assert!(Args::try_parse_from(&full).is_err());
+    assert!(validate_args(&parse(&["--max-files", "0", "d-pool", "1"])).is_err());
+}
+
+#[test]
+fn output_defaults_to_text_and_accepts_json() {
+    assert_eq!(parse(&["d-pool", "1"]).output, OutputFormat::Text);
+    assert_eq!(
+        parse(&["d-pool", "1", "--output", "json"]).output,
+        OutputFormat::Json
+    );
+    let full = ["e-cli", "--output", "xml", "d-pool", "1"];
+    assert!(Args::try_parse_from(full).is_err());
+}
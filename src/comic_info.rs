@@ -0,0 +1,225 @@
+//! `ComicInfo.xml`, the metadata file comic readers (Komga, Kavita,
+//! Tachiyomi, ...) look for in a cbz. Pool downloads write one into the
+//! pool's folder (see [`crate::commands::download_pool`]), so packaging the
+//! folder with `zip` puts it in the archive.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::type_defs::api_defs::{PoolData, Post};
+
+/// The file name readers look for.
+pub const FILE_NAME: &str = "ComicInfo.xml";
+
+/// The `AgeRating` values [`ComicInfo::from_pool`] picks from.
+const AGE_RATINGS: &[&str] = &["Everyone", "Mature 17+", "Adults Only 18+", "Unknown"];
+
+/// Artist tags that say something about the post rather than who made it.
+const META_ARTISTS: &[&str] = &[
+    "avoid_posting",
+    "conditional_dnp",
+    "epilepsy_warning",
+    "sound_warning",
+    "third-party_edit",
+    "unknown_artist",
+    "anonymous_artist",
+];
+
+/// The metadata of a pool, as written to `ComicInfo.xml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComicInfo {
+    /// Used as both the title and the series.
+    pub title: String,
+    pub summary: Option<String>,
+    /// The pool's artists, in the order they first appear; used as both
+    /// writer and penciller.
+    pub artists: Vec<String>,
+    /// General tags found on at least half of the pages, sorted.
+    pub tags: Vec<String>,
+    pub page_count: usize,
+    /// The pool's page on the site.
+    pub web: String,
+    /// One of the ComicInfo `AgeRating` values, from the most explicit post
+    /// rating in the pool.
+    pub age_rating: &'static str,
+}
+
+impl Default for ComicInfo {
+    fn default() -> Self {
+        ComicInfo {
+            title: String::new(),
+            summary: None,
+            artists: Vec::new(),
+            tags: Vec::new(),
+            page_count: 0,
+            web: String::new(),
+            age_rating: "Unknown",
+        }
+    }
+}
+
+impl ComicInfo {
+    /// Collects the metadata of `pool` from its `posts`, as fetched from
+    /// `api_source`.
+    pub fn from_pool(pool: &PoolData, posts: &[Post], api_source: &str) -> Self {
+        let mut artists: Vec<String> = Vec::new();
+        let mut tag_counts: HashMap<&str, usize> = HashMap::new();
+        for post in posts {
+            for artist in &post.tags.artist {
+                if !META_ARTISTS.contains(&artist.as_str()) && !artists.contains(artist) {
+                    artists.push(artist.clone());
+                }
+            }
+            for tag in &post.tags.general {
+                *tag_counts.entry(tag).or_default() += 1;
+            }
+        }
+        let mut tags = tag_counts
+            .into_iter()
+            .filter(|(_, count)| count * 2 >= posts.len())
+            .map(|(tag, _)| tag.to_owned())
+            .collect::<Vec<_>>();
+        tags.sort();
+
+        let age_rating = match posts.iter().map(|post| rating_rank(&post.rating)).max() {
+            Some(3) => "Adults Only 18+",
+            Some(2) => "Mature 17+",
+            Some(1) => "Everyone",
+            _ => "Unknown",
+        };
+
+        ComicInfo {
+            title: pool.display_name(),
+            summary: pool
+                .description
+                .as_deref()
+                .map(str::trim)
+                .filter(|description| !description.is_empty())
+                .map(str::to_owned),
+            artists,
+            tags,
+            page_count: posts.len(),
+            web: format!("https://{api_source}/pools/{}", pool.id),
+            age_rating,
+        }
+    }
+
+    /// Renders the metadata as a `ComicInfo.xml` document, in the element
+    /// order of the ComicInfo schema.
+    pub fn to_xml(&self) -> String {
+        let artists = self.artists.join(", ");
+        let tags = self.tags.join(", ");
+        let page_count = self.page_count.to_string();
+        let fields = [
+            ("Title", Some(self.title.as_str())),
+            ("Series", Some(self.title.as_str())),
+            ("Summary", self.summary.as_deref()),
+            ("Writer", Some(artists.as_str()).filter(|a| !a.is_empty())),
+            (
+                "Penciller",
+                Some(artists.as_str()).filter(|a| !a.is_empty()),
+            ),
+            ("Tags", Some(tags.as_str()).filter(|t| !t.is_empty())),
+            ("Web", Some(self.web.as_str())),
+            ("PageCount", Some(page_count.as_str())),
+            ("AgeRating", Some(self.age_rating)),
+        ];
+
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+             xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+        );
+        for (element, value) in fields {
+            if let Some(value) = value {
+                xml.push_str(&format!("  <{element}>{}</{element}>\n", escape(value)));
+            }
+        }
+        xml.push_str("</ComicInfo>\n");
+        xml
+    }
+
+    /// Reads back the fields [`ComicInfo::to_xml`] writes from a
+    /// `ComicInfo.xml`. Missing fields are left empty.
+    pub fn from_xml(xml: &str) -> Self {
+        let list = |element| {
+            element_text(xml, element)
+                .map(|text| text.split(',').map(|item| item.trim().to_owned()).collect())
+                .unwrap_or_default()
+        };
+        let title = element_text(xml, "Title")
+            .or_else(|| element_text(xml, "Series"))
+            .unwrap_or_default();
+        let age_rating = element_text(xml, "AgeRating").unwrap_or_default();
+        ComicInfo {
+            title,
+            summary: element_text(xml, "Summary"),
+            artists: list("Writer"),
+            tags: list("Tags"),
+            page_count: element_text(xml, "PageCount")
+                .and_then(|count| count.parse().ok())
+                .unwrap_or_default(),
+            web: element_text(xml, "Web").unwrap_or_default(),
+            age_rating: AGE_RATINGS
+                .iter()
+                .copied()
+                .find(|rating| *rating == age_rating)
+                .unwrap_or("Unknown"),
+        }
+    }
+}
+
+/// The `ComicInfo.xml` in the pool folder `dir`, if it has one that can be
+/// read.
+pub fn read(dir: &Path) -> Option<ComicInfo> {
+    let xml = fs::read_to_string(dir.join(FILE_NAME)).ok()?;
+    Some(ComicInfo::from_xml(&xml))
+}
+
+/// The unescaped text of the first non-empty `<element>` in `xml`.
+fn element_text(xml: &str, element: &str) -> Option<String> {
+    let start = xml.find(&format!("<{element}>"))? + element.len() + 2;
+    let end = start + xml[start..].find(&format!("</{element}>"))?;
+    let text = xml[start..end]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    Some(text).filter(|text| !text.trim().is_empty())
+}
+
+/// How explicit a post rating is: safe, questionable, explicit, or 0 if it's
+/// not known.
+fn rating_rank(rating: &str) -> u8 {
+    match rating {
+        "s" => 1,
+        "q" => 2,
+        "e" => 3,
+        _ => 0,
+    }
+}
+
+/// Escapes `text` for XML content, dropping the control characters XML 1.0
+/// doesn't allow.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+#[path = "comic_info_tests.rs"]
+mod tests;
@@ -0,0 +1,108 @@
+use super::*;
+use crate::type_defs::api_defs::{Alternates, File, Sample, Tags};
+
+fn post(id: u64, artists: &[&str], general: &[&str], rating: &str) -> Post {
+    Post {
+        id,
+        file: File {
+            ext: "png".into(),
+            url: None,
+            md5: None,
+            size: None,
+            width: None,
+            height: None,
+        },
+        tags: Tags {
+            artist: artists.iter().map(|a| a.to_string()).collect(),
+            character: vec![],
+            species: vec![],
+            general: general.iter().map(|t| t.to_string()).collect(),
+        },
+        sample: Sample {
+            has: false,
+            url: None,
+            alternates: Alternates {
+                lower_quality: None,
+            },
+        },
+        description: None,
+        flags: Default::default(),
+        rating: rating.into(),
+    }
+}
+
+fn pool(description: Option<&str>) -> PoolData {
+    PoolData {
+        id: 22364,
+        name: "Cloud_Jumping".into(),
+        description: description.map(str::to_owned),
+        post_ids: vec![1, 2, 3],
+        post_count: 3,
+        category: "series".into(),
+        is_active: false,
+    }
+}
+
+#[test]
+fn metadata_comes_from_the_pool_and_its_posts() {
+    let posts = [
+        post(
+            1,
+            &["someartist", "conditional_dnp"],
+            &["cloud", "sky"],
+            "s",
+        ),
+        post(2, &["someartist", "colorist"], &["cloud"], "q"),
+        post(3, &["someartist"], &["rain"], "s"),
+    ];
+    let info = ComicInfo::from_pool(&pool(Some("  A comic.  ")), &posts, "e621.net");
+
+    assert_eq!(info.title, "Cloud Jumping");
+    assert_eq!(info.summary.as_deref(), Some("A comic."));
+    assert_eq!(info.artists, vec!["someartist", "colorist"]);
+    assert_eq!(info.tags, vec!["cloud"]);
+    assert_eq!(info.page_count, 3);
+    assert_eq!(info.web, "https://e621.net/pools/22364");
+    assert_eq!(info.age_rating, "Mature 17+");
+
+    let explicit = ComicInfo::from_pool(&pool(None), &[post(1, &[], &[], "e")], "e621.net");
+    assert_eq!(explicit.age_rating, "Adults Only 18+");
+    assert_eq!(explicit.summary, None);
+    assert_eq!(
+        ComicInfo::from_pool(&pool(Some(" ")), &[], "e926.net").age_rating,
+        "Unknown"
+    );
+}
+
+#[test]
+fn xml_is_escaped_and_leaves_out_empty_fields() {
+    let info = ComicInfo::from_pool(
+        &pool(Some("Part 1 <of 2> & \"more\"\u{7}")),
+        &[post(1, &[], &["cloud"], "s")],
+        "e926.net",
+    );
+    let xml = info.to_xml();
+
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ComicInfo "));
+    assert!(xml.contains("  <Series>Cloud Jumping</Series>\n"), "{xml}");
+    assert!(
+        xml.contains("<Summary>Part 1 &lt;of 2&gt; &amp; &quot;more&quot;</Summary>"),
+        "{xml}"
+    );
+    assert!(xml.contains("<Tags>cloud</Tags>"), "{xml}");
+    assert!(xml.contains("<PageCount>1</PageCount>"), "{xml}");
+    assert!(xml.contains("<AgeRating>Everyone</AgeRating>"), "{xml}");
+    assert!(!xml.contains("<Writer>"), "{xml}");
+    assert!(xml.ends_with("</ComicInfo>\n"));
+}
+
+#[test]
+fn xml_reads_back() {
+    let info = ComicInfo::from_pool(
+        &pool(Some("Part <1> & more")),
+        &[post(1, &["someartist", "colorist"], &["cloud", "sky"], "e")],
+        "e621.net",
+    );
+    assert_eq!(ComicInfo::from_xml(&info.to_xml()), info);
+    assert_eq!(ComicInfo::from_xml(""), ComicInfo::default());
+}
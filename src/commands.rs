@@ -1,45 +1,31 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::mpsc::channel;
+use std::sync::Arc;
 
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::blocking::Client;
-
-use rayon::prelude::*;
-use tracing::{Level, debug, error, info, span};
+use reqwest::Client;
+use tracing::{error, info, warn};
 
+use crate::archive;
 use crate::cli::ArchiveFormat;
-use crate::funcs::{
-    self, DownloadFinished, ensure_dl_dir, get_pages, get_pool, get_post_data, slice_pool_posts,
-    slice_posts, sum_posts,
-};
+use crate::comic_info::{self, ComicInfo};
+use crate::config::PresetConfig;
+use crate::downloader::Downloader;
+use crate::export;
+use crate::funcs::{self, ensure_dl_dir, get_pool, get_post_data, get_post_set};
+use crate::gallery;
+use crate::manifest;
+use crate::state;
 use crate::tracker::Tracker;
-use crate::type_defs::api_defs::{self, Post};
-use crate::{AGENT, CliContext, DownloadStatistics, Login};
-
-fn report_progress(
-    context: &CliContext,
-    completed: i64,
-    failed: i64,
-    skipped: i64,
-    total: usize,
-    downloaded_amount: f64,
-) {
-    if let Some(observer) = &context.progress {
-        observer(crate::DownloadProgress {
-            completed,
-            failed,
-            skipped,
-            total,
-            downloaded_amount,
-            phase: None,
-        });
-    }
-}
+use crate::type_defs::api_defs::{ArtistData, PoolData, Post};
+use crate::{
+    AGENT, CliContext, DownloadError, DownloadProgress, DownloadRecord, DownloadStatistics,
+    FailureKind, Login, RecordStatus,
+};
 
-/// Builds a `reqwest::blocking::Client` configured with e-cli's `User-Agent` and
+/// Builds a `reqwest::Client` configured with e-cli's `User-Agent` and
 /// no request timeout (downloads of large files can legitimately take a while).
 /// Callers should build one client per top-level operation and reuse it across
 /// requests/downloads rather than constructing a new one per file, so that
@@ -47,27 +33,13 @@ fn report_progress(
 pub fn get_client() -> Client {
     Client::builder()
         .user_agent(AGENT)
-        // !Experimental
-        .timeout(None)
         .build()
         .expect("Error creating Client")
 }
 
-fn new_progress_bar(mp: &MultiProgress, total: u64) -> ProgressBar {
-    let bar = mp.add(ProgressBar::new(total));
-    bar.set_style(
-        ProgressStyle::with_template(
-            "{spinner} [{bar:40}] {pos}/{len} files ({per_sec}/s, ETA {eta})",
-        )
-        .expect("Invalid progress bar template")
-        .progress_chars("#>-"),
-    );
-    bar
-}
-
 /// Downloads a user's favourited posts into `output_dir`, optionally narrowed by
-/// `tags`. Pages are fetched according to `context.pages`, and each page's posts
-/// are downloaded in parallel chunks sized by `context.num_threads`.
+/// `tags`. Pages are fetched according to `context.pages`, and downloading
+/// starts with the first page, up to `context.num_threads` files at a time.
 ///
 /// `mp` receives one progress bar tracking overall files completed/total; `count`
 /// is the API page size (posts per request), not a total cap. Posts already
@@ -76,7 +48,8 @@ fn new_progress_bar(mp: &MultiProgress, total: u64) -> ProgressBar {
 /// `output_dir`. Returns [`DownloadStatistics::default`] (all zero) if no posts
 /// were found for the given favourites/tags.
 #[allow(clippy::too_many_arguments)]
-pub fn download_favourites(
+#[tracing::instrument(name = "DFavs", level = "debug", skip_all)]
+pub async fn download_favourites(
     context: &CliContext,
     login: &Login,
     username: &str,
@@ -87,89 +60,67 @@ pub fn download_favourites(
     output_dir: &Path,
     tracker: Option<&Tracker>,
 ) -> DownloadStatistics {
-    let span = span!(Level::DEBUG, "DFavs");
-    let _guard = span.enter();
-
     info!(
         "Downloading Favorites of {username} into the {} folder!",
         output_dir.display()
     );
-    let client = get_client();
-    let random_check: &str = if *random { "order:random" } else { "" };
-    let tags: &str = if !tags.is_empty() { tags } else { "" };
-    let fav: String = format!("fav:{}", username);
-    info!("Getting posts from pages!");
-    let data: Vec<Vec<Post>> = get_pages(context, login, &client, &fav, tags, random_check, count);
-    if data.is_empty() {
-        error!("No posts found...");
-        return DownloadStatistics::default();
-    }
-    ensure_dl_dir(output_dir);
-    let total = sum_posts(&data);
-    info!("Downloading {} posts...", total);
-    let bar = new_progress_bar(mp, total as u64);
-    let mut full_sum = 0.0;
-    let mut finished: i64 = 0;
-    let mut failed: i64 = 0;
-    let mut skipped: i64 = 0;
-    let mut records = Vec::new();
-    // Use one post per rayon task so the progress bar and ETA update after
-    // every completed file rather than waiting for a multi-post chunk.
-    let chunk_size = 1;
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(context.num_threads)
-        .build()
-        .unwrap();
-    for posts in data {
-        let sliced_data = slice_posts(api_defs::Posts { posts }, chunk_size);
-        let (tx, rx) = channel::<Vec<DownloadFinished>>();
-        let bar = bar.clone();
-        // Multi-threaded implementation.
-        pool.install(|| {
-            debug!("Starting download of {} posts.", sliced_data.len());
-            let dl_size: Vec<DownloadFinished> = sliced_data
-                .into_par_iter()
-                .map(|posts| {
-                    let low_quality = &context.lower_quality;
-                    let count = posts.len() as u64;
-                    let result = funcs::download_with_options(
-                        &client,
-                        login,
-                        posts.to_vec(),
-                        None,
-                        low_quality,
-                        output_dir,
-                        tracker,
-                        funcs::DownloadOptions {
-                            retries: context.retries,
-                            duplicate_index: context.duplicate_index.as_deref(),
-                            cancel: context.cancel.clone(),
-                        },
-                    );
-                    bar.inc(count);
-                    result
-                })
-                .collect();
-
-            tx.send(dl_size).unwrap();
-        });
-        for status in rx.recv().unwrap() {
-            finished += status.amount_finished;
-            failed += status.amount_failed;
-            skipped += status.amount_skipped;
-            full_sum += status.amount;
-            records.extend(status.records);
-            report_progress(context, finished, failed, skipped, total, full_sum);
-        }
-    }
-    bar.finish_with_message("Done!");
-    DownloadStatistics {
-        completed: finished,
-        failed,
-        skipped,
-        total,
-        downloaded_amount: full_sum,
-        records,
+    download_query(
+        context,
+        login,
+        &format!("fav:{username}"),
+        tags,
+        count,
+        random,
+        mp,
+        output_dir,
+        tracker,
+    )
+    .await
+}
+
+/// Downloads the posts a user uploaded into `output_dir`, optionally narrowed
+/// by `tags`. `user` is a username, or a numeric account ID when `by_id` is
+/// set (a `user_id:` search, which keeps working after a rename). Pages,
+/// `count`, `random`, skipping and the return value behave exactly as in
+/// [`download_favourites`].
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "DUploads", level = "debug", skip_all)]
+pub async fn download_uploads(
+    context: &CliContext,
+    login: &Login,
+    user: &str,
+    by_id: &bool,
+    count: &u32,
+    random: &bool,
+    tags: &str,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    info!(
+        "Downloading uploads of {user} into the {} folder!",
+        output_dir.display()
+    );
+    download_query(
+        context,
+        login,
+        &uploader_query(user, *by_id),
+        tags,
+        count,
+        random,
+        mp,
+        output_dir,
+        tracker,
+    )
+    .await
+}
+
+/// The search term selecting a user's uploads, as used by [`download_uploads`].
+pub fn uploader_query(user: &str, by_id: bool) -> String {
+    if by_id {
+        format!("user_id:{user}")
+    } else {
+        format!("user:{user}")
     }
 }
 
@@ -180,7 +131,8 @@ pub fn download_favourites(
 /// the CLI disallows that default for this specific command via
 /// [`crate::cli::validate_args`], but this function itself has no such guard.
 #[allow(clippy::too_many_arguments)]
-pub fn download_search(
+#[tracing::instrument(name = "DTags", level = "debug", skip_all)]
+pub async fn download_search(
     context: &CliContext,
     login: &Login,
     tags: &str,
@@ -190,99 +142,160 @@ pub fn download_search(
     output_dir: &Path,
     tracker: Option<&Tracker>,
 ) -> DownloadStatistics {
-    let span = span!(Level::DEBUG, "DTags");
-    let _guard = span.enter();
-
     info!(
         "Downloading posts, with '{tags}' tag/s, into the {} folder!",
         output_dir.display()
     );
+    download_query(
+        context, login, "", tags, page_count, random, mp, output_dir, tracker,
+    )
+    .await
+}
+
+/// Fetches the pages of a search (`prefix`, e.g. `fav:someuser`, combined with
+/// `tags`) and downloads them. Shared by the favourites, uploads and tag search
+/// downloads, which differ only in the search they run.
+#[allow(clippy::too_many_arguments)]
+async fn download_query(
+    context: &CliContext,
+    login: &Login,
+    prefix: &str,
+    tags: &str,
+    count: &u32,
+    random: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
     let client = get_client();
     let random_check: &str = if *random { "order:random" } else { "" };
-    let tags: &str = if !tags.is_empty() { tags } else { "" };
-    let fav = "";
     info!("Getting posts from pages!");
-    let data: Vec<Vec<Post>> =
-        get_pages(context, login, &client, fav, tags, random_check, page_count);
-    if data.is_empty() {
+    let stats = Downloader::new(context, login, &client, mp, output_dir, tracker)
+        .run_pages(async |sink| {
+            funcs::for_each_page(
+                context,
+                login,
+                &client,
+                prefix,
+                tags,
+                random_check,
+                count,
+                async |page| sink.send(page).await,
+            )
+            .await
+        })
+        .await;
+    if stats.total == 0 {
         error!("No posts found...");
-        return DownloadStatistics::default();
     }
-    ensure_dl_dir(output_dir);
-    let total = sum_posts(&data);
-    info!("Downloading {} posts...", total);
-    let bar = new_progress_bar(mp, total as u64);
-    let mut full_sum = 0.0;
-    let mut finished: i64 = 0;
-    let mut failed: i64 = 0;
-    let mut skipped: i64 = 0;
-    let mut records = Vec::new();
-    let chunk_size = 1;
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(context.num_threads)
-        .build()
-        .unwrap();
-    for posts in data {
-        let sliced_data = slice_posts(api_defs::Posts { posts }, chunk_size);
-
-        let (tx, rx) = channel::<Vec<DownloadFinished>>();
-        let bar = bar.clone();
-
-        // Multi-threaded implementation.
-        pool.install(|| {
-            let dl_size: Vec<DownloadFinished> = sliced_data
-                .into_par_iter()
-                .map(|posts| {
-                    let low_quality = &context.lower_quality;
-                    let count = posts.len() as u64;
-                    let result = funcs::download_with_options(
-                        &client,
-                        login,
-                        posts.to_vec(),
-                        None,
-                        low_quality,
-                        output_dir,
-                        tracker,
-                        funcs::DownloadOptions {
-                            retries: context.retries,
-                            duplicate_index: context.duplicate_index.as_deref(),
-                            cancel: context.cancel.clone(),
-                        },
-                    );
-                    bar.inc(count);
-                    result
-                })
-                .collect();
-
-            tx.send(dl_size).unwrap();
-        });
-        for status in rx.recv().unwrap() {
-            finished += status.amount_finished;
-            failed += status.amount_failed;
-            skipped += status.amount_skipped;
-            full_sum += status.amount;
-            records.extend(status.records);
-            report_progress(context, finished, failed, skipped, total, full_sum);
+    stats
+}
+
+/// Downloads everything by an artist into `output_dir`. The artist is looked
+/// up by name or one of their other names (see [`artist_tags`]), and every tag
+/// naming them — the artist tag, its aliases, and the other names listed on the
+/// artist entry — is searched in turn, with posts found under several of them
+/// downloaded once. With `write_urls`, the artist's other-site URLs are also
+/// written to `{artist}.urls.txt` in `output_dir`, one per line. Returns
+/// [`DownloadStatistics::default`] if the artist isn't found or has no posts.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "DArtist", level = "debug", skip_all)]
+pub async fn download_artist(
+    context: &CliContext,
+    login: &Login,
+    name: &str,
+    count: &u32,
+    write_urls: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    let client = get_client();
+    let (artist, tags) = match artist_tags(context, &client, login, name).await {
+        Ok(found) => found,
+        Err(e) => {
+            error!("{e}");
+            return DownloadStatistics::default();
+        }
+    };
+    info!(
+        "Downloading posts by {} (tags: {}) into the {} folder!",
+        artist.name,
+        tags.join(", "),
+        output_dir.display()
+    );
+    if *write_urls {
+        ensure_dl_dir(output_dir);
+        let path = output_dir.join(format!(
+            "{}.urls.txt",
+            funcs::sanitize_file_name(&artist.name)
+        ));
+        let urls = artist
+            .urls
+            .iter()
+            .map(|url| format!("{}\n", url.url))
+            .collect::<String>();
+        match fs::write(&path, urls) {
+            Ok(()) => info!(
+                "Wrote {} artist URLs to {}.",
+                artist.urls.len(),
+                path.display()
+            ),
+            Err(e) => error!("Failed to write {}: {e}", path.display()),
         }
     }
-    bar.finish_with_message("Done!");
-    DownloadStatistics {
-        completed: finished,
-        failed,
-        skipped,
-        total,
-        downloaded_amount: full_sum,
-        records,
+    info!("Getting posts from pages!");
+    let stats = Downloader::new(context, login, &client, mp, output_dir, tracker)
+        .run_pages(async |sink| {
+            funcs::for_each_page_for_tags(context, login, &client, &tags, count, async |page| {
+                sink.send(page).await
+            })
+            .await
+        })
+        .await;
+    if stats.total == 0 {
+        error!("No posts found...");
+    }
+    stats
+}
+
+/// Resolves `name` to an artist entry and the tags that name them: the artist
+/// tag itself, followed by tags aliased to it and the entry's other names
+/// (normalized to tag form), without repeats.
+pub async fn artist_tags(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+    name: &str,
+) -> Result<(ArtistData, Vec<String>), String> {
+    let artist = funcs::get_artist(context, client, login, name).await?;
+    let mut tags = vec![artist.name.clone()];
+    let aliases = funcs::get_tag_aliases(context, client, login, &artist.name)
+        .await
+        .into_iter()
+        .map(|alias| alias.antecedent_name);
+    let other_names = artist
+        .other_names
+        .iter()
+        .map(|other| other.trim().replace(' ', "_").to_lowercase());
+    for tag in aliases.chain(other_names) {
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
     }
+    Ok((artist, tags))
 }
 
-/// Downloads every post in the pool identified by `pool_id` into `output_dir`,
-/// with each file named `{0001, 0002, ...}-{artist}-{post_id}.{ext}` so the pool's
-/// original order is preserved regardless of parallel download order (index
-/// zero-padded to 4 digits, matching pool page ordering — important for archive
-/// readers, see [`zip_downloads`]). Returns
+/// Downloads every post in the pool identified by `pool_id` into its own
+/// subdirectory of `output_dir`, named after the pool (see
+/// [`funcs::pool_dir_name`]), with each file named
+/// `{0001, 0002, ...}-{artist}-{post_id}.{ext}` so the pool's original order is
+/// preserved regardless of parallel download order (index zero-padded to 4
+/// digits, matching pool page ordering — important for archive readers, see
+/// [`zip_downloads`]). A `ComicInfo.xml` describing the pool (see
+/// [`comic_info`]) is written next to the files, for cbz readers. Returns
 /// [`DownloadStatistics::default`] if the pool doesn't exist or has no posts.
-pub fn download_pool(
+pub async fn download_pool(
     context: &CliContext,
     login: &Login,
     pool_id: &u64,
@@ -290,126 +303,547 @@ pub fn download_pool(
     output_dir: &Path,
     tracker: Option<&Tracker>,
 ) -> DownloadStatistics {
-    let span = span!(Level::DEBUG, "DPool");
-    let _guard = span.enter();
+    let client = get_client();
+    match get_pool(context, &client, login, pool_id).await {
+        Some(data) => {
+            download_pool_data(context, login, &client, &data, mp, output_dir, tracker).await
+        }
+        None => DownloadStatistics::default(),
+    }
+}
 
+/// Downloads each of `pools` (as returned by [`resolve_pools`]) the same way
+/// as [`download_pool`], one after another, each into its own subdirectory of
+/// `output_dir` — or, with `archive`, straight into an archive each (see
+/// [`download_pool_archive`]). The returned statistics cover every pool.
+/// The posts of pools not started before the download budget (if any) was
+/// reached are recorded as remaining, and those of pools not started before
+/// the download was cancelled as cancelled, for `retry-failed`.
+pub async fn download_pools(
+    context: &CliContext,
+    login: &Login,
+    pools: &[PoolData],
+    archive: Option<ArchiveFormat>,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
     let client = get_client();
-    if let Some(data) = get_pool(context, &client, login, pool_id) {
-        ensure_dl_dir(output_dir);
+    let mut stats = DownloadStatistics::default();
+    for (index, pool) in pools.iter().enumerate() {
+        if is_cancelled(context) {
+            for pool in &pools[index..] {
+                stats.merge(cancelled_pool(pool));
+            }
+            break;
+        }
+        if context.budget.as_ref().is_some_and(|b| b.is_reached()) {
+            warn!(
+                "Download budget reached, not starting pool '{}'.",
+                pool.display_name()
+            );
+            stats.merge(unstarted_pool(
+                pool,
+                RecordStatus::Remaining,
+                DownloadError::new(FailureKind::BudgetReached, "download budget reached"),
+            ));
+            continue;
+        }
+        stats.merge(match archive {
+            Some(format) => {
+                download_pool_archive(
+                    context, login, &client, pool, format, mp, output_dir, tracker,
+                )
+                .await
+            }
+            None => {
+                download_pool_data(context, login, &client, pool, mp, output_dir, tracker).await
+            }
+        });
+    }
+    stats
+}
+
+/// Statistics recording every post of `pool`, which wasn't started, as
+/// `status` (remaining or cancelled) because of `error`. Only the post IDs
+/// are known, which is all `retry-failed` needs.
+fn unstarted_pool(
+    pool: &PoolData,
+    status: RecordStatus,
+    error: DownloadError,
+) -> DownloadStatistics {
+    let records = pool
+        .post_ids
+        .iter()
+        .map(|&post_id| DownloadRecord {
+            post_id,
+            source_url: None,
+            md5: None,
+            artist: String::new(),
+            rating: String::new(),
+            tags: vec![],
+            extension: String::new(),
+            local_filename: None,
+            status,
+            bytes: 0,
+            error: Some(error.clone()),
+            derived: None,
+        })
+        .collect::<Vec<_>>();
+    let count = records.len();
+    DownloadStatistics {
+        remaining: if status == RecordStatus::Remaining {
+            count as i64
+        } else {
+            0
+        },
+        cancelled: if status == RecordStatus::Cancelled {
+            count as i64
+        } else {
+            0
+        },
+        total: count,
+        records,
+        ..Default::default()
+    }
+}
+
+/// [`unstarted_pool`] for a pool not started, or whose posts couldn't be
+/// fetched, because the download was cancelled.
+fn cancelled_pool(pool: &PoolData) -> DownloadStatistics {
+    unstarted_pool(
+        pool,
+        RecordStatus::Cancelled,
+        DownloadError::new(FailureKind::Cancelled, "download cancelled"),
+    )
+}
+
+fn is_cancelled(context: &CliContext) -> bool {
+    context
+        .cancel
+        .as_ref()
+        .is_some_and(|cancel| cancel.is_cancelled())
+}
+
+#[tracing::instrument(name = "DPool", level = "debug", skip_all)]
+async fn download_pool_data(
+    context: &CliContext,
+    login: &Login,
+    client: &Client,
+    data: &PoolData,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    let output_dir = output_dir.join(funcs::pool_dir_name(data));
+    info!(
+        "Downloading pool '{}' ({}) into the {} folder!",
+        data.display_name(),
+        data.id,
+        output_dir.display()
+    );
+    let posts = get_post_data(context, client, login, &data.post_ids).await;
+    if posts.is_empty() && is_cancelled(context) {
+        return cancelled_pool(data);
+    }
+    if posts.is_empty() {
+        error!("Error getting post data.");
+        return DownloadStatistics::default();
+    }
+    let total = posts.len();
+    let comic_info = ComicInfo::from_pool(data, &posts, context.api_source());
+    let posts_indexed = posts
+        .into_iter()
+        .enumerate()
+        .map(|(i, post)| (Some((i as u64) + 1), post));
+    let stats = Downloader::new(context, login, client, mp, &output_dir, tracker)
+        .run(posts_indexed, total)
+        .await;
+    if output_dir.is_dir() {
+        let path = output_dir.join(comic_info::FILE_NAME);
+        if let Err(e) = state::write_atomic(&path, comic_info.to_xml()) {
+            warn!("Failed to write {}: {e}", path.display());
+        }
+    }
+    stats
+}
+
+/// Downloads the pool `data` into a zip or cbz archive named after it (see
+/// [`funcs::pool_dir_name`]) in `output_dir`, with a `ComicInfo.xml` for cbz
+/// readers, instead of into a folder. Pages are added in pool order as they
+/// finish (see [`archive::PoolArchive`]); until then they're kept in a
+/// `{archive}.part` staging directory next to it. A run that stops early
+/// (cancelled, a failed page, the download budget) leaves the unfinished
+/// archive and the waiting pages there, and the next run carries on from the
+/// first page missing. Pools whose archive already exists are skipped.
+/// Deleted pages are left out. Pages aren't checked against the duplicate
+/// index, whose files may live elsewhere, or against `tracker`, since pages waiting in the staging directory or already in the
+/// unfinished archive were tracked when they downloaded, and would be left
+/// out of the archive; instead the pool's posts are tracked once the archive
+/// is complete.
+#[tracing::instrument(name = "DPool", level = "debug", skip_all)]
+#[allow(clippy::too_many_arguments)]
+async fn download_pool_archive(
+    context: &CliContext,
+    login: &Login,
+    client: &Client,
+    data: &PoolData,
+    format: ArchiveFormat,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    let out = output_dir.join(format!(
+        "{}.{}",
+        funcs::pool_dir_name(data),
+        format.extension()
+    ));
+    if out.exists() {
         info!(
-            "Downloading pool with id '{pool_id}' into the {} folder!",
-            output_dir.display()
+            "Pool '{}' ({}) is already archived in {}, skipping.",
+            data.display_name(),
+            data.id,
+            out.display()
         );
-        let mut posts_indexed: HashMap<u64, Post> = HashMap::new();
-        let posts = get_post_data(context, &client, login, &data.post_ids);
-        if posts.is_empty() {
-            error!("Error getting post data.");
+        let pages = data.post_ids.len() as i64;
+        return DownloadStatistics {
+            skipped: pages,
+            total: pages as usize,
+            ..Default::default()
+        };
+    }
+    let mut staging = out.clone().into_os_string();
+    staging.push(".part");
+    let staging = PathBuf::from(staging);
+    ensure_dl_dir(&staging);
+    let archive = match archive::PoolArchive::open(&staging.join(PARTIAL_ARCHIVE)) {
+        Ok(archive) => archive,
+        Err(e) => {
+            error!("{e}");
             return DownloadStatistics::default();
         }
-        for (i, _) in data.post_ids.iter().enumerate() {
-            let index: u64 = (i as u64) + 1;
-            posts_indexed.insert(
-                index,
-                posts
-                    .iter()
-                    .find(|&p| p == &posts[i])
-                    .expect("Post not found.")
-                    .clone(),
-            );
+    };
+
+    info!(
+        "Downloading pool '{}' ({}) into {}!",
+        data.display_name(),
+        data.id,
+        out.display()
+    );
+    let posts = get_post_data(context, client, login, &data.post_ids).await;
+    if posts.is_empty() && is_cancelled(context) {
+        return cancelled_pool(data);
+    }
+    if posts.is_empty() {
+        error!("Error getting post data.");
+        return DownloadStatistics::default();
+    }
+    let last = posts.len() as u64;
+    let comic_info = ComicInfo::from_pool(data, &posts, context.api_source());
+    let posts = posts
+        .into_iter()
+        .enumerate()
+        .map(|(i, post)| ((i as u64) + 1, post))
+        .filter(|(index, _)| *index >= archive.next_index())
+        .collect::<Vec<_>>();
+    let (stats, archive, result) =
+        archive_pages(context, login, client, mp, &staging, archive, posts).await;
+
+    if let Err(e) = result {
+        error!("{e}");
+    } else if archive.is_complete(last) {
+        let pages = archive.pages();
+        let xml = comic_info.to_xml();
+        match archive.complete(&[(comic_info::FILE_NAME, xml.as_bytes())], &out) {
+            Ok(()) => {
+                if let Err(e) = fs::remove_dir_all(&staging) {
+                    warn!("Failed to remove {}: {e}", staging.display());
+                }
+                if let Some(tracker) = tracker {
+                    for &post_id in &data.post_ids {
+                        tracker.insert(post_id);
+                    }
+                }
+                info!("Archived {pages} pages into {}.", out.display());
+            }
+            Err(e) => error!("{e}"),
         }
-        info!("Downloading {} posts...", data.post_ids.len());
-        let mut posts_sorted = posts_indexed.into_iter().collect::<Vec<_>>();
-        posts_sorted.sort_by_key(|a| a.0);
-        let bar = new_progress_bar(mp, posts_sorted.len() as u64);
-        let mut full_sum = 0.0;
-        let mut finished: i64 = 0;
-        let mut failed: i64 = 0;
-        let mut skipped: i64 = 0;
-        let mut records = Vec::new();
-        let chunk_size = 1;
-        let sliced_posts = slice_pool_posts(posts_sorted, chunk_size);
-        let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(context.num_threads)
-            .build()
-            .unwrap();
-        let (tx, rx) = channel::<Vec<DownloadFinished>>();
-        let bar_clone = bar.clone();
-        pool.install(|| {
-            let dl_sizes: Vec<DownloadFinished> = sliced_posts
-                .into_par_iter()
-                .map(|chunk| {
-                    let mut sum = DownloadFinished {
-                        amount_finished: 0,
-                        amount_failed: 0,
-                        amount_skipped: 0,
-                        amount: 0.0,
-                        records: Vec::new(),
-                    };
-                    for (index, post) in chunk {
-                        let result = funcs::download_with_options(
-                            &client,
-                            login,
-                            vec![post],
-                            Some(&index),
-                            &context.lower_quality,
-                            output_dir,
-                            tracker,
-                            funcs::DownloadOptions {
-                                retries: context.retries,
-                                duplicate_index: context.duplicate_index.as_deref(),
-                                cancel: context.cancel.clone(),
-                            },
-                        );
-                        sum.amount_finished += result.amount_finished;
-                        sum.amount_failed += result.amount_failed;
-                        sum.amount_skipped += result.amount_skipped;
-                        sum.amount += result.amount;
-                        bar_clone.inc(1);
+        return stats;
+    }
+    if let Err(e) = archive.suspend() {
+        error!("{e}");
+    }
+    warn!(
+        "Pool '{}' isn't complete; run d-pool again to finish {}.",
+        data.display_name(),
+        out.display()
+    );
+    stats
+}
+
+/// Downloads the pages of a pool archive, numbered by their place in the
+/// pool, into `staging`, and adds each to `archive` as it's done. Pages
+/// already downloaded elsewhere are downloaded again: the archive needs their
+/// files, so neither the duplicate index nor the tracking file skips them.
+async fn archive_pages(
+    context: &CliContext,
+    login: &Login,
+    client: &Client,
+    mp: &MultiProgress,
+    staging: &Path,
+    mut archive: archive::PoolArchive,
+    posts: Vec<(u64, Post)>,
+) -> (DownloadStatistics, archive::PoolArchive, Result<(), String>) {
+    let indexes = posts
+        .iter()
+        .map(|(index, post)| (post.id, *index))
+        .collect::<HashMap<_, _>>();
+
+    // Pages are handed to the archive from the progress reports, and
+    // appended on a thread of their own so the downloads carry on meanwhile.
+    let (tx, rx) = std::sync::mpsc::channel::<(u64, Option<PathBuf>)>();
+    let mut run_context = context.clone();
+    run_context.duplicate_index = None;
+    let forward = context.progress.clone();
+    let page_dir = staging.to_path_buf();
+    run_context.progress = Some(Arc::new(move |progress: DownloadProgress| {
+        if let Some(record) = &progress.record
+            && let Some(&index) = indexes.get(&record.post_id)
+            && let Some(page) = archived_page(record, &page_dir)
+        {
+            let _ = tx.send((index, page));
+        }
+        if let Some(forward) = &forward {
+            forward(progress);
+        }
+    }));
+    let appender = tokio::task::spawn_blocking(move || {
+        let mut result = Ok(());
+        for (index, page) in rx {
+            if result.is_ok() {
+                result = archive.add(index, page);
+            }
+        }
+        (archive, result)
+    });
+
+    let total = posts.len();
+    let posts = posts.into_iter().map(|(index, post)| (Some(index), post));
+    let stats = Downloader::new(&run_context, login, client, mp, staging, None)
+        .run(posts, total)
+        .await;
+    // Closes the channel, so the appender finishes.
+    drop(run_context);
+    let (archive, result) = appender.await.expect("archive thread panicked");
+    (stats, archive, result)
+}
+
+/// The unfinished archive in a [`download_pool_archive`] staging directory.
+const PARTIAL_ARCHIVE: &str = ".e-cli-archive.zip";
+
+/// What to put into a pool archive for a downloaded page: its file in
+/// `dir` (just downloaded, or waiting there from an earlier run), `None` to
+/// leave it out for good (it failed for good, e.g. deleted, so there's no
+/// file), or nothing yet if a later run may still download it.
+fn archived_page(record: &DownloadRecord, dir: &Path) -> Option<Option<PathBuf>> {
+    if let Some(name) = &record.local_filename
+        && dir.join(name).is_file()
+    {
+        return Some(Some(dir.join(name)));
+    }
+    let permanent = record.error.as_ref().is_some_and(|e| e.kind.is_permanent());
+    (record.status == RecordStatus::Failed && permanent).then_some(None)
+}
+
+/// Resolves `queries` — each either a numeric pool ID or a pool name — to pool
+/// metadata, in the order given and without repeats. A name must identify one
+/// pool: an exact (case-insensitive) name match wins, otherwise the search has
+/// to return a single pool. With `series`, pools linked from each resolved
+/// pool's description (see [`funcs::linked_pool_ids`]) are followed and added
+/// too, transitively. Errors name the query that couldn't be resolved.
+pub async fn resolve_pools(
+    context: &CliContext,
+    login: &Login,
+    queries: &[String],
+    series: bool,
+) -> Result<Vec<PoolData>, String> {
+    let client = get_client();
+    let mut pools: Vec<PoolData> = Vec::new();
+    for query in queries {
+        let pool = match query.trim().parse::<u64>() {
+            Ok(id) => get_pool(context, &client, login, &id)
+                .await
+                .ok_or_else(|| format!("No pool found with id '{id}'."))?,
+            Err(_) => pick_pool(
+                query,
+                funcs::search_pools(context, &client, login, query, &25).await,
+            )?,
+        };
+        if !pools.iter().any(|p| p.id == pool.id) {
+            pools.push(pool);
+        }
+    }
+    if series {
+        let mut i = 0;
+        while i < pools.len() {
+            let linked =
+                funcs::linked_pool_ids(pools[i].description.as_deref().unwrap_or_default());
+            for id in linked {
+                if pools.iter().any(|p| p.id == id) {
+                    continue;
+                }
+                match get_pool(context, &client, login, &id).await {
+                    Some(pool) => {
+                        info!("Following linked pool '{}' ({id}).", pool.display_name());
+                        pools.push(pool);
                     }
-                    sum
-                })
-                .collect();
+                    None => warn!("Linked pool {id} could not be fetched, skipping."),
+                }
+            }
+            i += 1;
+        }
+    }
+    Ok(pools)
+}
 
-            tx.send(dl_sizes).unwrap();
-        });
-        for status in rx.recv().unwrap() {
-            finished += status.amount_finished;
-            failed += status.amount_failed;
-            skipped += status.amount_skipped;
-            full_sum += status.amount;
-            records.extend(status.records);
-            report_progress(
+fn pick_pool(query: &str, matches: Vec<PoolData>) -> Result<PoolData, String> {
+    let wanted = query.trim().replace('_', " ").to_lowercase();
+    if let Some(pool) = matches
+        .iter()
+        .find(|pool| pool.display_name().to_lowercase() == wanted)
+    {
+        return Ok(pool.clone());
+    }
+    match matches.len() {
+        0 => Err(format!("No pool matches '{query}'.")),
+        1 => Ok(matches.into_iter().next().expect("one match")),
+        n => Err(format!(
+            "'{query}' matches {n} pools ({}); pass a pool ID instead (see `e-cli pools search`).",
+            matches
+                .iter()
+                .take(5)
+                .map(|pool| format!("{} '{}'", pool.id, pool.display_name()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Downloads every post in a post set, looked up by numeric ID or shortname
+/// (see [`funcs::get_post_set`]), into `output_dir`. With `indexed`, files are
+/// prefixed with their zero-padded position in the set the same way
+/// [`download_pool`] numbers pages; otherwise they use the plain
+/// `{artist}-{post_id}.{ext}` naming. Returns [`DownloadStatistics::default`]
+/// if the set doesn't exist or has no posts.
+#[tracing::instrument(name = "DSet", level = "debug", skip_all)]
+pub async fn download_set(
+    context: &CliContext,
+    login: &Login,
+    set: &str,
+    indexed: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    let client = get_client();
+    let Some(data) = get_post_set(context, &client, login, set).await else {
+        error!("No post set found for '{set}'.");
+        return DownloadStatistics::default();
+    };
+    info!(
+        "Downloading set '{}' ({}) into the {} folder!",
+        data.name,
+        data.shortname,
+        output_dir.display()
+    );
+    let posts = get_post_data(context, &client, login, &data.post_ids).await;
+    if posts.is_empty() {
+        error!("Error getting post data.");
+        return DownloadStatistics::default();
+    }
+    let total = posts.len();
+    let posts = posts
+        .into_iter()
+        .enumerate()
+        .map(|(i, post)| (indexed.then_some((i as u64) + 1), post));
+    Downloader::new(context, login, &client, mp, output_dir, tracker)
+        .run(posts, total)
+        .await
+}
+
+/// Runs a named preset from `config.toml`. Presets with `source = "set"`
+/// download the configured post set (see [`download_set`]); every other preset
+/// is a tag search (see [`download_search`]). `count` and `random` are the
+/// already-merged CLI/preset values.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_preset(
+    context: &CliContext,
+    login: &Login,
+    preset: &PresetConfig,
+    count: &u32,
+    random: &bool,
+    mp: &MultiProgress,
+    output_dir: &Path,
+    tracker: Option<&Tracker>,
+) -> DownloadStatistics {
+    match preset.source.as_deref() {
+        Some("set") => {
+            download_set(
                 context,
-                finished,
-                failed,
-                skipped,
-                data.post_ids.len(),
-                full_sum,
-            );
+                login,
+                preset.set.as_deref().unwrap_or_default(),
+                &preset.indexed.unwrap_or(false),
+                mp,
+                output_dir,
+                tracker,
+            )
+            .await
         }
-        bar.finish_with_message("Done!");
-
-        DownloadStatistics {
-            completed: finished,
-            failed,
-            skipped,
-            total: data.post_ids.len(),
-            downloaded_amount: full_sum,
-            records,
+        _ => {
+            download_search(
+                context,
+                login,
+                preset.tags.as_deref().unwrap_or_default(),
+                count,
+                random,
+                mp,
+                output_dir,
+                tracker,
+            )
+            .await
         }
-    } else {
-        DownloadStatistics::default()
     }
 }
 
-/// Packages the contents of `dir` (as produced by [`download_pool`]) into an
-/// archive named `{name}.{ext}` in the current working directory, where `ext`
-/// comes from [`ArchiveFormat::extension`]. Only meaningful for pool downloads,
-/// since the index-prefixed filenames are what makes a resulting cbz/zip
-/// readable in order. Shells out to the `7z` executable, which must be on
-/// `PATH`. Returns `false` (and logs the reason) if `dir` doesn't exist or `7z`
-/// fails/isn't found; `name` is sanitized by stripping `/` before use.
-pub fn zip_downloads(dir: &Path, name: &str, format: ArchiveFormat) -> bool {
+/// Where `zip` writes the archive named `name`: `{name}.{ext}` (with `/`
+/// stripped from `name`, and `ext` from [`ArchiveFormat::extension`]) in
+/// `to` if it's a directory, at `to` itself if it's a path, or in the current
+/// working directory if it's unset.
+pub fn archive_path(name: &str, format: ArchiveFormat, to: Option<&Path>) -> PathBuf {
+    let file_name = format!("{}.{}", name.replace('/', ""), format.extension());
+    match to {
+        Some(to) if to.is_dir() => to.join(file_name),
+        Some(to) => to.to_path_buf(),
+        None => PathBuf::from(file_name),
+    }
+}
+
+/// Packages the files of `dir` (as produced by [`download_pool`]) into an
+/// archive at `out`, in pool order (see [`archive::entries`]). Only
+/// meaningful for pool downloads, since the index-prefixed filenames are what
+/// makes a resulting cbz/zip readable in order. zip, cbz and tar.zst archives
+/// are written in-process, deflated unless `store` is set; 7z shells out to
+/// the `7z` executable, which must be on `PATH`. epub and pdf make a book of
+/// the folder's images (see [`export`]), described by its `ComicInfo.xml` if
+/// it has one. Returns `false` (and logs the reason) if `dir` doesn't exist
+/// or has no files, or the archive can't be written.
+pub fn zip_downloads(
+    dir: &Path,
+    out: &Path,
+    format: ArchiveFormat,
+    store: bool,
+    mp: &MultiProgress,
+) -> bool {
     if !dir.exists() {
         error!(
             "Nothing to zip! The {} folder doesn't exist. Run d-pool first.",
@@ -417,47 +851,162 @@ pub fn zip_downloads(dir: &Path, name: &str, format: ArchiveFormat) -> bool {
         );
         return false;
     }
+    let entries = match archive::entries(dir) {
+        Ok(entries) if entries.is_empty() => {
+            error!("Nothing to zip! The {} folder has no files.", dir.display());
+            return false;
+        }
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read {}: {e}", dir.display());
+            return false;
+        }
+    };
 
-    let safe_name = name.replace("/", "");
-    let ok = match format {
-        ArchiveFormat::Zip => run_7z(dir, &safe_name, "zip", "zip"),
-        ArchiveFormat::SevenZip => run_7z(dir, &safe_name, "7z", "7z"),
-        ArchiveFormat::Cbz => {
-            run_7z(dir, &safe_name, "zip", "zip")
-                && fs::rename(format!("./{safe_name}.zip"), format!("./{safe_name}.cbz"))
-                    .map_err(|e| error!("Failed to rename archive to .cbz: {e}"))
-                    .is_ok()
+    let bar = mp.add(ProgressBar::new(entries.iter().map(|e| e.size).sum()));
+    bar.set_style(
+        ProgressStyle::with_template(
+            "  {msg} [{bar:25}] {binary_bytes}/{binary_total_bytes} ({binary_bytes_per_sec})",
+        )
+        .expect("Invalid progress bar template")
+        .progress_chars("=> "),
+    );
+    bar.set_message(format!("Packaging {}", out.display()));
+    let result = match format {
+        ArchiveFormat::Zip | ArchiveFormat::Cbz => archive::write_zip(&entries, out, store, &bar),
+        ArchiveFormat::TarZst => archive::write_tar_zst(&entries, out, &bar),
+        ArchiveFormat::SevenZip => run_7z(&entries, out),
+        ArchiveFormat::Epub | ArchiveFormat::Pdf => {
+            let info = comic_info::read(dir).unwrap_or_else(|| ComicInfo {
+                title: out
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                ..Default::default()
+            });
+            let pages = book_pages(&entries);
+            if format == ArchiveFormat::Epub {
+                export::write_epub(&pages, &info, out, &bar)
+            } else {
+                export::write_pdf(&pages, &info, out, &bar)
+            }
         }
     };
+    bar.finish_and_clear();
 
-    if ok {
-        info!(
-            "Packaged {} into '{safe_name}.{}'.",
-            dir.display(),
-            format.extension()
+    match result {
+        Ok(()) => {
+            info!(
+                "Packaged {} files from {} into '{}'.",
+                entries.len(),
+                dir.display(),
+                out.display()
+            );
+            true
+        }
+        Err(e) => {
+            error!("{e}");
+            false
+        }
+    }
+}
+
+/// The images among `entries`, for a book's pages. Warns about files that
+/// can't be a page, like videos.
+fn book_pages(entries: &[archive::Entry]) -> Vec<archive::Entry> {
+    let (pages, others): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .filter(|entry| entry.name != comic_info::FILE_NAME)
+        .cloned()
+        .partition(|entry| export::media_type(entry).is_some());
+    if !others.is_empty() {
+        warn!(
+            "Leaving out {} files that aren't images: {}",
+            others.len(),
+            others
+                .iter()
+                .map(|entry| entry.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
         );
     }
-    ok
+    pages
 }
 
-fn run_7z(dir: &Path, name: &str, archive_type: &str, ext: &str) -> bool {
+fn run_7z(entries: &[archive::Entry], out: &Path) -> Result<(), String> {
+    // `7z a` adds to an existing archive rather than replacing it.
+    if let Err(e) = fs::remove_file(out)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        return Err(format!("Failed to replace {}: {e}", out.display()));
+    }
     let mut cmd = Command::new("7z");
     cmd.arg("a")
-        .arg(format!("-t{archive_type}"))
-        .arg(format!("{name}.{ext}"))
-        .arg(dir.join("*"));
+        .arg("-t7z")
+        .arg(out)
+        .args(entries.iter().map(|entry| &entry.path));
 
     match cmd.output() {
-        Ok(out) if out.status.success() => true,
-        Ok(out) => {
-            error!(
-                "7z exited with an error: {}",
-                String::from_utf8_lossy(&out.stderr)
+        Ok(out) if out.status.success() => Ok(()),
+        Ok(out) => Err(format!(
+            "7z exited with an error: {}",
+            String::from_utf8_lossy(&out.stderr)
+        )),
+        Err(e) => Err(format!(
+            "Failed to run 7z (is it installed and on PATH?): {e}"
+        )),
+    }
+}
+
+/// Writes a gallery of the files under `dir` (see [`gallery`]), with tags
+/// and ratings from the run manifests at `manifests`, linking posts on
+/// `api_source`. Returns `false` (and logs the reason) if `dir` doesn't
+/// exist, a manifest can't be read, or the gallery can't be written.
+pub fn make_gallery(
+    dir: &Path,
+    manifests: &[PathBuf],
+    api_source: &str,
+    mp: &MultiProgress,
+) -> bool {
+    if !dir.exists() {
+        error!(
+            "Nothing to show! The {} folder doesn't exist.",
+            dir.display()
+        );
+        return false;
+    }
+    let mut records = Vec::new();
+    for path in manifests {
+        match manifest::read(path) {
+            Ok(read) => records.extend(read),
+            Err(e) => {
+                error!("{e}");
+                return false;
+            }
+        }
+    }
+
+    let bar = mp.add(ProgressBar::new(0));
+    bar.set_style(
+        ProgressStyle::with_template("  {msg} [{bar:25}] {pos}/{len}")
+            .expect("Invalid progress bar template")
+            .progress_chars("=> "),
+    );
+    bar.set_message("Making thumbnails");
+    let result = gallery::write(dir, &records, api_source, &bar);
+    bar.finish_and_clear();
+
+    match result {
+        Ok(count) => {
+            info!(
+                "Wrote a gallery of {count} files to '{}'.",
+                dir.join(gallery::INDEX).display()
             );
-            false
+            true
         }
         Err(e) => {
-            error!("Failed to run 7z (is it installed and on PATH?): {e}");
+            error!("{e}");
             false
         }
     }
@@ -5,5 +5,256 @@ fn zip_downloads_fails_when_dir_missing() {
     let base = tempfile::tempdir().expect("tempdir");
     let missing = base.path().join("does-not-exist");
 
-    assert!(!zip_downloads(&missing, "archive", ArchiveFormat::Zip));
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    assert!(!zip_downloads(
+        &missing,
+        &base.path().join("archive.zip"),
+        ArchiveFormat::Zip,
+        false,
+        &mp
+    ));
+}
+
+#[test]
+fn archive_path_goes_into_a_directory_or_to_a_file() {
+    let base = tempfile::tempdir().expect("tempdir");
+    assert_eq!(
+        archive_path("Cloud/Jumping", ArchiveFormat::Cbz, None),
+        PathBuf::from("CloudJumping.cbz")
+    );
+    assert_eq!(
+        archive_path("pool", ArchiveFormat::TarZst, Some(base.path())),
+        base.path().join("pool.tar.zst")
+    );
+    let file = base.path().join("custom.zip");
+    assert_eq!(archive_path("pool", ArchiveFormat::Zip, Some(&file)), file);
+}
+
+fn pool(id: u64, name: &str) -> PoolData {
+    PoolData {
+        id,
+        name: name.into(),
+        description: None,
+        post_ids: vec![],
+        post_count: 0,
+        category: "series".into(),
+        is_active: true,
+    }
+}
+
+#[test]
+fn pick_pool_prefers_exact_name_then_single_match() {
+    let matches = vec![pool(1, "Cloud_Jumping_2"), pool(2, "Cloud_Jumping")];
+    assert_eq!(pick_pool("cloud jumping", matches).expect("exact").id, 2);
+    assert_eq!(
+        pick_pool("jump", vec![pool(3, "Cloud_Jumping")])
+            .expect("single")
+            .id,
+        3
+    );
+}
+
+#[test]
+fn pick_pool_rejects_ambiguous_or_missing_names() {
+    let matches = vec![pool(1, "Cloud_Jumping_1"), pool(2, "Cloud_Jumping_2")];
+    let error = pick_pool("cloud", matches).expect_err("ambiguous");
+    assert!(error.contains("matches 2 pools"));
+    assert!(pick_pool("cloud", vec![]).is_err());
+}
+
+fn context() -> CliContext {
+    CliContext {
+        verbose: false,
+        nsfw: false,
+        lower_quality: false,
+        pages: 1,
+        file_bars: false,
+        num_threads: 1,
+        retries: 0,
+        duplicate_index: None,
+        cancel: None,
+        rate_limit: None,
+        budget: None,
+        disk: None,
+        postprocess: None,
+        progress: None,
+    }
+}
+
+#[test]
+fn pools_past_the_budget_stay_remaining() {
+    let budget = crate::budget::Budget::new(None, Some(0));
+    assert!(!budget.try_reserve(None));
+    let context = CliContext {
+        budget: Some(Arc::new(budget)),
+        ..context()
+    };
+    let mut first = pool(1, "First");
+    first.post_ids = vec![10, 11];
+    let mut second = pool(2, "Second");
+    second.post_ids = vec![20];
+    let base = tempfile::tempdir().expect("tempdir");
+
+    let stats = crate::runtime::block_on(download_pools(
+        &context,
+        &Login::default(),
+        &[first, second],
+        None,
+        &MultiProgress::new(),
+        base.path(),
+        None,
+    ));
+    assert_eq!((stats.remaining, stats.total), (3, 3));
+    let ids = stats
+        .records
+        .iter()
+        .map(|record| record.post_id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [10, 11, 20]);
+    assert!(stats.records.iter().all(|record| {
+        record.status == RecordStatus::Remaining
+            && record.error.as_ref().map(|e| e.kind) == Some(FailureKind::BudgetReached)
+    }));
+}
+
+#[test]
+fn pools_not_started_before_cancelling_are_cancelled() {
+    let cancel = tokio_util::sync::CancellationToken::new();
+    cancel.cancel();
+    let context = CliContext {
+        cancel: Some(cancel),
+        ..context()
+    };
+    let mut first = pool(1, "First");
+    first.post_ids = vec![10];
+    let mut second = pool(2, "Second");
+    second.post_ids = vec![20, 21];
+    let base = tempfile::tempdir().expect("tempdir");
+
+    let stats = crate::runtime::block_on(download_pools(
+        &context,
+        &Login::default(),
+        &[first, second],
+        None,
+        &MultiProgress::new(),
+        base.path(),
+        None,
+    ));
+    assert_eq!((stats.cancelled, stats.total), (3, 3));
+    assert!(
+        stats
+            .records
+            .iter()
+            .all(|record| record.status == RecordStatus::Cancelled)
+    );
+}
+
+fn post(id: u64, md5: &str) -> Post {
+    use crate::type_defs::api_defs::{Alternates, File, Sample, Tags};
+    Post {
+        id,
+        file: File {
+            ext: "jpg".into(),
+            url: Some(format!("http://example.invalid/{id}.jpg")),
+            md5: Some(md5.into()),
+            size: None,
+            width: None,
+            height: None,
+        },
+        tags: Tags {
+            artist: vec!["someartist".into()],
+            character: vec![],
+            species: vec![],
+            general: vec![],
+        },
+        sample: Sample {
+            has: false,
+            url: None,
+            alternates: Alternates {
+                lower_quality: None,
+            },
+        },
+        description: None,
+        flags: Default::default(),
+        rating: "s".into(),
+    }
+}
+
+#[test]
+fn archived_pages_are_not_left_out_as_duplicates() {
+    let base = tempfile::tempdir().expect("tempdir");
+    let index =
+        crate::duplicate::DuplicateIndex::load(&base.path().join("index.json")).expect("index");
+    index.insert("abc", "0001-someartist-1.jpg");
+    // With the budget spent, the page is left for later instead of
+    // reaching the network.
+    let budget = crate::budget::Budget::new(None, Some(0));
+    assert!(!budget.try_reserve(None));
+    let context = CliContext {
+        duplicate_index: Some(Arc::new(index)),
+        budget: Some(Arc::new(budget)),
+        ..context()
+    };
+    let staging = base.path().join("pool.cbz.part");
+    ensure_dl_dir(&staging);
+    let archive = archive::PoolArchive::open(&staging.join(PARTIAL_ARCHIVE)).expect("archive");
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    let (stats, archive, result) = crate::runtime::block_on(archive_pages(
+        &context,
+        &Login::default(),
+        &get_client(),
+        &mp,
+        &staging,
+        archive,
+        vec![(1, post(1, "abc"))],
+    ));
+    result.expect("appended");
+    assert_eq!(stats.records[0].status, RecordStatus::Remaining);
+    assert_eq!(archive.next_index(), 1);
+    assert!(!archive.is_complete(1));
+    assert_eq!(
+        context.duplicate_index.as_ref().unwrap().contains("abc"),
+        Some("0001-someartist-1.jpg".into())
+    );
+}
+
+#[test]
+fn only_pages_that_failed_for_good_are_left_out() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let record = |status, kind: Option<FailureKind>| DownloadRecord {
+        post_id: 1,
+        source_url: None,
+        md5: None,
+        artist: String::new(),
+        rating: String::new(),
+        tags: vec![],
+        extension: "jpg".into(),
+        local_filename: Some("0001-someartist-1.jpg".into()),
+        status,
+        bytes: 0,
+        error: kind.map(|kind| DownloadError::new(kind, "")),
+        derived: None,
+    };
+    assert_eq!(
+        archived_page(&record(RecordStatus::Duplicate, None), dir.path()),
+        None
+    );
+    assert_eq!(
+        archived_page(&record(RecordStatus::Skipped, None), dir.path()),
+        None
+    );
+    assert_eq!(
+        archived_page(
+            &record(RecordStatus::Failed, Some(FailureKind::Deleted)),
+            dir.path()
+        ),
+        Some(None)
+    );
+    std::fs::write(dir.path().join("0001-someartist-1.jpg"), b"page").expect("write");
+    assert_eq!(
+        archived_page(&record(RecordStatus::Duplicate, None), dir.path()),
+        Some(Some(dir.path().join("0001-someartist-1.jpg")))
+    );
 }
@@ -14,10 +14,14 @@ pub struct Config {
     pub global: GlobalConfig,
     #[serde(rename = "d-favs")]
     pub d_favs: FavouritesConfig,
+    #[serde(rename = "d-uploads")]
+    pub d_uploads: UploadsConfig,
     #[serde(rename = "d-tags")]
     pub d_tags: TagsConfig,
     #[serde(rename = "d-pool")]
     pub d_pool: PoolConfig,
+    #[serde(rename = "d-set")]
+    pub d_set: SetConfig,
     pub zip: ZipConfig,
     pub presets: HashMap<String, PresetConfig>,
 }
@@ -33,6 +37,9 @@ pub struct GlobalConfig {
     pub num_threads: Option<usize>,
     pub dir: Option<String>,
     pub track_file: Option<PathBuf>,
+    /// A command printing the username and API key on two lines, used when
+    /// no other credentials are stored (see [`crate::credentials`]).
+    pub credential_command: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -44,6 +51,15 @@ pub struct FavouritesConfig {
     pub tags: Option<String>,
 }
 
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadsConfig {
+    pub username: Option<String>,
+    pub count: Option<u32>,
+    pub random: Option<bool>,
+    pub tags: Option<String>,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct TagsConfig {
@@ -56,6 +72,14 @@ pub struct TagsConfig {
 #[serde(default)]
 pub struct PoolConfig {
     pub pool_id: Option<u64>,
+    pub series: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SetConfig {
+    pub set: Option<String>,
+    pub indexed: Option<bool>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -73,6 +97,8 @@ pub struct PresetConfig {
     pub fav_tags: Option<String>,
     pub username: Option<String>,
     pub pool_id: Option<u64>,
+    pub set: Option<String>,
+    pub indexed: Option<bool>,
     pub count: Option<u32>,
     pub pages: Option<i64>,
     pub random: Option<bool>,
@@ -80,6 +106,8 @@ pub struct PresetConfig {
     pub nsfw: Option<bool>,
     pub dir: Option<String>,
     pub track_file: Option<PathBuf>,
+    /// The named account to sign in with (see [`crate::credentials`]).
+    pub account: Option<String>,
 }
 
 pub fn path() -> Result<PathBuf, String> {
@@ -117,12 +145,16 @@ pub fn load(path: &std::path::Path) -> Result<Config, String> {
 /// default. The file is regenerated from the template, so any extra keys or
 /// comments added by hand are not preserved.
 pub fn save(config: &Config) -> Result<(), String> {
-    let path = path()?;
+    save_to(&path()?, config)
+}
+
+/// [`save`] to the configuration file at `path`.
+pub fn save_to(path: &std::path::Path, config: &Config) -> Result<(), String> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .map_err(|e| format!("Could not create config directory: {e}"))?;
     }
-    fs::write(&path, render(config))
+    fs::write(path, render(config))
         .map_err(|e| format!("Could not write config {}: {e}", path.display()))
 }
 
@@ -158,6 +190,12 @@ fn render(config: &Config) -> String {
             .map(|p| p.to_string_lossy().to_string()),
         "\"./seen.txt\"",
     );
+    str_key(
+        &mut out,
+        "credential_command",
+        config.global.credential_command.clone(),
+        "\"pass show e621\" # Prints the username, then the API key",
+    );
     out.push('\n');
 
     out.push_str("[d-favs]\n");
@@ -177,6 +215,23 @@ fn render(config: &Config) -> String {
     str_key(&mut out, "tags", config.d_favs.tags.clone(), "\"\"");
     out.push('\n');
 
+    out.push_str("[d-uploads]\n");
+    str_key(
+        &mut out,
+        "username",
+        config.d_uploads.username.clone(),
+        "\"someuser\"",
+    );
+    int_key(
+        &mut out,
+        "count",
+        config.d_uploads.count.map(|v| v as i64),
+        "5",
+    );
+    bool_key(&mut out, "random", config.d_uploads.random, "false");
+    str_key(&mut out, "tags", config.d_uploads.tags.clone(), "\"\"");
+    out.push('\n');
+
     out.push_str("[d-tags]\n");
     str_key(&mut out, "tags", config.d_tags.tags.clone(), "\"scalie\"");
     int_key(
@@ -195,6 +250,12 @@ fn render(config: &Config) -> String {
         config.d_pool.pool_id.map(|v| v as i64),
         "22364",
     );
+    bool_key(&mut out, "series", config.d_pool.series, "false");
+    out.push('\n');
+
+    out.push_str("[d-set]\n");
+    str_key(&mut out, "set", config.d_set.set.clone(), "\"my_set\"");
+    bool_key(&mut out, "indexed", config.d_set.indexed, "false");
     out.push('\n');
 
     out.push_str("[zip]\n");
@@ -208,7 +269,7 @@ fn render(config: &Config) -> String {
         &mut out,
         "format",
         config.zip.format.clone(),
-        "\"zip\" # Options: \"zip\", \"7z\", \"cbz\"",
+        "\"zip\" # Options: \"zip\", \"7z\", \"cbz\", \"tar.zst\", \"epub\", \"pdf\"",
     );
     out.push('\n');
 
@@ -229,6 +290,8 @@ fn render(config: &Config) -> String {
             preset.pool_id.map(|value| value as i64),
             "22364",
         );
+        str_key(&mut out, "set", preset.set.clone(), "\"my_set\"");
+        bool_key(&mut out, "indexed", preset.indexed, "false");
         int_key(&mut out, "count", preset.count.map(|v| v as i64), "5");
         int_key(&mut out, "pages", preset.pages, "1");
         bool_key(&mut out, "random", preset.random, "false");
@@ -244,6 +307,7 @@ fn render(config: &Config) -> String {
                 .map(|p| p.to_string_lossy().to_string()),
             "\"./seen.txt\"",
         );
+        str_key(&mut out, "account", preset.account.clone(), "\"team\"");
         out.push('\n');
     }
 
@@ -340,6 +404,7 @@ const TEMPLATE: &str = r#"# e-cli configuration
 # num_threads = 5
 # dir = "./dl/"
 # track_file = "./seen.txt"
+# credential_command = "pass show e621" # Prints the username, then the API key
 
 [d-favs]
 # username = "someuser"
@@ -347,6 +412,12 @@ const TEMPLATE: &str = r#"# e-cli configuration
 # random = false
 # tags = ""
 
+[d-uploads]
+# username = "someuser"
+# count = 5
+# random = false
+# tags = ""
+
 [d-tags]
 # tags = "scalie"
 # count = 5
@@ -354,16 +425,28 @@ const TEMPLATE: &str = r#"# e-cli configuration
 
 [d-pool]
 # pool_id = 22364
+# series = false
+
+[d-set]
+# set = "my_set" # A set ID or shortname
+# indexed = false
 
 [zip]
 # name = "Cloudjumping"
-# format = "zip" # Options: "zip", "7z", "cbz"
+# format = "zip" # Options: "zip", "7z", "cbz", "tar.zst", "epub", "pdf"
 
 # Reusable tag searches can be added as [presets.name] sections.
 # [presets.art]
 # tags = "dragon"
 # count = 25
 # pages = 1
+# account = "team" # A named account from credentials.toml
+#
+# Set source = "set" to download a post set instead.
+# [presets.collection]
+# source = "set"
+# set = "my_set"
+# indexed = true
 "#;
 
 #[cfg(test)]
@@ -391,6 +474,10 @@ mod tests {
                 [d-pool]
                 pool_id = 123
 
+                [d-set]
+                set = "my_set"
+                indexed = true
+
                 [zip]
                 name = "archive"
                 format = "cbz"
@@ -411,6 +498,8 @@ mod tests {
         assert_eq!(config.d_favs.count, Some(25));
         assert_eq!(config.d_tags.tags.as_deref(), Some("dragon"));
         assert_eq!(config.d_pool.pool_id, Some(123));
+        assert_eq!(config.d_set.set.as_deref(), Some("my_set"));
+        assert_eq!(config.d_set.indexed, Some(true));
         assert_eq!(config.zip.name.as_deref(), Some("archive"));
         assert_eq!(config.zip.format.as_deref(), Some("cbz"));
         assert_eq!(config.presets["art"].tags.as_deref(), Some("dragon"));
@@ -428,7 +517,9 @@ mod tests {
 
     #[test]
     fn template_lists_archive_format_values() {
-        assert!(TEMPLATE.contains("Options: \"zip\", \"7z\", \"cbz\""));
+        assert!(
+            TEMPLATE.contains("Options: \"zip\", \"7z\", \"cbz\", \"tar.zst\", \"epub\", \"pdf\"")
+        );
     }
 
     #[test]
@@ -437,8 +528,11 @@ mod tests {
         assert!(out.contains("# verbose = false"));
         assert!(out.contains("# nsfw = false"));
         assert!(out.contains("# dir = \"./dl/\""));
+        assert!(out.contains("# credential_command = \"pass show e621\""));
         assert!(out.contains("# tags = \"\""));
-        assert!(out.contains("# format = \"zip\" # Options: \"zip\", \"7z\", \"cbz\""));
+        assert!(out.contains(
+            "# format = \"zip\" # Options: \"zip\", \"7z\", \"cbz\", \"tar.zst\", \"epub\", \"pdf\""
+        ));
         assert!(!out.contains("\nnsfw = false"));
     }
 
@@ -0,0 +1,227 @@
+//! Stored API credentials, so runs don't have to ask for them. They're looked
+//! up in order from the `E_CLI_USERNAME`/`E_CLI_API_KEY` environment
+//! variables, a `credentials.toml` next to the config file (which must only
+//! be readable by its owner), and the config's `credential_command`.
+//!
+//! Besides the default account, `credentials.toml` can hold named accounts
+//! in `[accounts.<name>]` tables, selected with `--account` or a preset's
+//! `account` key. Named accounts aren't read from the environment.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Login;
+use crate::config::{self, GlobalConfig};
+
+pub const USERNAME_VAR: &str = "E_CLI_USERNAME";
+pub const API_KEY_VAR: &str = "E_CLI_API_KEY";
+/// Set for `credential_command` to the account asked for, if it's a named one.
+pub const ACCOUNT_VAR: &str = "E_CLI_ACCOUNT";
+const CREDENTIALS_FILE: &str = "credentials.toml";
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredCredentials {
+    username: String,
+    api_key: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CredentialsFile {
+    #[serde(flatten)]
+    default: Option<StoredCredentials>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    accounts: BTreeMap<String, StoredCredentials>,
+}
+
+impl From<StoredCredentials> for Login {
+    fn from(stored: StoredCredentials) -> Self {
+        Login {
+            username: stored.username,
+            api_key: stored.api_key,
+        }
+    }
+}
+
+/// The credentials file, next to the config file.
+pub fn path() -> Result<PathBuf, String> {
+    Ok(config::path()?.with_file_name(CREDENTIALS_FILE))
+}
+
+/// Looks up stored credentials for `account` (the default account if
+/// `None`), in the order described in the module docs. Returns `None` if
+/// the default account isn't set up, and an error if a source is set up but
+/// unusable or a named account isn't found.
+pub fn load(global: &GlobalConfig, account: Option<&str>) -> Result<Option<Login>, String> {
+    if account.is_none()
+        && let Some(login) = from_env(env::var(USERNAME_VAR).ok(), env::var(API_KEY_VAR).ok())?
+    {
+        return Ok(Some(login));
+    }
+    let path = path()?;
+    if let Some(login) = read(&path, account)? {
+        return Ok(Some(login));
+    }
+    match (&global.credential_command, account) {
+        (Some(command), account) => run_command(command, account).map(Some),
+        (None, Some(account)) => Err(format!(
+            "No account named '{account}' in {}. Add it with `e-cli login --account {account}`.",
+            path.display()
+        )),
+        (None, None) => Ok(None),
+    }
+}
+
+fn from_env(username: Option<String>, api_key: Option<String>) -> Result<Option<Login>, String> {
+    match (username, api_key) {
+        (Some(username), Some(api_key)) => Ok(Some(Login { username, api_key })),
+        (None, None) => Ok(None),
+        _ => Err(format!(
+            "Set both {USERNAME_VAR} and {API_KEY_VAR} to log in from the environment."
+        )),
+    }
+}
+
+/// Reads `account` (the default account if `None`) from the credentials
+/// file at `path`, if both exist. Refuses a file other users can access.
+pub fn read(path: &Path, account: Option<&str>) -> Result<Option<Login>, String> {
+    let mut file = read_file(path)?;
+    let stored = match account {
+        Some(account) => file.accounts.remove(account),
+        None => file.default,
+    };
+    Ok(stored.map(Login::from))
+}
+
+fn read_file(path: &Path) -> Result<CredentialsFile, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(CredentialsFile::default()),
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+    };
+    check_permissions(path)?;
+    toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {e}", path.display()))
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} can be read by other users (mode {:o}); run `chmod 600 {}` to use it.",
+            path.display(),
+            mode & 0o777,
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+/// Stores `login` as `account` (the default account if `None`) in the
+/// credentials file at `path`, keeping the other accounts in it. The file
+/// is readable only by the current user.
+pub fn save(path: &Path, account: Option<&str>, login: &Login) -> Result<(), String> {
+    let mut file = read_file(path)?;
+    let stored = StoredCredentials {
+        username: login.username.clone(),
+        api_key: login.api_key.clone(),
+    };
+    match account {
+        Some(account) => {
+            file.accounts.insert(account.to_owned(), stored);
+        }
+        None => file.default = Some(stored),
+    }
+    write_file(path, &file)
+}
+
+/// Deletes `account` (the default account if `None`) from the credentials
+/// file at `path`, and the file once it's empty. Returns `false` if there
+/// was no such account.
+pub fn remove(path: &Path, account: Option<&str>) -> Result<bool, String> {
+    let mut file = read_file(path)?;
+    let removed = match account {
+        Some(account) => file.accounts.remove(account).is_some(),
+        None => file.default.take().is_some(),
+    };
+    if !removed {
+        return Ok(false);
+    }
+    if file.default.is_none() && file.accounts.is_empty() {
+        fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+    } else {
+        write_file(path, &file)?;
+    }
+    Ok(true)
+}
+
+fn write_file(path: &Path, file: &CredentialsFile) -> Result<(), String> {
+    let content =
+        toml::to_string(file).map_err(|e| format!("Failed to serialize credentials: {e}"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Could not create {}: {e}", parent.display()))?;
+    }
+    crate::state::write_private(path, content)
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+/// Runs `command` through the shell, with [`ACCOUNT_VAR`] set to `account`
+/// if it's a named one. It should print the username on the first line and
+/// the API key on the second.
+fn run_command(command: &str, account: Option<&str>) -> Result<Login, String> {
+    #[cfg(windows)]
+    let mut shell = Command::new("cmd");
+    #[cfg(windows)]
+    shell.args(["/C", command]);
+    #[cfg(not(windows))]
+    let mut shell = Command::new("sh");
+    #[cfg(not(windows))]
+    shell.args(["-c", command]);
+    if let Some(account) = account {
+        shell.env(ACCOUNT_VAR, account);
+    }
+
+    let output = shell
+        .output()
+        .map_err(|e| format!("Could not run credential_command: {e}"))?;
+    if !output.status.success() {
+        return Err(format!("credential_command exited with {}.", output.status));
+    }
+    parse_command_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_command_output(output: &str) -> Result<Login, String> {
+    let mut lines = output.lines().map(str::trim);
+    match (lines.next(), lines.next()) {
+        (Some(username), Some(api_key)) if !username.is_empty() && !api_key.is_empty() => {
+            Ok(Login {
+                username: username.to_owned(),
+                api_key: api_key.to_owned(),
+            })
+        }
+        _ => Err(
+            "credential_command must print the username on the first line and the API key on the second."
+                .to_owned(),
+        ),
+    }
+}
+
+#[cfg(test)]
+#[path = "credentials_tests.rs"]
+mod tests;
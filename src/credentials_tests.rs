@@ -0,0 +1,111 @@
+use super::*;
+
+#[test]
+fn environment_needs_both_variables() {
+    let login = from_env(Some("someuser".into()), Some("key".into()))
+        .expect("both set")
+        .expect("login");
+    assert_eq!(login.username, "someuser");
+    assert!(from_env(None, None).expect("neither set").is_none());
+    let Err(error) = from_env(Some("someuser".into()), None) else {
+        panic!("only the username is set");
+    };
+    assert!(error.contains(API_KEY_VAR), "{error}");
+}
+
+#[test]
+fn saved_credentials_read_back() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("e-cli").join(CREDENTIALS_FILE);
+    assert!(read(&path, None).expect("missing file").is_none());
+
+    let login = Login {
+        username: "someuser".into(),
+        api_key: "secret".into(),
+    };
+    save(&path, None, &login).expect("save");
+    let read_back = read(&path, None).expect("read").expect("login");
+    assert_eq!(read_back.username, "someuser");
+    assert_eq!(read_back.api_key, "secret");
+
+    assert!(remove(&path, None).expect("remove"));
+    assert!(!remove(&path, None).expect("remove again"));
+    assert!(!path.exists());
+}
+
+#[test]
+fn named_accounts_are_kept_apart() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join(CREDENTIALS_FILE);
+    let login = |username: &str| Login {
+        username: username.into(),
+        api_key: format!("{username}-key"),
+    };
+    save(&path, None, &login("me")).expect("save default");
+    save(&path, Some("team"), &login("archivist")).expect("save team");
+
+    let content = fs::read_to_string(&path).expect("read file");
+    assert!(content.contains("[accounts.team]"), "{content}");
+    assert_eq!(
+        read(&path, None).expect("read").expect("default").username,
+        "me"
+    );
+    assert_eq!(
+        read(&path, Some("team"))
+            .expect("read")
+            .expect("team")
+            .api_key,
+        "archivist-key"
+    );
+    assert!(read(&path, Some("other")).expect("read").is_none());
+
+    assert!(remove(&path, None).expect("remove default"));
+    assert!(read(&path, None).expect("read").is_none());
+    assert!(read(&path, Some("team")).expect("read").is_some());
+    assert!(remove(&path, Some("team")).expect("remove team"));
+    assert!(!path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn credentials_readable_by_others_are_refused() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join(CREDENTIALS_FILE);
+    save(
+        &path,
+        None,
+        &Login {
+            username: "someuser".into(),
+            api_key: "secret".into(),
+        },
+    )
+    .expect("save");
+    let mode = fs::metadata(&path).expect("metadata").permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).expect("chmod");
+    let Err(error) = read(&path, None) else {
+        panic!("credentials readable by others were used");
+    };
+    assert!(error.contains("chmod 600"), "{error}");
+}
+
+#[test]
+fn credential_command_prints_username_then_key() {
+    let login = parse_command_output("someuser\nsecret\n").expect("parse");
+    assert_eq!(login.username, "someuser");
+    assert_eq!(login.api_key, "secret");
+    assert!(parse_command_output("secret\n").is_err());
+
+    #[cfg(unix)]
+    {
+        let login = run_command("printf 'someuser\\nsecret\\n'", None).expect("run");
+        assert_eq!(login.api_key, "secret");
+        let login = run_command("printf '%s\\nsecret\\n' \"$E_CLI_ACCOUNT\"", Some("team"))
+            .expect("run for account");
+        assert_eq!(login.username, "team");
+        assert!(run_command("exit 1", None).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! `e-cli daemon`: a JSON control API for frontends such as the companion
+//! GUI, so they can drive downloads without reimplementing the CLI's
+//! orchestration. It starts, lists and cancels [`crate::jobs`] (the same
+//! `/api/jobs` as [`crate::server`]), streams their progress as server-sent
+//! events, and reads and writes `config.toml`.
+//!
+//! - `GET /api/jobs`, `POST /api/jobs` with a [`JobRequest`], and
+//!   `DELETE /api/jobs/{id}` to cancel one.
+//! - `GET /api/events`: a `job` event with the [`crate::jobs::JobInfo`] of
+//!   every job when connecting, and again whenever one changes, and a
+//!   `progress` event with every [`crate::jobs::JobProgress`] of the running
+//!   job.
+//! - `GET /api/config` and `PUT /api/config` with a whole [`Config`], less
+//!   the settings that pick what runs or which account signs in (see
+//!   [`redact`]).
+//!
+//! Every request needs an `Authorization: Bearer <token>` header. Unless
+//! one is given, the token is made anew each start and written to
+//! [`TOKEN_FILE`] next to `config.toml`, readable only by the current user.
+//! Like `serve`, it only answers requests addressed to the host it listens
+//! on, and it only listens on another than the loopback address with a
+//! token that was given.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::config::{self, Config};
+use crate::jobs::{JobEvent, Jobs};
+use crate::server::{self, Failure};
+use crate::tracker::Tracker;
+use crate::{CliContext, Login, state};
+
+/// The file next to `config.toml` that holds the token of the running
+/// daemon, if it made one.
+pub const TOKEN_FILE: &str = "daemon-token";
+
+/// What [`run`] shares between requests.
+struct Daemon {
+    jobs: Arc<Jobs>,
+    config_path: PathBuf,
+    /// What requests must bring in their `Authorization` header.
+    token: Arc<str>,
+    /// Ends the event streams when the daemon stops, so it isn't kept
+    /// waiting on them.
+    cancel: CancellationToken,
+}
+
+type Shared = Arc<Daemon>;
+
+/// Serves the control API at `addr` until `context`'s cancellation token
+/// fires, then cancels the jobs still running and waits for them to stop.
+/// Jobs download into `dir` with `context` and `login`, recording
+/// posts in `tracker`; the configuration is read from and written to
+/// `config_path`, whose presets preset jobs run. Requests must bring
+/// `token`, or else a new one written to [`TOKEN_FILE`].
+pub async fn run(
+    addr: SocketAddr,
+    context: CliContext,
+    login: Login,
+    dir: &Path,
+    tracker: Option<Arc<Tracker>>,
+    config_path: &Path,
+    token: Option<String>,
+) -> Result<(), String> {
+    let token = match token {
+        Some(token) => token,
+        None if addr.ip().is_loopback() => {
+            let token = new_token()?;
+            let path = config_path.with_file_name(TOKEN_FILE);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Could not create {}: {e}", parent.display()))?;
+            }
+            state::write_private(&path, &token)
+                .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            info!("The API token is in {}.", path.display());
+            token
+        }
+        None => {
+            return Err(format!(
+                "Listening on {} lets others on the network reach the API; pass --token-file to set the token they need.",
+                addr.ip()
+            ));
+        }
+    };
+    let config = config::load(config_path)?;
+    let cancel = context.cancel.clone().unwrap_or_default();
+    let jobs = Jobs::new(context, login, dir.to_path_buf(), tracker, None);
+    jobs.set_config(&config);
+    let daemon = Arc::new(Daemon {
+        jobs,
+        config_path: config_path.to_path_buf(),
+        token: token.into(),
+        cancel: cancel.clone(),
+    });
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to listen on {addr}: {e}"))?;
+    let addr = listener.local_addr().unwrap_or(addr);
+    info!(
+        "Listening at http://{addr}/api/ for {} (Ctrl+C to stop).",
+        dir.display()
+    );
+    let jobs = Arc::clone(&daemon.jobs);
+    let served = axum::serve(listener, router(daemon, addr))
+        .with_graceful_shutdown(async move { cancel.cancelled().await })
+        .await
+        .map_err(|e| format!("The daemon stopped: {e}"));
+    let _ = tokio::task::spawn_blocking(move || jobs.stop()).await;
+    served
+}
+
+fn router(daemon: Shared, addr: SocketAddr) -> Router {
+    Router::new()
+        .route("/api/events", get(events))
+        .route("/api/config", get(read_config).put(write_config))
+        .with_state(daemon.clone())
+        .merge(server::job_routes(Arc::clone(&daemon.jobs)))
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(&daemon.token),
+            server::check_token,
+        ))
+        .layer(middleware::from_fn_with_state(
+            addr.ip(),
+            server::check_host,
+        ))
+}
+
+/// A random token of 32 hex-encoded bytes.
+fn new_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Failed to make an API token: {e}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Leaves out of `config` what the API doesn't read or write: the
+/// `credential_command`, which every run executes, and the accounts presets
+/// sign in with.
+fn redact(config: &mut Config) {
+    config.global.credential_command = None;
+    for preset in config.presets.values_mut() {
+        preset.account = None;
+    }
+}
+
+async fn events(
+    State(daemon): State<Shared>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // Subscribed before listing, so no change falls in between.
+    let updates = daemon.jobs.subscribe();
+    let current = stream::iter(daemon.jobs.list().into_iter().map(JobEvent::Job));
+    let changes = stream::unfold(updates, |mut updates| async move {
+        loop {
+            match updates.recv().await {
+                Ok(event) => return Some((event, updates)),
+                // A slow client misses some progress, but the next update
+                // brings it up to date.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let stream = current
+        .chain(changes)
+        .filter_map(|event| async move {
+            let (encoded, id) = match &event {
+                JobEvent::Job(info) => (Event::default().event("job").json_data(info), info.id),
+                JobEvent::Progress(progress) => (
+                    Event::default().event("progress").json_data(progress),
+                    progress.job,
+                ),
+            };
+            match encoded {
+                Ok(event) => Some(Ok(event)),
+                Err(e) => {
+                    warn!("Failed to encode an event of job {id}: {e}");
+                    None
+                }
+            }
+        })
+        .take_until(daemon.cancel.clone().cancelled_owned());
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn read_config(State(daemon): State<Shared>) -> Result<Json<Config>, Failure> {
+    let path = daemon.config_path.clone();
+    let mut config = tokio::task::spawn_blocking(move || config::load(&path))
+        .await
+        .map_err(server::internal)?
+        .map_err(server::internal)?;
+    redact(&mut config);
+    Ok(Json(config))
+}
+
+/// Replaces the configuration. New presets apply to the jobs started after
+/// it; other settings when the daemon or the CLI next starts. What
+/// [`redact`] leaves out is kept as it is in the file.
+async fn write_config(
+    State(daemon): State<Shared>,
+    Json(mut config): Json<Config>,
+) -> Result<StatusCode, Failure> {
+    let path = daemon.config_path.clone();
+    let config = tokio::task::spawn_blocking(move || {
+        let current = config::load(&path)?;
+        redact(&mut config);
+        config.global.credential_command = current.global.credential_command;
+        for (name, preset) in &mut config.presets {
+            preset.account = current
+                .presets
+                .get(name)
+                .and_then(|current| current.account.clone());
+        }
+        config::save_to(&path, &config)?;
+        Ok::<_, String>(config)
+    })
+    .await
+    .map_err(server::internal)?
+    .map_err(server::internal)?;
+    daemon.jobs.set_config(&config);
+    info!(
+        "Saved the configuration to {}.",
+        daemon.config_path.display()
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+#[path = "daemon_tests.rs"]
+mod tests;
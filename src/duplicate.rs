@@ -18,19 +18,19 @@ pub struct DuplicateIndex {
 
 impl DuplicateIndex {
     pub fn load(path: &Path) -> io::Result<Self> {
-        let entries = if path.exists() {
-            serde_json::from_str::<IndexFile>(&fs::read_to_string(path)?)
-                .map_err(io::Error::other)?
-                .entries
-        } else {
-            HashMap::new()
-        };
         Ok(Self {
             path: path.to_path_buf(),
-            entries: Mutex::new(entries),
+            entries: Mutex::new(read_entries(path)?),
         })
     }
 
+    /// Reads the index again, taking in what other runs wrote to it since
+    /// it was loaded, e.g. before rewriting it (see [`DuplicateIndex::remove`]).
+    pub fn reload(&self) -> io::Result<()> {
+        *self.entries.lock().unwrap() = read_entries(&self.path)?;
+        Ok(())
+    }
+
     pub fn contains(&self, md5: &str) -> Option<String> {
         self.entries.lock().unwrap().get(md5).cloned()
     }
@@ -49,6 +49,19 @@ impl DuplicateIndex {
         }
     }
 
+    /// Forgets the hashes recorded for `filename`, e.g. once the file is
+    /// deleted. Returns whether there were any.
+    pub fn remove(&self, filename: &str) -> io::Result<bool> {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|_, name| name != filename);
+        if entries.len() == before {
+            return Ok(false);
+        }
+        self.save_locked(&entries)?;
+        Ok(true)
+    }
+
     fn save_locked(&self, entries: &HashMap<String, String>) -> io::Result<()> {
         if let Some(parent) = self.path.parent() {
             fs::create_dir_all(parent)?;
@@ -57,8 +70,19 @@ impl DuplicateIndex {
             entries: entries.clone(),
         })
         .map_err(io::Error::other)?;
-        fs::write(&self.path, content)
+        crate::state::write_atomic(&self.path, content)
+    }
+}
+
+fn read_entries(path: &Path) -> io::Result<HashMap<String, String>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
     }
+    Ok(
+        serde_json::from_str::<IndexFile>(&fs::read_to_string(path)?)
+            .map_err(io::Error::other)?
+            .entries,
+    )
 }
 
 #[cfg(test)]
@@ -75,4 +99,18 @@ mod tests {
         let reloaded = DuplicateIndex::load(&path).expect("reload");
         assert_eq!(reloaded.contains("abc").as_deref(), Some("artist-1.jpg"));
     }
+
+    #[test]
+    fn removes_hashes_of_deleted_files() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("index.json");
+        let index = DuplicateIndex::load(&path).expect("load");
+        index.insert("abc", "artist-1.jpg");
+        index.insert("def", "artist-2.jpg");
+        assert!(index.remove("artist-1.jpg").expect("remove"));
+        assert!(!index.remove("artist-1.jpg").expect("remove again"));
+        let reloaded = DuplicateIndex::load(&path).expect("reload");
+        assert_eq!(reloaded.contains("abc"), None);
+        assert_eq!(reloaded.contains("def").as_deref(), Some("artist-2.jpg"));
+    }
 }
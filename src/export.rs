@@ -0,0 +1,469 @@
+//! EPUB and PDF exports of a downloaded pool, for e-readers that don't read
+//! cbz. Like the archives in [`crate::archive`], they're built in-process
+//! from the pool folder's files in pool order, one page per image, with the
+//! metadata from its `ComicInfo.xml` (see [`crate::comic_info`]).
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use image::{DynamicImage, ImageReader, RgbImage};
+use indicatif::ProgressBar;
+use zip::CompressionMethod;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use crate::archive::{self, Entry};
+use crate::comic_info::{ComicInfo, escape};
+
+/// The largest page side a PDF allows, in points.
+const MAX_PDF_SIDE: f32 = 14400.0;
+
+/// The EPUB media type of an image that can go on a page, by the extension
+/// of its file; `None` for anything else (videos, Flash, metadata).
+pub fn media_type(entry: &Entry) -> Option<&'static str> {
+    let extension = Path::new(&entry.name).extension()?.to_str()?;
+    match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Writes `pages` (images, see [`media_type`]) into a fixed-layout EPUB 3 at
+/// `out`: one page per image, sized to the image, with the title, artists,
+/// summary, tags and source link from `info`.
+pub fn write_epub(
+    pages: &[Entry],
+    info: &ComicInfo,
+    out: &Path,
+    progress: &ProgressBar,
+) -> Result<(), String> {
+    // Sized up front, so an unreadable image fails before anything's written.
+    let sizes = pages
+        .iter()
+        .map(|page| {
+            image::image_dimensions(&page.path)
+                .map_err(|e| format!("Failed to read {}: {e}", page.path.display()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let images = pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| {
+            let extension = Path::new(&page.name)
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_ascii_lowercase();
+            format!("images/page-{:04}.{extension}", i + 1)
+        })
+        .collect::<Vec<_>>();
+
+    archive::write_replacing(out, |file| {
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default();
+        // Readers find the mimetype by its place: first, and uncompressed.
+        zip.start_file("mimetype", stored)
+            .map_err(io::Error::other)?;
+        zip.write_all(b"application/epub+zip")?;
+        for (name, contents) in [
+            ("META-INF/container.xml", CONTAINER.to_owned()),
+            ("OEBPS/content.opf", package(pages, &images, info)),
+            ("OEBPS/nav.xhtml", nav(info)),
+        ] {
+            zip.start_file(name, deflated).map_err(io::Error::other)?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        for (i, (page, (width, height))) in pages.iter().zip(&sizes).enumerate() {
+            let number = i + 1;
+            zip.start_file(format!("OEBPS/page-{number:04}.xhtml"), deflated)
+                .map_err(io::Error::other)?;
+            zip.write_all(page_xhtml(info, number, &images[i], *width, *height).as_bytes())?;
+            zip.start_file(format!("OEBPS/{}", images[i]), stored)
+                .map_err(io::Error::other)?;
+            io::copy(&mut progress.wrap_read(File::open(&page.path)?), &mut zip)?;
+        }
+        let buffer = zip.finish().map_err(io::Error::other)?;
+        buffer.into_inner().map_err(io::IntoInnerError::into_error)
+    })
+}
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// The package document: metadata, the files in the book and their order.
+fn package(pages: &[Entry], images: &[String], info: &ComicInfo) -> String {
+    let mut metadata = String::new();
+    let identifier = if info.web.is_empty() {
+        format!("urn:e-cli:{}", info.title)
+    } else {
+        info.web.clone()
+    };
+    metadata.push_str(&format!(
+        "    <dc:identifier id=\"id\">{}</dc:identifier>\n",
+        escape(&identifier)
+    ));
+    metadata.push_str(&format!(
+        "    <dc:title>{}</dc:title>\n",
+        escape(&info.title)
+    ));
+    for artist in &info.artists {
+        metadata.push_str(&format!(
+            "    <dc:creator>{}</dc:creator>\n",
+            escape(artist)
+        ));
+    }
+    if let Some(summary) = &info.summary {
+        metadata.push_str(&format!(
+            "    <dc:description>{}</dc:description>\n",
+            escape(summary)
+        ));
+    }
+    for tag in &info.tags {
+        metadata.push_str(&format!("    <dc:subject>{}</dc:subject>\n", escape(tag)));
+    }
+    if !info.web.is_empty() {
+        metadata.push_str(&format!(
+            "    <dc:source>{}</dc:source>\n",
+            escape(&info.web)
+        ));
+    }
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    for (i, (page, image)) in pages.iter().zip(images).enumerate() {
+        let number = i + 1;
+        let cover = if number == 1 {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            "    <item id=\"page-{number:04}\" href=\"page-{number:04}.xhtml\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        manifest.push_str(&format!(
+            "    <item id=\"image-{number:04}\" href=\"{image}\" media-type=\"{}\"{cover}/>\n",
+            media_type(page).unwrap_or("application/octet-stream")
+        ));
+        spine.push_str(&format!("    <itemref idref=\"page-{number:04}\"/>\n"));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}    <dc:language>und</dc:language>
+    <meta property="dcterms:modified">1980-01-01T00:00:00Z</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:orientation">auto</meta>
+    <meta property="rendition:spread">none</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#
+    )
+}
+
+/// The table of contents, which only leads to the first page.
+fn nav(info: &ComicInfo) -> String {
+    let title = escape(&info.title);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{title}</title></head>
+<body>
+  <nav epub:type="toc"><ol><li><a href="page-0001.xhtml">{title}</a></li></ol></nav>
+</body>
+</html>
+"#
+    )
+}
+
+/// A page showing just `image`, at its size.
+fn page_xhtml(info: &ComicInfo, number: usize, image: &str, width: u32, height: u32) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+  <title>{} - {number}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: {width}px; height: {height}px; }}</style>
+</head>
+<body><img src="{image}" alt="Page {number}"/></body>
+</html>
+"#,
+        escape(&info.title)
+    )
+}
+
+/// Writes `pages` (images, see [`media_type`]) into a PDF at `out`, one page
+/// per image at its pixel size (scaled down to the largest page a PDF
+/// allows), with the title, artists, summary and tags from `info`. JPEGs are
+/// embedded as they are; other images are stored losslessly, flattened onto
+/// white. Pages are written one at a time, so a long pool doesn't have to fit
+/// in memory.
+pub fn write_pdf(
+    pages: &[Entry],
+    info: &ComicInfo,
+    out: &Path,
+    progress: &ProgressBar,
+) -> Result<(), String> {
+    archive::write_replacing(out, |file| {
+        // Objects 1 to 3 are the catalog, the page tree and the document
+        // info; each page then takes three: itself, its contents and its
+        // image.
+        let page_id = |i: usize| 4 + 3 * i;
+        let mut pdf = PdfWriter::new(BufWriter::new(file), page_id(pages.len()))?;
+        pdf.object(1, "<< /Type /Catalog /Pages 2 0 R >>")?;
+        let kids = (0..pages.len())
+            .map(|i| format!("{} 0 R", page_id(i)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        pdf.object(
+            2,
+            &format!("<< /Type /Pages /Kids [{kids}] /Count {} >>", pages.len()),
+        )?;
+        pdf.object(3, &document_info(info))?;
+
+        for (i, page) in pages.iter().enumerate() {
+            let id = page_id(i);
+            let image = PageImage::load(&page.path)?;
+            let (width, height) = page_size(image.width, image.height);
+            pdf.object(
+                id,
+                &format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width} {height}] \
+                     /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+                    id + 2,
+                    id + 1
+                ),
+            )?;
+            let contents = format!("q {width} 0 0 {height} 0 0 cm /Im0 Do Q");
+            pdf.stream(id + 1, "", contents.as_bytes())?;
+            pdf.stream(id + 2, &image.dictionary, &image.data)?;
+            progress.inc(page.size);
+        }
+        let buffer = pdf.finish()?;
+        buffer.into_inner().map_err(io::IntoInnerError::into_error)
+    })
+}
+
+/// The PDF document info dictionary for `info`.
+fn document_info(info: &ComicInfo) -> String {
+    let mut dictionary = String::from("<< /Creator (e-cli)");
+    for (key, value) in [
+        ("Title", Some(info.title.clone())),
+        ("Author", Some(info.artists.join(", "))),
+        ("Subject", info.summary.clone()),
+        ("Keywords", Some(info.tags.join(", "))),
+    ] {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            dictionary.push_str(&format!(" /{key} {}", pdf_text(&value)));
+        }
+    }
+    dictionary.push_str(" >>");
+    dictionary
+}
+
+/// `text` as a PDF text string: UTF-16, hex-encoded.
+fn pdf_text(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        hex.push_str(&format!("{unit:04X}"));
+    }
+    hex.push('>');
+    hex
+}
+
+/// The page size for an image, in points: its size in pixels, scaled down
+/// to fit [`MAX_PDF_SIDE`].
+fn page_size(width: u32, height: u32) -> (f32, f32) {
+    let (width, height) = (width as f32, height as f32);
+    let scale = (MAX_PDF_SIDE / width.max(height)).min(1.0);
+    (width * scale, height * scale)
+}
+
+/// An image ready to embed into a PDF: its stream dictionary entries and
+/// data.
+struct PageImage {
+    width: u32,
+    height: u32,
+    dictionary: String,
+    data: Vec<u8>,
+}
+
+impl PageImage {
+    fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let error = |e: image::ImageError| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        };
+
+        // PDF readers decode grayscale and RGB JPEGs themselves.
+        if let Some(components @ (1 | 3)) = jpeg_components(&bytes) {
+            let (width, height) = ImageReader::new(Cursor::new(&bytes))
+                .with_guessed_format()?
+                .into_dimensions()
+                .map_err(error)?;
+            let color_space = if components == 1 {
+                "DeviceGray"
+            } else {
+                "DeviceRGB"
+            };
+            return Ok(Self {
+                width,
+                height,
+                dictionary: image_dictionary(width, height, color_space, "DCTDecode"),
+                data: bytes,
+            });
+        }
+
+        let image = flatten(image::load_from_memory(&bytes).map_err(error)?);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(image.as_raw())?;
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            dictionary: image_dictionary(image.width(), image.height(), "DeviceRGB", "FlateDecode"),
+            data: encoder.finish()?,
+        })
+    }
+}
+
+fn image_dictionary(width: u32, height: u32, color_space: &str, filter: &str) -> String {
+    format!(
+        "/Type /XObject /Subtype /Image /Width {width} /Height {height} \
+         /ColorSpace /{color_space} /BitsPerComponent 8 /Filter /{filter}"
+    )
+}
+
+/// `image` as RGB, with any transparency blended onto white.
+pub(crate) fn flatten(image: DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend =
+            |c: u8| ((u16::from(c) * u16::from(a) + 255 * (255 - u16::from(a))) / 255) as u8;
+        image::Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// The number of color components of a JPEG, from its frame header; `None`
+/// if `bytes` isn't a JPEG.
+fn jpeg_components(bytes: &[u8]) -> Option<u8> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut i = 2;
+    while i + 3 < bytes.len() {
+        if bytes[i] != 0xFF {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        match marker {
+            // Fill bytes before a marker.
+            0xFF => i += 1,
+            // Markers without a segment.
+            0x01 | 0xD0..=0xD7 => i += 2,
+            // Start of frame, except DHT, JPG and DAC, which share the range.
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                return bytes.get(i + 9).copied();
+            }
+            _ => {
+                let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]);
+                i += 2 + usize::from(length);
+            }
+        }
+    }
+    None
+}
+
+/// Writes the objects of a PDF file in whatever order they come, keeping
+/// track of where each one starts for the cross-reference table.
+struct PdfWriter<W: Write> {
+    out: W,
+    position: u64,
+    /// Where each object starts, by object number; object 0 is reserved.
+    offsets: Vec<u64>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    /// Starts a PDF with objects numbered below `size`.
+    fn new(out: W, size: usize) -> io::Result<Self> {
+        let mut pdf = Self {
+            out,
+            position: 0,
+            offsets: vec![0; size],
+        };
+        // The binary comment tells transfer tools the file isn't text.
+        pdf.write(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(pdf)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn object(&mut self, id: usize, body: &str) -> io::Result<()> {
+        self.offsets[id] = self.position;
+        self.write(format!("{id} 0 obj\n{body}\nendobj\n").as_bytes())
+    }
+
+    /// Writes a stream object: `dictionary` holds the entries besides its
+    /// length.
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) -> io::Result<()> {
+        self.offsets[id] = self.position;
+        self.write(
+            format!(
+                "{id} 0 obj\n<< {dictionary} /Length {} >>\nstream\n",
+                data.len()
+            )
+            .as_bytes(),
+        )?;
+        self.write(data)?;
+        self.write(b"\nendstream\nendobj\n")
+    }
+
+    /// Writes the cross-reference table and trailer, and returns the output.
+    fn finish(mut self) -> io::Result<W> {
+        let xref = self.position;
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len());
+        for offset in &self.offsets[1..] {
+            table.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        table.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            self.offsets.len()
+        ));
+        self.write(table.as_bytes())?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+#[path = "export_tests.rs"]
+mod tests;
@@ -0,0 +1,147 @@
+use super::*;
+use std::io::Read;
+
+use image::{ImageFormat, Rgba, RgbaImage};
+
+/// A pool folder with a JPEG page, a transparent PNG page and a video.
+fn pool_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().expect("tempdir");
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 3, image::Rgb([200, 10, 10])))
+        .save_with_format(dir.path().join("0001-artist-1.jpg"), ImageFormat::Jpeg)
+        .expect("write jpeg");
+    RgbaImage::from_pixel(4, 5, Rgba([0, 0, 0, 0]))
+        .save_with_format(dir.path().join("0002-artist-2.png"), ImageFormat::Png)
+        .expect("write png");
+    fs::write(dir.path().join("0003-artist-3.webm"), "video").expect("write video");
+    dir
+}
+
+fn pages(dir: &Path) -> Vec<Entry> {
+    archive::entries(dir)
+        .expect("entries")
+        .into_iter()
+        .filter(|entry| media_type(entry).is_some())
+        .collect()
+}
+
+fn info() -> ComicInfo {
+    ComicInfo {
+        title: "Cloud & Sky".into(),
+        summary: Some("A comic.".into()),
+        artists: vec!["someartist".into()],
+        tags: vec!["cloud".into()],
+        web: "https://e926.net/pools/1".into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn only_images_make_pages() {
+    let dir = pool_dir();
+    let names = pages(dir.path())
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["0001-artist-1.jpg", "0002-artist-2.png"]);
+}
+
+#[test]
+fn epubs_have_a_fixed_layout_page_per_image() {
+    let dir = pool_dir();
+    let out = dir.path().join("pool.epub");
+    write_epub(&pages(dir.path()), &info(), &out, &ProgressBar::hidden()).expect("write epub");
+
+    let mut epub = zip::ZipArchive::new(File::open(&out).expect("open")).expect("read zip");
+    {
+        let mut mimetype = epub.by_index(0).expect("mimetype");
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        let mut contents = String::new();
+        mimetype.read_to_string(&mut contents).expect("read");
+        assert_eq!(contents, "application/epub+zip");
+    }
+    let mut read = |name: &str| {
+        let mut contents = String::new();
+        epub.by_name(name)
+            .expect(name)
+            .read_to_string(&mut contents)
+            .expect("read");
+        contents
+    };
+    let package = read("OEBPS/content.opf");
+    assert!(
+        package.contains("<dc:title>Cloud &amp; Sky</dc:title>"),
+        "{package}"
+    );
+    assert!(
+        package.contains("<dc:creator>someartist</dc:creator>"),
+        "{package}"
+    );
+    assert!(
+        package.contains("rendition:layout\">pre-paginated"),
+        "{package}"
+    );
+    assert!(
+        package.contains("<itemref idref=\"page-0001\"/>\n    <itemref idref=\"page-0002\"/>"),
+        "{package}"
+    );
+    assert!(
+        package.contains("href=\"images/page-0002.png\" media-type=\"image/png\""),
+        "{package}"
+    );
+    let page = read("OEBPS/page-0001.xhtml");
+    assert!(page.contains("content=\"width=2, height=3\""), "{page}");
+    assert!(page.contains("src=\"images/page-0001.jpg\""), "{page}");
+    assert!(read("META-INF/container.xml").contains("OEBPS/content.opf"));
+}
+
+#[test]
+fn pdfs_have_a_page_per_image_and_a_valid_xref() {
+    let dir = pool_dir();
+    let out = dir.path().join("pool.pdf");
+    write_pdf(&pages(dir.path()), &info(), &out, &ProgressBar::hidden()).expect("write pdf");
+
+    let pdf = fs::read(&out).expect("read");
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.starts_with("%PDF-1.4"));
+    assert!(text.contains("/Count 2"), "{text}");
+    assert!(text.contains("/MediaBox [0 0 2 3]"), "{text}");
+    assert!(text.contains("/MediaBox [0 0 4 5]"), "{text}");
+    assert!(text.contains("/Filter /DCTDecode"), "{text}");
+    assert!(text.contains("/Filter /FlateDecode"), "{text}");
+    assert!(text.contains(&format!("/Title {}", pdf_text("Cloud & Sky"))));
+
+    // Every object is where the cross-reference table says it is.
+    let start = text.rfind("startxref\n").expect("startxref") + "startxref\n".len();
+    let xref = text[start..]
+        .lines()
+        .next()
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    assert!(pdf[xref..].starts_with(b"xref\n0 10\n"));
+    let table = String::from_utf8_lossy(&pdf[xref..]);
+    for (id, line) in table.lines().skip(3).take(9).enumerate() {
+        let offset = line[..10].parse::<usize>().unwrap();
+        assert!(
+            pdf[offset..].starts_with(format!("{} 0 obj\n", id + 1).as_bytes()),
+            "object {}",
+            id + 1
+        );
+    }
+}
+
+#[test]
+fn transparent_images_are_flattened_onto_white() {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 0])));
+    assert_eq!(flatten(image).get_pixel(0, 0).0, [255, 255, 255]);
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255])));
+    assert_eq!(flatten(image).get_pixel(0, 0).0, [0, 0, 0]);
+}
+
+#[test]
+fn large_pages_are_scaled_to_fit_a_pdf() {
+    assert_eq!(page_size(800, 1200), (800.0, 1200.0));
+    assert_eq!(page_size(1000, 28800), (500.0, 14400.0));
+    assert_eq!(jpeg_components(b"not a jpeg"), None);
+}
@@ -1,13 +1,18 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{DownloadRecord, DownloadStatistics};
+use crate::{DownloadRecord, DownloadStatistics, FailureKind, RecordStatus};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailureManifest {
     pub api_source: String,
+    /// The named account the run signed in with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
     pub destination: PathBuf,
     pub lower_quality: bool,
     pub retries: u32,
@@ -23,6 +28,7 @@ impl FailureManifest {
 
     pub fn from_statistics(
         api_source: &str,
+        account: Option<&str>,
         destination: &Path,
         lower_quality: bool,
         retries: u32,
@@ -31,11 +37,17 @@ impl FailureManifest {
         let records = stats
             .records
             .iter()
-            .filter(|r| r.status == "failed")
+            .filter(|r| {
+                matches!(
+                    r.status,
+                    RecordStatus::Failed | RecordStatus::Remaining | RecordStatus::Cancelled
+                )
+            })
             .cloned()
             .collect::<Vec<_>>();
         (!records.is_empty()).then(|| Self {
             api_source: api_source.to_owned(),
+            account: account.map(str::to_owned),
             destination: destination.to_path_buf(),
             lower_quality,
             retries,
@@ -46,7 +58,139 @@ impl FailureManifest {
     pub fn save(&self, path: &Path) -> Result<(), String> {
         let content = serde_json::to_string_pretty(self)
             .map_err(|e| format!("Failed to serialize failure manifest: {e}"))?;
-        fs::write(path, content)
+        crate::state::write_atomic(path, content)
             .map_err(|e| format!("Failed to write failure manifest {}: {e}", path.display()))
     }
+
+    /// Removes and returns the records that can never succeed (see
+    /// [`FailureKind::is_permanent`]).
+    pub fn take_permanent(&mut self) -> Vec<DownloadRecord> {
+        self.take(|record| failure_kind(record).is_permanent())
+    }
+
+    /// Removes and returns the records to retry: those matching a class in
+    /// `only` (every record, if it's empty) and none in `exclude`.
+    pub fn take_selected(
+        &mut self,
+        only: &[FailureClass],
+        exclude: &[FailureClass],
+    ) -> Vec<DownloadRecord> {
+        self.take(|record| {
+            let kind = failure_kind(record);
+            (only.is_empty() || only.iter().any(|class| class.matches(kind)))
+                && !exclude.iter().any(|class| class.matches(kind))
+        })
+    }
+
+    /// Puts back the `retried` records (as taken by [`take_selected`]) that
+    /// the retry's `stats` have no record of, unchanged: the retry was
+    /// cancelled or their posts couldn't be fetched, so they're still to do.
+    ///
+    /// [`take_selected`]: Self::take_selected
+    pub fn restore_unattempted(
+        &mut self,
+        retried: Vec<DownloadRecord>,
+        stats: &DownloadStatistics,
+    ) {
+        let attempted = stats
+            .records
+            .iter()
+            .map(|record| record.post_id)
+            .collect::<HashSet<_>>();
+        self.records.extend(
+            retried
+                .into_iter()
+                .filter(|record| !attempted.contains(&record.post_id)),
+        );
+    }
+
+    fn take(&mut self, mut select: impl FnMut(&DownloadRecord) -> bool) -> Vec<DownloadRecord> {
+        let (taken, kept) = std::mem::take(&mut self.records)
+            .into_iter()
+            .partition(|record| select(record));
+        self.records = kept;
+        taken
+    }
+}
+
+/// Why `record` failed; [`FailureKind::Unknown`] if it doesn't say.
+pub fn failure_kind(record: &DownloadRecord) -> FailureKind {
+    record
+        .error
+        .as_ref()
+        .map_or(FailureKind::Unknown, |error| error.kind)
+}
+
+/// A class of failures, as given to `retry-failed --only` and `--exclude`:
+/// `http` for any HTTP status, `http-5xx` for a range of them, `http-404` for
+/// one, or a failure kind such as `timeout` or `deleted`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureClass {
+    Http,
+    /// Statuses from `100 * n` to `100 * n + 99`.
+    HttpRange(u16),
+    HttpStatus(u16),
+    Kind(FailureKind),
+}
+
+/// The names of the failure kinds other than `http`.
+const KIND_NAMES: &[(&str, FailureKind)] = &[
+    ("timeout", FailureKind::Timeout),
+    ("connection-reset", FailureKind::ConnectionReset),
+    ("io", FailureKind::Io),
+    ("md5-mismatch", FailureKind::Md5Mismatch),
+    ("deleted", FailureKind::Deleted),
+    ("blacklisted", FailureKind::Blacklisted),
+    ("budget-reached", FailureKind::BudgetReached),
+    ("low-disk-space", FailureKind::LowDiskSpace),
+    ("cancelled", FailureKind::Cancelled),
+    ("unknown", FailureKind::Unknown),
+];
+
+impl FailureClass {
+    pub fn matches(self, kind: FailureKind) -> bool {
+        match (self, kind) {
+            (FailureClass::Http, FailureKind::Http { .. }) => true,
+            (FailureClass::HttpRange(n), FailureKind::Http { status }) => status / 100 == n,
+            (FailureClass::HttpStatus(wanted), FailureKind::Http { status }) => status == wanted,
+            (FailureClass::Kind(wanted), kind) => wanted == kind,
+            _ => false,
+        }
+    }
 }
+
+impl FromStr for FailureClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase().replace('_', "-");
+        if name == "http" {
+            return Ok(FailureClass::Http);
+        }
+        if let Some(status) = name.strip_prefix("http-") {
+            if let Some(n) = status.strip_suffix("xx")
+                && let Ok(n @ 1..=5) = n.parse::<u16>()
+            {
+                return Ok(FailureClass::HttpRange(n));
+            }
+            if let Ok(status @ 100..=599) = status.parse::<u16>() {
+                return Ok(FailureClass::HttpStatus(status));
+            }
+        }
+        KIND_NAMES
+            .iter()
+            .find(|(kind_name, _)| *kind_name == name)
+            .map(|&(_, kind)| FailureClass::Kind(kind))
+            .ok_or_else(|| {
+                let names = KIND_NAMES.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                format!(
+                    "unknown failure class '{s}'; expected http, http-4xx, http-5xx, http-<status>, {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+#[path = "failure_manifest_tests.rs"]
+mod tests;
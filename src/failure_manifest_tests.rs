@@ -0,0 +1,156 @@
+use super::*;
+use crate::DownloadError;
+
+fn failed(post_id: u64, kind: FailureKind) -> DownloadRecord {
+    DownloadRecord {
+        post_id,
+        source_url: None,
+        md5: None,
+        artist: "someartist".into(),
+        rating: "s".into(),
+        tags: vec!["someartist".into()],
+        extension: "png".into(),
+        local_filename: None,
+        status: RecordStatus::Failed,
+        bytes: 0,
+        error: Some(DownloadError::new(kind, "failed")),
+        derived: None,
+    }
+}
+
+fn manifest(records: Vec<DownloadRecord>) -> FailureManifest {
+    FailureManifest {
+        api_source: "e926.net".into(),
+        account: None,
+        destination: PathBuf::from("dl"),
+        lower_quality: false,
+        retries: 3,
+        records,
+    }
+}
+
+#[test]
+fn failure_classes_parse_and_match() {
+    let http_503 = FailureKind::Http { status: 503 };
+    let http_404 = FailureKind::Http { status: 404 };
+
+    let server = "http-5xx".parse::<FailureClass>().expect("http-5xx");
+    assert!(server.matches(http_503));
+    assert!(!server.matches(http_404));
+    assert!("http".parse::<FailureClass>().unwrap().matches(http_404));
+    assert!(
+        "HTTP-404"
+            .parse::<FailureClass>()
+            .unwrap()
+            .matches(http_404)
+    );
+    assert!(
+        "connection_reset"
+            .parse::<FailureClass>()
+            .unwrap()
+            .matches(FailureKind::ConnectionReset)
+    );
+    assert_eq!(
+        "deleted".parse::<FailureClass>(),
+        Ok(FailureClass::Kind(FailureKind::Deleted))
+    );
+
+    for bad in ["http-9xx", "http-42", "gone"] {
+        let error = bad.parse::<FailureClass>().expect_err(bad);
+        assert!(error.contains("expected http"), "{error}");
+    }
+}
+
+#[test]
+fn retries_are_selected_by_class_and_permanent_failures_taken_out() {
+    let mut manifest = manifest(vec![
+        failed(1, FailureKind::Http { status: 503 }),
+        failed(2, FailureKind::Deleted),
+        failed(3, FailureKind::Timeout),
+        failed(4, FailureKind::Http { status: 404 }),
+        failed(5, FailureKind::Http { status: 502 }),
+    ]);
+
+    let permanent = manifest.take_permanent();
+    assert_eq!(
+        permanent.iter().map(|r| r.post_id).collect::<Vec<_>>(),
+        vec![2, 4]
+    );
+
+    let only = [FailureClass::Http];
+    let exclude = [FailureClass::HttpStatus(502)];
+    let selected = manifest.take_selected(&only, &exclude);
+    assert_eq!(
+        selected.iter().map(|r| r.post_id).collect::<Vec<_>>(),
+        vec![1]
+    );
+    assert_eq!(
+        manifest
+            .records
+            .iter()
+            .map(|r| r.post_id)
+            .collect::<Vec<_>>(),
+        vec![3, 5]
+    );
+
+    assert_eq!(manifest.take_selected(&[], &[]).len(), 2);
+    assert!(manifest.records.is_empty());
+}
+
+#[test]
+fn manifests_with_unclassified_errors_still_load() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("failed.json");
+    fs::write(
+        &path,
+        r#"{"api_source": "e926.net", "destination": "dl", "lower_quality": false, "retries": 3,
+            "records": [
+                {"post_id": 1, "source_url": null, "md5": null, "artist": "a", "extension": "png",
+                 "local_filename": null, "status": "failed", "bytes": 0, "error": "download failed"},
+                {"post_id": 2, "source_url": null, "md5": null, "artist": "a", "extension": "png",
+                 "local_filename": null, "status": "remaining", "bytes": 0,
+                 "error": "not enough disk space"}
+            ]}"#,
+    )
+    .expect("write");
+
+    let loaded = FailureManifest::load(&path).expect("load");
+    assert_eq!(failure_kind(&loaded.records[0]), FailureKind::Unknown);
+    assert_eq!(loaded.records[1].status, RecordStatus::Remaining);
+    assert_eq!(failure_kind(&loaded.records[1]), FailureKind::LowDiskSpace);
+
+    let current = manifest(vec![failed(3, FailureKind::Http { status: 503 })]);
+    current.save(&path).expect("save");
+    let reloaded = FailureManifest::load(&path).expect("reload");
+    assert_eq!(reloaded.records[0].error, current.records[0].error);
+}
+
+#[test]
+fn retries_that_never_ran_are_put_back() {
+    let mut manifest = manifest(vec![
+        failed(1, FailureKind::Timeout),
+        failed(2, FailureKind::Timeout),
+        failed(3, FailureKind::Timeout),
+    ]);
+    let retrying = manifest.take_selected(&[], &[]);
+    assert!(manifest.records.is_empty());
+
+    // Only post 2 was fetched before the retry was cancelled.
+    let mut done = failed(2, FailureKind::Timeout);
+    done.status = RecordStatus::Completed;
+    done.error = None;
+    let stats = DownloadStatistics {
+        completed: 1,
+        total: 1,
+        records: vec![done],
+        ..Default::default()
+    };
+    manifest.restore_unattempted(retrying, &stats);
+    let ids = manifest
+        .records
+        .iter()
+        .map(|record| record.post_id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [1, 3]);
+    assert_eq!(failure_kind(&manifest.records[0]), FailureKind::Timeout);
+}
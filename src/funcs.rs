@@ -1,25 +1,20 @@
+use std::collections::HashSet;
 use std::path::Path;
 
-use std::fs::{File, OpenOptions};
-use std::{
-    fs,
-    fs::create_dir_all,
-    io::Write,
-    sync::mpsc,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-    },
-    thread,
-    time::Duration,
-};
-
-use reqwest::blocking::{Client, Response};
-use tracing::{Level, debug, error, info, span, warn};
+use std::{fs::create_dir_all, time::Duration};
 
+use reqwest::{Client, RequestBuilder, Response};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::budget::{Budget, DiskReserve, LOW_SPACE_ERROR, RateLimiter};
+use crate::postprocess::PostProcessor;
+use crate::runtime::cancellable;
 use crate::tracker::Tracker;
-use crate::type_defs::api_defs::{PoolData, Post, Posts};
-use crate::{CliContext, Login};
+use crate::type_defs::api_defs::{ArtistData, PoolData, Post, PostSetData, Posts, TagAlias};
+use crate::{CliContext, DownloadError, FailureKind, Login, RecordStatus};
 
 /// Total number of posts across all pages in `data` (i.e. the flattened count,
 /// as returned by [`get_pages`]).
@@ -31,7 +26,12 @@ pub fn sum_posts(data: &Vec<Vec<Post>>) -> usize {
     sum
 }
 
-fn file_name(index: Option<&u64>, artist_name: &str, post_id: u64, file_ext: &str) -> String {
+pub(crate) fn file_name(
+    index: Option<&u64>,
+    artist_name: &str,
+    post_id: u64,
+    file_ext: &str,
+) -> String {
     match index {
         Some(i) => format!("{i:04}-{artist_name}-{post_id}.{file_ext}"),
         None => format!("{artist_name}-{post_id}.{file_ext}"),
@@ -42,12 +42,31 @@ fn file_name(index: Option<&u64>, artist_name: &str, post_id: u64, file_ext: &st
 pub struct DownloadStatus {
     pub finished: bool,
     pub downloaded_bytes: f64,
+    /// Why the download failed, if it did.
+    pub error: Option<DownloadError>,
+}
+
+impl DownloadStatus {
+    fn failed(error: DownloadError) -> Self {
+        Self {
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    /// The failure to record for this download, which didn't finish.
+    fn into_error(self) -> DownloadError {
+        self.error
+            .unwrap_or_else(|| DownloadError::new(FailureKind::Unknown, "download failed"))
+    }
 }
 
 pub struct DownloadFinished {
     pub amount_finished: i64,
     pub amount_failed: i64,
     pub amount_skipped: i64,
+    pub amount_remaining: i64,
+    pub amount_cancelled: i64,
     pub amount: f64,
     pub records: Vec<crate::DownloadRecord>,
 }
@@ -58,6 +77,8 @@ impl DownloadFinished {
             completed: self.amount_finished,
             failed: self.amount_failed,
             skipped: self.amount_skipped,
+            remaining: self.amount_remaining,
+            cancelled: self.amount_cancelled,
             total,
             downloaded_amount: self.amount,
             records: self.records,
@@ -68,7 +89,36 @@ impl DownloadFinished {
 pub struct DownloadOptions<'a> {
     pub retries: u32,
     pub duplicate_index: Option<&'a crate::duplicate::DuplicateIndex>,
-    pub cancel: Option<Arc<AtomicBool>>,
+    pub cancel: Option<CancellationToken>,
+    pub transfer: TransferOptions<'a>,
+    pub budget: Option<&'a Budget>,
+    pub disk: Option<&'a DiskReserve>,
+    /// Makes a converted copy of each downloaded image.
+    pub postprocess: Option<&'a PostProcessor>,
+}
+
+/// Settings for the file transfers themselves, passed down to
+/// [`download_file_with_retries`].
+#[derive(Clone, Copy, Default)]
+pub struct TransferOptions<'a> {
+    /// Throttles the transfer, together with every other transfer sharing it.
+    pub rate_limit: Option<&'a RateLimiter>,
+    /// Receives byte-level progress as the file is written.
+    pub observer: Option<&'a dyn TransferObserver>,
+}
+
+/// Receives byte-level progress of a file transfer (see
+/// [`TransferOptions::observer`]).
+pub trait TransferObserver: Sync {
+    /// A response for the file `name` arrived. `resumed` bytes are already on
+    /// disk from an earlier attempt, and `total` is the full file size, if
+    /// the server reported it. Called again for every retry.
+    fn started(&self, name: &str, resumed: u64, total: Option<u64>);
+    /// `bytes` more bytes were written.
+    fn advanced(&self, bytes: u64);
+    /// The transfer failed for `reason` and retry number `attempt` (counting
+    /// from 1) starts after `delay`.
+    fn retrying(&self, attempt: u32, reason: &str, delay: Duration);
 }
 
 /// Downloads a batch of posts into `output_dir`, skipping (and counting in
@@ -84,7 +134,7 @@ pub struct DownloadOptions<'a> {
 /// instead of the full-resolution [`download_file`] where a sample/lower-quality
 /// variant is available. Successfully downloaded posts are recorded in `tracker`.
 #[allow(clippy::too_many_arguments)]
-pub fn download(
+pub async fn download(
     client: &Client,
     login: &Login,
     data: Vec<Post>,
@@ -105,12 +155,18 @@ pub fn download(
             retries: 3,
             duplicate_index: None,
             cancel: None,
+            transfer: TransferOptions::default(),
+            budget: None,
+            disk: None,
+            postprocess: None,
         },
     )
+    .await
 }
 
 #[allow(clippy::too_many_arguments)]
-pub fn download_with_options(
+#[tracing::instrument(name = "download_handler", level = "debug", skip_all)]
+pub async fn download_with_options(
     client: &Client,
     login: &Login,
     data: Vec<Post>,
@@ -120,21 +176,22 @@ pub fn download_with_options(
     tracker: Option<&Tracker>,
     options: DownloadOptions<'_>,
 ) -> DownloadFinished {
-    let span = span!(Level::DEBUG, "download_handler");
-    let _guard = span.enter();
-
     let mut downloaded_bytes = 0.0;
     let mut amount_finished = 0;
     let mut amount_failed = 0;
     let mut amount_skipped = 0;
+    let mut amount_remaining = 0;
+    let mut amount_cancelled = 0;
     let mut records = Vec::new();
 
-    for post in data {
+    let mut posts = data.into_iter();
+    while let Some(post) = posts.next() {
         if options
             .cancel
             .as_ref()
-            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            .is_some_and(CancellationToken::is_cancelled)
         {
+            amount_cancelled += cancel_rest(post, posts, &mut records);
             break;
         }
         let artist_name = post.tags.parse_artists();
@@ -152,11 +209,14 @@ pub fn download_with_options(
                 source_url: post.file.url.clone(),
                 md5: post.file.md5.clone(),
                 artist: artist_name.clone(),
+                rating: post.rating.clone(),
+                tags: post.tags.all(),
                 extension: post.file.ext.clone(),
                 local_filename: None,
-                status: "skipped".into(),
+                status: RecordStatus::Skipped,
                 bytes: 0,
                 error: None,
+                derived: None,
             });
             continue;
         }
@@ -177,11 +237,14 @@ pub fn download_with_options(
                 source_url: post.file.url.clone(),
                 md5: post.file.md5.clone(),
                 artist: artist_name.clone(),
+                rating: post.rating.clone(),
+                tags: post.tags.all(),
                 extension: post.file.ext.clone(),
                 local_filename: Some(path.file_name().unwrap().to_string_lossy().into()),
-                status: "skipped".into(),
+                status: RecordStatus::Skipped,
                 bytes: 0,
                 error: None,
+                derived: None,
             });
             continue;
         }
@@ -196,24 +259,82 @@ pub fn download_with_options(
                 source_url: post.file.url.clone(),
                 md5: post.file.md5.clone(),
                 artist: artist_name.clone(),
+                rating: post.rating.clone(),
+                tags: post.tags.all(),
                 extension: post.file.ext.clone(),
                 local_filename: Some(existing),
-                status: "duplicate".into(),
+                status: RecordStatus::Duplicate,
                 bytes: 0,
                 error: None,
+                derived: None,
+            });
+            continue;
+        }
+
+        // Lower-quality variants have no size in the API response, so only
+        // the bytes already used are checked for them.
+        let size = if *lower_quality { None } else { post.file.size };
+        let stopped = if options.disk.is_some_and(|disk| !disk.has_room(size)) {
+            Some(DownloadError::new(
+                FailureKind::LowDiskSpace,
+                LOW_SPACE_ERROR,
+            ))
+        } else if options
+            .budget
+            .is_some_and(|budget| !budget.try_reserve(size))
+        {
+            Some(DownloadError::new(
+                FailureKind::BudgetReached,
+                "download budget reached",
+            ))
+        } else {
+            None
+        };
+        if let Some(error) = stopped {
+            amount_remaining += 1;
+            records.push(crate::DownloadRecord {
+                post_id: post.id,
+                source_url: post.file.url.clone(),
+                md5: post.file.md5.clone(),
+                artist: artist_name.clone(),
+                rating: post.rating.clone(),
+                tags: post.tags.all(),
+                extension: post.file.ext.clone(),
+                local_filename: None,
+                status: RecordStatus::Remaining,
+                bytes: 0,
+                error: Some(error),
+                derived: None,
             });
             continue;
         }
 
         if *lower_quality {
-            let stat = lower_quality_dl_file_with_retries(
-                client,
-                login,
-                &post,
-                &artist_name,
-                index,
-                output_dir,
-                options.retries,
+            // A transfer cut short by cancellation keeps its `.part` file, so
+            // the next run resumes it.
+            let Some(stat) = cancellable(
+                options.cancel.as_ref(),
+                lower_quality_dl_file_with_retries(
+                    client,
+                    login,
+                    &post,
+                    &artist_name,
+                    index,
+                    output_dir,
+                    options.retries,
+                    options.transfer,
+                ),
+            )
+            .await
+            else {
+                settle(options.budget, size, None);
+                amount_cancelled += cancel_rest(post, posts, &mut records);
+                break;
+            };
+            settle(
+                options.budget,
+                size,
+                stat.finished.then_some(stat.downloaded_bytes as u64),
             );
             if stat.finished {
                 downloaded_bytes += stat.downloaded_bytes;
@@ -234,21 +355,26 @@ pub fn download_with_options(
                 {
                     index.insert(md5, &filename);
                 }
+                let derived = postprocess(options.postprocess, &output_dir.join(&filename)).await;
                 records.push(crate::DownloadRecord {
                     post_id: post.id,
                     source_url: post.file.url.clone(),
                     md5: post.file.md5.clone(),
                     artist: artist_name.clone(),
+                    rating: post.rating.clone(),
+                    tags: post.tags.all(),
                     extension: post.file.ext.clone(),
                     local_filename: Some(filename),
-                    status: "completed".into(),
+                    status: RecordStatus::Completed,
                     bytes: stat.downloaded_bytes as u64,
                     error: None,
+                    derived,
                 });
             } else {
+                let error = stat.into_error();
                 amount_failed += 1;
                 warn!(
-                    "Failed to download {}-{}.{}",
+                    "Failed to download {}-{}.{}: {error}",
                     artist_name, post.id, post.file.ext
                 );
                 records.push(crate::DownloadRecord {
@@ -256,26 +382,45 @@ pub fn download_with_options(
                     source_url: post.file.url.clone(),
                     md5: post.file.md5.clone(),
                     artist: artist_name.clone(),
+                    rating: post.rating.clone(),
+                    tags: post.tags.all(),
                     extension: post.file.ext.clone(),
                     local_filename: None,
-                    status: "failed".into(),
+                    status: RecordStatus::Failed,
                     bytes: 0,
-                    error: Some("download failed".into()),
+                    error: Some(error),
+                    derived: None,
                 });
             }
         } else {
             match &post.file.url {
                 Some(url) => {
-                    let stat = download_file_with_retries(
-                        client,
-                        login,
-                        url,
-                        &post.file.ext,
-                        post.id,
-                        &artist_name,
-                        index,
-                        output_dir,
-                        options.retries,
+                    let Some(stat) = cancellable(
+                        options.cancel.as_ref(),
+                        download_file_with_retries(
+                            client,
+                            login,
+                            url,
+                            &post.file.ext,
+                            post.id,
+                            &artist_name,
+                            index,
+                            output_dir,
+                            options.retries,
+                            options.transfer,
+                        ),
+                    )
+                    .await
+                    else {
+                        settle(options.budget, size, None);
+                        amount_cancelled += cancel_rest(post, posts, &mut records);
+                        break;
+                    };
+                    let stat = verify_md5(stat, &path, post.file.md5.as_deref()).await;
+                    settle(
+                        options.budget,
+                        size,
+                        stat.finished.then_some(stat.downloaded_bytes as u64),
                     );
                     if stat.finished {
                         downloaded_bytes += stat.downloaded_bytes;
@@ -296,21 +441,26 @@ pub fn download_with_options(
                         {
                             index.insert(md5, &filename);
                         }
+                        let derived = postprocess(options.postprocess, &path).await;
                         records.push(crate::DownloadRecord {
                             post_id: post.id,
                             source_url: post.file.url.clone(),
                             md5: post.file.md5.clone(),
                             artist: artist_name.clone(),
+                            rating: post.rating.clone(),
+                            tags: post.tags.all(),
                             extension: post.file.ext.clone(),
                             local_filename: Some(filename),
-                            status: "completed".into(),
+                            status: RecordStatus::Completed,
                             bytes: stat.downloaded_bytes as u64,
                             error: None,
+                            derived,
                         });
                     } else {
+                        let error = stat.into_error();
                         amount_failed += 1;
                         warn!(
-                            "Failed to download {}-{}.{}",
+                            "Failed to download {}-{}.{}: {error}",
                             artist_name, post.id, post.file.ext
                         );
                         records.push(crate::DownloadRecord {
@@ -318,30 +468,35 @@ pub fn download_with_options(
                             source_url: post.file.url.clone(),
                             md5: post.file.md5.clone(),
                             artist: artist_name.clone(),
+                            rating: post.rating.clone(),
+                            tags: post.tags.all(),
                             extension: post.file.ext.clone(),
                             local_filename: None,
-                            status: "failed".into(),
+                            status: RecordStatus::Failed,
                             bytes: 0,
-                            error: Some("download failed".into()),
+                            error: Some(error),
+                            derived: None,
                         });
                     }
                 }
                 None => {
-                    warn!(
-                        "Cannot download post {}-{} due to it missing a file url",
-                        artist_name, post.id
-                    );
+                    settle(options.budget, size, None);
+                    let error = missing_url(&post);
+                    warn!("Cannot download post {}-{}: {error}", artist_name, post.id);
                     amount_failed += 1;
                     records.push(crate::DownloadRecord {
                         post_id: post.id,
                         source_url: None,
                         md5: post.file.md5.clone(),
                         artist: artist_name.clone(),
+                        rating: post.rating.clone(),
+                        tags: post.tags.all(),
                         extension: post.file.ext.clone(),
                         local_filename: None,
-                        status: "failed".into(),
+                        status: RecordStatus::Failed,
                         bytes: 0,
-                        error: Some("missing file URL".into()),
+                        error: Some(error),
+                        derived: None,
                     });
                 }
             }
@@ -352,13 +507,119 @@ pub fn download_with_options(
         amount_finished,
         amount_failed,
         amount_skipped,
+        amount_remaining,
+        amount_cancelled,
         amount: downloaded_bytes,
         records,
     }
 }
 
+/// Records `post` and the `rest` of a batch as cancelled, returning how many
+/// posts that was.
+fn cancel_rest(
+    post: Post,
+    rest: impl Iterator<Item = Post>,
+    records: &mut Vec<crate::DownloadRecord>,
+) -> i64 {
+    let before = records.len();
+    records.extend(
+        std::iter::once(post)
+            .chain(rest)
+            .map(|post| crate::DownloadRecord {
+                post_id: post.id,
+                source_url: post.file.url.clone(),
+                md5: post.file.md5.clone(),
+                artist: post.tags.parse_artists(),
+                rating: post.rating.clone(),
+                tags: post.tags.all(),
+                extension: post.file.ext.clone(),
+                local_filename: None,
+                status: RecordStatus::Cancelled,
+                bytes: 0,
+                error: Some(DownloadError::new(
+                    FailureKind::Cancelled,
+                    "download cancelled",
+                )),
+                derived: None,
+            }),
+    );
+    (records.len() - before) as i64
+}
+
+/// Why `post` has no file URL: deleted posts have none at all, and others
+/// are hidden from requests without a login.
+fn missing_url(post: &Post) -> DownloadError {
+    if post.flags.deleted {
+        DownloadError::new(FailureKind::Deleted, "post was deleted")
+    } else {
+        DownloadError::new(
+            FailureKind::Blacklisted,
+            "missing file URL (the post is hidden without --login)",
+        )
+    }
+}
+
+/// Checks a finished download against the MD5 the API lists for it. On a
+/// mismatch the file is removed and the download fails.
+async fn verify_md5(stat: DownloadStatus, path: &Path, expected: Option<&str>) -> DownloadStatus {
+    let Some(expected) = expected.filter(|_| stat.finished) else {
+        return stat;
+    };
+    let file = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || file_md5(&file))
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+    match actual {
+        Ok(actual) if actual.eq_ignore_ascii_case(expected) => stat,
+        Ok(actual) => {
+            let _ = fs::remove_file(path).await;
+            DownloadStatus::failed(DownloadError::new(
+                FailureKind::Md5Mismatch,
+                format!("MD5 mismatch (expected {expected}, got {actual}), file removed"),
+            ))
+        }
+        Err(e) => DownloadStatus::failed(DownloadError::new(
+            FailureKind::Io,
+            format!("Failed to read {}: {e}", path.display()),
+        )),
+    }
+}
+
+/// The MD5 of the file at `path`, as lowercase hex.
+pub(crate) fn file_md5(path: &Path) -> std::io::Result<String> {
+    use md5::{Digest, Md5};
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Md5::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Makes the converted copy of the downloaded file at `path` with
+/// `processor`, if there is one and the file is an image it converts.
+/// Returns the copy's path for the post's record; a failed conversion is
+/// only logged, since the download itself succeeded.
+async fn postprocess(processor: Option<&PostProcessor>, path: &Path) -> Option<String> {
+    let processor = processor.filter(|_| PostProcessor::handles(path))?;
+    match processor.process(path.to_path_buf()).await {
+        Ok(copy) => {
+            debug!("Converted {} into {}.", path.display(), copy.display());
+            Some(copy.to_string_lossy().into_owned())
+        }
+        Err(e) => {
+            warn!("Failed to convert {e}");
+            None
+        }
+    }
+}
+
+fn settle(budget: Option<&Budget>, size: Option<u64>, written: Option<u64>) {
+    if let Some(budget) = budget {
+        budget.settle(size, written);
+    }
+}
+
 /// Streams `target_url`'s response body directly to a file in `output_dir`
-/// (via `Response::copy_to`, so the whole file is never buffered in memory),
+/// (chunk by chunk, so the whole file is never buffered in memory),
 /// named `{index-}{artist_name}-{post_id}.{file_ext}` (zero-padded 4-digit
 /// index prefix if `Some`). `downloaded_bytes` on success reflects the actual
 /// bytes written, not a trusted `Content-Length` header. Returns a
@@ -366,7 +627,7 @@ pub fn download_with_options(
 /// the copy itself fails (the failure is logged; this function does not panic
 /// on network/IO errors).
 #[allow(clippy::too_many_arguments)]
-pub fn download_file(
+pub async fn download_file(
     client: &Client,
     login: &Login,
     target_url: &str,
@@ -376,9 +637,6 @@ pub fn download_file(
     index: Option<&u64>,
     output_dir: &Path,
 ) -> DownloadStatus {
-    let span = span!(Level::DEBUG, "file_download");
-    let _guard = span.enter();
-
     download_file_with_retries(
         client,
         login,
@@ -389,11 +647,17 @@ pub fn download_file(
         index,
         output_dir,
         3,
+        TransferOptions::default(),
     )
+    .await
 }
 
+/// [`download_file`] with a configurable number of `retries` for transient
+/// failures, resuming from any `.part` file left by an earlier attempt, and
+/// throttled and observed as `transfer` says.
 #[allow(clippy::too_many_arguments)]
-pub fn download_file_with_retries(
+#[tracing::instrument(name = "file_download", level = "debug", skip_all)]
+pub async fn download_file_with_retries(
     client: &Client,
     login: &Login,
     target_url: &str,
@@ -403,92 +667,171 @@ pub fn download_file_with_retries(
     index: Option<&u64>,
     output_dir: &Path,
     retries: u32,
+    transfer: TransferOptions<'_>,
 ) -> DownloadStatus {
-    let span = span!(Level::DEBUG, "file_download");
-    let _guard = span.enter();
     let name = file_name(index, artist_name, post_id, file_ext);
     let target = output_dir.join(&name);
     let part = output_dir.join(format!("{name}.part"));
 
     for attempt in 0..=retries {
-        let existing = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
-        let mut request = if !login.username.is_empty() && !login.api_key.is_empty() {
-            client
-                .get(target_url)
-                .basic_auth(&login.username, Some(&login.api_key))
-        } else {
-            client.get(target_url)
-        };
+        let existing = fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+        let mut request = authorize(client.get(target_url), login);
         if existing > 0 {
             request = request.header(reqwest::header::RANGE, format!("bytes={existing}-"));
         }
-        let mut response = match request.send() {
+        let mut response = match request.send().await {
             Ok(response) if response.status().is_success() => response,
             Ok(response)
                 if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
                     || response.status().is_server_error() =>
             {
+                let error = http_error(response.status());
                 if attempt < retries {
-                    thread::sleep(Duration::from_millis(200 * 2u64.pow(attempt.min(4))));
+                    retry_after(transfer, attempt, &error).await;
                     continue;
                 }
-                warn!("Failed to request {name}: HTTP {}", response.status());
-                return DownloadStatus::default();
+                warn!("Failed to request {name}: {error}");
+                return DownloadStatus::failed(error);
             }
             Ok(response) => {
-                warn!("Failed to request {name}: HTTP {}", response.status());
-                return DownloadStatus::default();
+                let error = http_error(response.status());
+                warn!("Failed to request {name}: {error}");
+                return DownloadStatus::failed(error);
             }
             Err(error) if attempt < retries => {
-                thread::sleep(Duration::from_millis(200 * 2u64.pow(attempt.min(4))));
                 debug!("Retrying {name} after request failure: {error}");
+                retry_after(transfer, attempt, &request_error(&error)).await;
                 continue;
             }
             Err(error) => {
                 warn!("Failed to request {name}: {error}");
-                return DownloadStatus::default();
+                return DownloadStatus::failed(request_error(&error));
             }
         };
         let append = existing > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
-        let mut out = if append {
-            match OpenOptions::new().create(true).append(true).open(&part) {
-                Ok(file) => file,
-                Err(_) => return DownloadStatus::default(),
-            }
+        let out = if append {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&part)
+                .await
         } else {
-            match File::create(&part) {
-                Ok(file) => file,
-                Err(_) => return DownloadStatus::default(),
+            File::create(&part).await
+        };
+        let mut out = match out {
+            Ok(out) => out,
+            Err(e) => {
+                let error = DownloadError::new(
+                    FailureKind::Io,
+                    format!("Failed to create {}: {e}", part.display()),
+                );
+                warn!("{error}");
+                return DownloadStatus::failed(error);
             }
         };
-        match response.copy_to(&mut out) {
-            Ok(written) if out.flush().is_ok() && fs::rename(&part, &target).is_ok() => {
+        let resumed = if append { existing } else { 0 };
+        if let Some(observer) = transfer.observer {
+            let total = response.content_length().map(|length| resumed + length);
+            observer.started(&name, resumed, total);
+        }
+        let written = match write_body(&mut response, &mut out, transfer).await {
+            Ok(written) => fs::rename(&part, &target)
+                .await
+                .map(|()| written)
+                .map_err(|e| DownloadError::new(FailureKind::Io, e.to_string())),
+            Err(error) => Err(error),
+        };
+        match written {
+            Ok(written) => {
                 let total = if append { existing + written } else { written };
                 return DownloadStatus {
                     finished: true,
                     downloaded_bytes: total as f64,
+                    error: None,
                 };
             }
-            _ if attempt < retries => {
-                thread::sleep(Duration::from_millis(200 * 2u64.pow(attempt.min(4))));
+            Err(error) if attempt < retries => {
+                retry_after(transfer, attempt, &error).await;
             }
-            _ => {
-                warn!("Failed to write {name}");
-                return DownloadStatus::default();
+            Err(error) => {
+                warn!("Failed to write {name}: {error}");
+                return DownloadStatus::failed(error);
             }
         }
     }
     DownloadStatus::default()
 }
 
+/// Copies the rest of `response`'s body into `out`, returning the number of
+/// bytes written.
+async fn write_body(
+    response: &mut Response,
+    out: &mut File,
+    transfer: TransferOptions<'_>,
+) -> Result<u64, DownloadError> {
+    let io_error = |e: std::io::Error| DownloadError::new(FailureKind::Io, e.to_string());
+    let mut written = 0;
+    while let Some(chunk) = response.chunk().await.map_err(|e| request_error(&e))? {
+        if let Some(rate_limit) = transfer.rate_limit {
+            rate_limit.acquire(chunk.len() as u64).await;
+        }
+        out.write_all(&chunk).await.map_err(io_error)?;
+        if let Some(observer) = transfer.observer {
+            observer.advanced(chunk.len() as u64);
+        }
+        written += chunk.len() as u64;
+    }
+    out.flush().await.map_err(io_error)?;
+    Ok(written)
+}
+
+/// Classifies a failed request, or a failure reading its body.
+fn request_error(error: &reqwest::Error) -> DownloadError {
+    let kind = if error.is_timeout() {
+        FailureKind::Timeout
+    } else if let Some(status) = error.status() {
+        FailureKind::Http {
+            status: status.as_u16(),
+        }
+    } else {
+        FailureKind::ConnectionReset
+    };
+    DownloadError::new(kind, error.to_string())
+}
+
+fn http_error(status: reqwest::StatusCode) -> DownloadError {
+    DownloadError::new(
+        FailureKind::Http {
+            status: status.as_u16(),
+        },
+        format!("HTTP {status}"),
+    )
+}
+
+/// Waits out the backoff before retry number `attempt + 1`, telling the
+/// transfer's observer why.
+async fn retry_after(transfer: TransferOptions<'_>, attempt: u32, reason: &DownloadError) {
+    let delay = backoff(attempt);
+    if let Some(observer) = transfer.observer {
+        observer.retrying(attempt + 1, &reason.message, delay);
+    }
+    tokio::time::sleep(delay).await;
+}
+
+/// How long to wait before retry number `attempt + 1`: exponential, from
+/// 200ms up to 3.2s.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(200 * 2u64.pow(attempt.min(4)))
+}
+
 /// Downloads a lower-quality variant of `post`, for use when `--lower-quality`
 /// is set. Precedence, preferring an actually-lower-quality source first:
 /// the sample's 480p video alternate, then the sample image/thumbnail URL,
 /// and only if neither exists does this fall back to the full-resolution
-/// `post.file.url`. Returns a default (`finished: false`) `DownloadStatus` if
+/// `post.file.url`. Returns a failed `DownloadStatus` if
 /// none of those are available.
 #[allow(clippy::too_many_arguments)]
-pub fn lower_quality_dl_file(
+pub async fn lower_quality_dl_file(
     client: &Client,
     login: &Login,
     post: &Post,
@@ -496,11 +839,22 @@ pub fn lower_quality_dl_file(
     index: Option<&u64>,
     output_dir: &Path,
 ) -> DownloadStatus {
-    lower_quality_dl_file_with_retries(client, login, post, artist_name, index, output_dir, 3)
+    lower_quality_dl_file_with_retries(
+        client,
+        login,
+        post,
+        artist_name,
+        index,
+        output_dir,
+        3,
+        TransferOptions::default(),
+    )
+    .await
 }
 
 #[allow(clippy::too_many_arguments)]
-pub fn lower_quality_dl_file_with_retries(
+#[tracing::instrument(name = "lower_quality_handler", level = "debug", skip_all)]
+pub async fn lower_quality_dl_file_with_retries(
     client: &Client,
     login: &Login,
     post: &Post,
@@ -508,10 +862,8 @@ pub fn lower_quality_dl_file_with_retries(
     index: Option<&u64>,
     output_dir: &Path,
     retries: u32,
+    transfer: TransferOptions<'_>,
 ) -> DownloadStatus {
-    let span = span!(Level::DEBUG, "lower_quality_handler");
-    let _guard = span.enter();
-
     let url = post
         .sample
         .alternates
@@ -523,23 +875,25 @@ pub fn lower_quality_dl_file_with_retries(
         .or(post.file.url.as_ref());
 
     match url {
-        Some(url) => download_file_with_retries(
-            client,
-            login,
-            url,
-            &post.file.ext,
-            post.id,
-            artist_name,
-            index,
-            output_dir,
-            retries,
-        ),
+        Some(url) => {
+            download_file_with_retries(
+                client,
+                login,
+                url,
+                &post.file.ext,
+                post.id,
+                artist_name,
+                index,
+                output_dir,
+                retries,
+                transfer,
+            )
+            .await
+        }
         None => {
-            warn!(
-                "Cannot download post {}-{} due it not having any file url.",
-                artist_name, &post.id
-            );
-            DownloadStatus::default()
+            let error = missing_url(post);
+            warn!("Cannot download post {}-{}: {error}", artist_name, &post.id);
+            DownloadStatus::failed(error)
         }
     }
 }
@@ -601,15 +955,9 @@ pub fn slice_pool_posts(arr: Vec<(u64, Post)>, chunk_size: i32) -> Vec<Vec<(u64,
     res
 }
 
-/// Fetches all matching posts for a favourites/tag search, one page at a time,
-/// stopping when the API returns an empty page. `context.pages == -1` fetches
-/// every page; `context.pages > 0` fetches at most that many; any other value
-/// (e.g. `0`) fetches nothing. `fav`/`tags`/`random` are combined into the
-/// request's `tags` query parameter as-is (pass `""` for any that don't apply).
-/// A non-2xx response stops pagination early (logged, not propagated as an
-/// error — check the returned `Vec`'s length rather than expecting a
-/// `Result`), but a response body that fails to parse as JSON will panic.
-pub fn get_pages(
+/// Fetches all matching posts for a favourites/tag search, collecting every
+/// page before returning (see [`for_each_page`] for how paging behaves).
+pub async fn get_pages(
     context: &CliContext,
     login: &Login,
     client: &Client,
@@ -618,84 +966,85 @@ pub fn get_pages(
     random: &str,
     count: &u32,
 ) -> Vec<Vec<Post>> {
-    let mut pages = 0;
     let mut posts: Vec<Vec<Post>> = vec![];
+    for_each_page(
+        context,
+        login,
+        client,
+        fav,
+        tags,
+        random,
+        count,
+        async |page| {
+            posts.push(page);
+            true
+        },
+    )
+    .await;
+    posts
+}
 
-    let span = span!(Level::DEBUG, "get_pages");
-    let _guard = span.enter();
-
-    if context.pages == -1 {
-        loop {
-            let target: String = format!(
-                "https://{}/posts.json?tags={} {} {}&limit={}&page={}",
-                context.api_source(),
-                fav,
-                tags,
-                random,
-                count,
-                pages + 1
-            );
-            debug!(target);
-            report_phase(context, format!("Fetching page {}...", pages + 1));
-
-            let Some(res) = request(context, client, login, &target) else {
-                break;
-            };
-            if let Err(e) = res.error_for_status_ref() {
-                error!("Response returned: {}", e);
-                break;
-            }
-            let data = res.json::<Posts>().expect("Error reading response json.");
+/// Fetches a favourites/tag search one page at a time, handing each page to
+/// `on_page` as soon as it arrives, and stopping when the API returns an empty
+/// page or `on_page` returns `false`. `context.pages == -1` fetches every
+/// page; `context.pages > 0` fetches at most that many; any other value (e.g.
+/// `0`) fetches nothing. `fav`/`tags`/`random` are combined into the request's
+/// `tags` query parameter as-is (pass `""` for any that don't apply).
+/// A non-2xx response stops pagination early (logged, not propagated as an
+/// error), but a response body that fails to parse as JSON will panic.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "get_pages", level = "debug", skip_all)]
+pub async fn for_each_page(
+    context: &CliContext,
+    login: &Login,
+    client: &Client,
+    fav: &str,
+    tags: &str,
+    random: &str,
+    count: &u32,
+    mut on_page: impl AsyncFnMut(Vec<Post>) -> bool,
+) {
+    let mut pages = 0;
 
-            if data.posts.is_empty() {
-                break;
-            }
+    if context.pages != -1 && context.pages <= 0 {
+        return;
+    }
+    while context.pages == -1 || pages < context.pages {
+        let target: String = format!(
+            "https://{}/posts.json?tags={} {} {}&limit={}&page={}",
+            context.api_source(),
+            fav,
+            tags,
+            random,
+            count,
+            pages + 1
+        );
+        debug!(target);
+        report_phase(context, format!("Fetching page {}...", pages + 1));
 
-            posts.push(data.posts);
-            pages += 1;
+        let Some(res) = request(context, client, login, &target).await else {
+            break;
+        };
+        if let Err(e) = res.error_for_status_ref() {
+            error!("Response returned: {}", e);
+            break;
         }
-    } else if context.pages > 0 {
-        loop {
-            if pages == context.pages {
-                break;
-            }
-
-            let target: String = format!(
-                "https://{}/posts.json?tags={} {} {}&limit={}&page={}",
-                context.api_source(),
-                fav,
-                tags,
-                random,
-                count,
-                pages + 1
-            );
-
-            report_phase(context, format!("Fetching page {}...", pages + 1));
-            let Some(res) = request(context, client, login, &target) else {
-                break;
-            };
-            if let Err(e) = res.error_for_status_ref() {
-                error!("Response returned: {}", e);
-                break;
-            }
-            let data = res.json::<Posts>().expect("Error reading response json.");
-
-            if data.posts.is_empty() {
-                break;
-            }
+        let data = res
+            .json::<Posts>()
+            .await
+            .expect("Error reading response json.");
 
-            posts.push(data.posts);
-            pages += 1;
+        if data.posts.is_empty() || !on_page(data.posts).await {
+            break;
         }
+        pages += 1;
     }
-
-    posts
 }
 
 /// Looks up pool metadata (name, description, ordered `post_ids`) by `pool_id`.
 /// Returns `None` if the request fails (non-2xx) or no pool with that ID
 /// exists; panics if a 2xx response body fails to parse as JSON.
-pub fn get_pool(
+pub async fn get_pool(
     context: &CliContext,
     client: &Client,
     login: &Login,
@@ -707,7 +1056,7 @@ pub fn get_pool(
         pool_id
     );
     report_phase(context, format!("Fetching pool {pool_id}..."));
-    let res = request(context, client, login, &target)?;
+    let res = request(context, client, login, &target).await?;
     if let Err(e) = res.error_for_status_ref() {
         error!("Response returned: {}", e);
         return None;
@@ -715,6 +1064,7 @@ pub fn get_pool(
 
     let data = res
         .json::<Vec<PoolData>>()
+        .await
         .expect("Error reading response json.");
     if data.is_empty() {
         return None;
@@ -723,12 +1073,338 @@ pub fn get_pool(
     Some(data[0].clone())
 }
 
+/// Searches pools whose name contains `query` (spaces are matched against the
+/// API's `_`-separated names), most recently updated first, returning at most
+/// `limit` results. A failed request is logged and yields an empty `Vec`.
+pub async fn search_pools(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+    query: &str,
+    limit: &u32,
+) -> Vec<PoolData> {
+    let limit = limit.to_string();
+    let name = format!("*{}*", query.trim().replace(' ', "_"));
+    let target = match reqwest::Url::parse_with_params(
+        &format!("https://{}/pools.json", context.api_source()),
+        [
+            ("limit", limit.as_str()),
+            ("search[name_matches]", name.as_str()),
+        ],
+    ) {
+        Ok(target) => target,
+        Err(e) => {
+            error!("Invalid pool search '{query}': {e}");
+            return Vec::new();
+        }
+    };
+    report_phase(context, format!("Searching pools for '{query}'..."));
+    let Some(res) = request(context, client, login, target.as_str()).await else {
+        return Vec::new();
+    };
+    if let Err(e) = res.error_for_status_ref() {
+        error!("Response returned: {}", e);
+        return Vec::new();
+    }
+
+    res.json::<Vec<PoolData>>()
+        .await
+        .expect("Error reading response json.")
+}
+
+/// Pool IDs linked from a pool description with the site's `pool #123` syntax,
+/// in order of appearance and without repeats. Series pools conventionally link
+/// their previous/next parts this way.
+pub fn linked_pool_ids(description: &str) -> Vec<u64> {
+    let lower = description.to_lowercase();
+    let mut ids = Vec::new();
+    for (start, _) in lower.match_indices("pool #") {
+        let digits = lower[start + "pool #".len()..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>();
+        if let Ok(id) = digits.parse::<u64>()
+            && !ids.contains(&id)
+        {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// The subdirectory a pool is downloaded into: its display name made safe
+/// for the filesystem (see [`sanitize_file_name`]), or `pool-{id}` if nothing
+/// usable is left.
+pub fn pool_dir_name(pool: &PoolData) -> String {
+    let name = sanitize_file_name(&pool.display_name());
+    if name.is_empty() {
+        format!("pool-{}", pool.id)
+    } else {
+        name
+    }
+}
+
+/// Removes characters that aren't valid in file names on common platforms,
+/// along with surrounding whitespace and trailing dots. May return an empty
+/// string.
+pub fn sanitize_file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .filter(|c| {
+            !c.is_control() && !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        })
+        .collect::<String>();
+    name.trim().trim_end_matches('.').trim().to_owned()
+}
+
+/// Looks up a post set by its numeric ID or, if `set` isn't a number, by its
+/// shortname. `post_ids` come back in the set's own order. Returns `None` if
+/// the request fails (non-2xx) or no matching set exists; panics if a 2xx
+/// response body fails to parse as JSON.
+pub async fn get_post_set(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+    set: &str,
+) -> Option<PostSetData> {
+    let search = if set.parse::<u64>().is_ok() {
+        "id"
+    } else {
+        "shortname"
+    };
+    let key = format!("search[{search}]");
+    let target = match reqwest::Url::parse_with_params(
+        &format!("https://{}/post_sets.json", context.api_source()),
+        [("limit", "1"), (key.as_str(), set)],
+    ) {
+        Ok(target) => target,
+        Err(e) => {
+            error!("Invalid set '{set}': {e}");
+            return None;
+        }
+    };
+    report_phase(context, format!("Fetching set {set}..."));
+    let res = request(context, client, login, target.as_str()).await?;
+    if let Err(e) = res.error_for_status_ref() {
+        error!("Response returned: {}", e);
+        return None;
+    }
+
+    let data = res
+        .json::<Vec<PostSetData>>()
+        .await
+        .expect("Error reading response json.");
+    data.into_iter().next()
+}
+
+/// Looks up an artist entry by `name`, which may also be one of the artist's
+/// other names. The returned entry comes from the artist's own page, so it
+/// includes their URLs. Fails if the request fails, or if no artist is
+/// called `name` (see [`pick_artist`]).
+pub async fn get_artist(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+    name: &str,
+) -> Result<ArtistData, String> {
+    let name = name.trim().replace(' ', "_").to_lowercase();
+    let target = reqwest::Url::parse_with_params(
+        &format!("https://{}/artists.json", context.api_source()),
+        [("limit", "25"), ("search[any_name_matches]", name.as_str())],
+    )
+    .map_err(|e| format!("Invalid artist name '{name}': {e}"))?;
+    report_phase(context, format!("Fetching artist {name}..."));
+    let failed = || format!("Failed to look up artist '{name}'.");
+    let res = request(context, client, login, target.as_str())
+        .await
+        .ok_or_else(failed)?;
+    if let Err(e) = res.error_for_status_ref() {
+        error!("Response returned: {}", e);
+        return Err(failed());
+    }
+    let found = json_list::<ArtistData>(
+        res.json().await.expect("Error reading response json."),
+        "artists",
+    );
+    let artist = pick_artist(&found, &name)?;
+
+    let target = format!(
+        "https://{}/artists/{}.json",
+        context.api_source(),
+        artist.id
+    );
+    let res = request(context, client, login, &target)
+        .await
+        .ok_or_else(failed)?;
+    match res.error_for_status() {
+        Ok(res) => Ok(res.json().await.expect("Error reading response json.")),
+        Err(e) => {
+            error!("Response returned: {}", e);
+            Err(failed())
+        }
+    }
+}
+
+/// The artist named `name` among the results of a name search, `found`.
+/// Those can also be artists who only list `name` as one of their other
+/// names, so unless exactly one of them does, the pick is left to the user:
+/// the error lists the candidates.
+pub fn pick_artist<'a>(found: &'a [ArtistData], name: &str) -> Result<&'a ArtistData, String> {
+    if let Some(artist) = found.iter().find(|artist| artist.name == name) {
+        return Ok(artist);
+    }
+    let mut others = found.iter().filter(|artist| {
+        artist
+            .other_names
+            .iter()
+            .any(|other| other.trim().replace(' ', "_").to_lowercase() == name)
+    });
+    match (others.next(), others.next()) {
+        (Some(artist), None) => Ok(artist),
+        _ if found.is_empty() => Err(format!("No artist found for '{name}'.")),
+        _ => {
+            let candidates = found
+                .iter()
+                .map(|artist| artist.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(format!(
+                "No artist is named '{name}'. Did you mean one of: {candidates}?"
+            ))
+        }
+    }
+}
+
+/// Active tag aliases that resolve to `tag`, i.e. the old or alternative tag
+/// names the site maps onto it. A failed request is logged and yields an empty
+/// `Vec`.
+pub async fn get_tag_aliases(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+    tag: &str,
+) -> Vec<TagAlias> {
+    let target = match reqwest::Url::parse_with_params(
+        &format!("https://{}/tag_aliases.json", context.api_source()),
+        [
+            ("limit", "100"),
+            ("search[consequent_name]", tag),
+            ("search[status]", "active"),
+        ],
+    ) {
+        Ok(target) => target,
+        Err(e) => {
+            error!("Invalid tag '{tag}': {e}");
+            return Vec::new();
+        }
+    };
+    report_phase(context, format!("Fetching aliases of {tag}..."));
+    let Some(res) = request(context, client, login, target.as_str()).await else {
+        return Vec::new();
+    };
+    if let Err(e) = res.error_for_status_ref() {
+        error!("Response returned: {}", e);
+        return Vec::new();
+    }
+    json_list(
+        res.json().await.expect("Error reading response json."),
+        "tag_aliases",
+    )
+}
+
+/// Fetches pages for each of `tags` in turn (see [`get_pages`]), dropping posts
+/// already returned for an earlier tag, so overlapping searches yield every
+/// post once. Tags that match nothing contribute no pages.
+pub async fn get_pages_for_tags(
+    context: &CliContext,
+    login: &Login,
+    client: &Client,
+    tags: &[String],
+    count: &u32,
+) -> Vec<Vec<Post>> {
+    let mut pages = Vec::new();
+    for_each_page_for_tags(context, login, client, tags, count, async |posts| {
+        pages.push(posts);
+        true
+    })
+    .await;
+    pages
+}
+
+/// Streaming form of [`get_pages_for_tags`]: each deduplicated page is handed
+/// to `on_page` as it arrives, and returning `false` stops fetching entirely.
+pub async fn for_each_page_for_tags(
+    context: &CliContext,
+    login: &Login,
+    client: &Client,
+    tags: &[String],
+    count: &u32,
+    mut on_page: impl AsyncFnMut(Vec<Post>) -> bool,
+) {
+    let mut seen = HashSet::new();
+    let mut stopped = false;
+    for tag in tags {
+        for_each_page(
+            context,
+            login,
+            client,
+            "",
+            tag,
+            "",
+            count,
+            async |mut posts| {
+                posts.retain(|post| seen.insert(post.id));
+                stopped = !posts.is_empty() && !on_page(posts).await;
+                !stopped
+            },
+        )
+        .await;
+        if stopped {
+            break;
+        }
+    }
+}
+
+/// Reads a list endpoint's response, which is normally a JSON array but comes
+/// back as `{"<key>": []}` when nothing matches.
+fn json_list<T: serde::de::DeserializeOwned>(value: serde_json::Value, key: &str) -> Vec<T> {
+    let list = match value {
+        serde_json::Value::Object(mut object) => object.remove(key).unwrap_or_default(),
+        value => value,
+    };
+    serde_json::from_value(list).unwrap_or_default()
+}
+
+/// Checks `login` against `context`'s API with a small post search, failing
+/// with a message if the credentials are rejected or the request fails.
+pub async fn validate_login(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+) -> Result<(), String> {
+    let response = client
+        .get(format!(
+            "https://{}/posts.json?tags=&limit=5",
+            context.api_source()
+        ))
+        .basic_auth(&login.username, Some(&login.api_key))
+        .send()
+        .await
+        .map_err(|e| format!("Could not reach {}: {e}", context.api_source()))?;
+    match response.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!(
+            "The credentials provided aren't valid, or something else happened. Err: {e}"
+        )),
+    }
+}
+
 /// Fetches full post data for each ID in `post_ids`, one request per ID, in
 /// the order given (this is what lets [`crate::commands::download_pool`]
 /// preserve a pool's original ordering). On the first failed request or empty
 /// result, returns an empty `Vec` immediately rather than partial results —
 /// callers should treat an empty return as "failed", not "no posts requested".
-pub fn get_post_data(
+pub async fn get_post_data(
     context: &CliContext,
     client: &Client,
     login: &Login,
@@ -743,7 +1419,7 @@ pub fn get_post_data(
             id
         );
         report_phase(context, format!("Fetching post {id}..."));
-        let Some(data) = request(context, client, login, &target) else {
+        let Some(data) = request(context, client, login, &target).await else {
             return Vec::new();
         };
         if let Err(e) = data.error_for_status_ref() {
@@ -751,7 +1427,10 @@ pub fn get_post_data(
             return Vec::new();
         }
 
-        let post = data.json::<Posts>().expect("Error reading response json.");
+        let post = data
+            .json::<Posts>()
+            .await
+            .expect("Error reading response json.");
         if post.posts.is_empty() {
             return Vec::new();
         }
@@ -767,58 +1446,39 @@ pub fn get_post_data(
 /// responsible for calling `.error_for_status_ref()` or similar. Panics if the
 /// request itself fails to send (network error), rather than returning a
 /// `Result`.
-pub fn send_request(client: &Client, login: &Login, target: &str) -> Response {
-    if !login.username.is_empty() && !login.api_key.is_empty() {
-        client
-            .get(target)
-            .basic_auth(login.username.clone(), Some(login.api_key.clone()))
-            .send()
-            .expect("Error getting response!")
-    } else {
-        client.get(target).send().expect("Error getting response!")
-    }
+pub async fn send_request(client: &Client, login: &Login, target: &str) -> Response {
+    authorize(client.get(target), login)
+        .send()
+        .await
+        .expect("Error getting response!")
 }
 
-fn request(context: &CliContext, client: &Client, login: &Login, target: &str) -> Option<Response> {
-    let Some(cancel) = context.cancel.clone() else {
-        return Some(send_request(client, login, target));
-    };
-    if cancel.load(Ordering::Relaxed) {
-        return None;
+/// Adds basic auth to `request` when `login` has both a username and an API
+/// key.
+fn authorize(request: RequestBuilder, login: &Login) -> RequestBuilder {
+    if login.is_authenticated() {
+        request.basic_auth(&login.username, Some(&login.api_key))
+    } else {
+        request
     }
+}
 
-    let client = client.clone();
-    let login = Login {
-        username: login.username.clone(),
-        api_key: login.api_key.clone(),
-    };
-    let target = target.to_owned();
-    let (tx, rx) = mpsc::channel();
-    thread::spawn(move || {
-        let result = if !login.username.is_empty() && !login.api_key.is_empty() {
-            client
-                .get(&target)
-                .basic_auth(login.username, Some(login.api_key))
-                .timeout(Duration::from_secs(30))
-                .send()
-        } else {
-            client.get(&target).timeout(Duration::from_secs(30)).send()
-        };
-        let _ = tx.send(result);
-    });
-
-    loop {
-        if cancel.load(Ordering::Relaxed) {
-            return None;
-        }
-        match rx.recv_timeout(Duration::from_millis(50)) {
-            Ok(Ok(response)) => return Some(response),
-            Ok(Err(error)) => {
-                error!("Error getting response: {error}");
-                return None;
-            }
-            Err(mpsc::RecvTimeoutError::Timeout) => {}
-            Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+/// An API request that gives up after 30 seconds, or as soon as
+/// `context.cancel` fires. Failures are logged and yield `None`.
+async fn request(
+    context: &CliContext,
+    client: &Client,
+    login: &Login,
+    target: &str,
+) -> Option<Response> {
+    let send = authorize(client.get(target), login)
+        .timeout(Duration::from_secs(30))
+        .send();
+    match cancellable(context.cancel.as_ref(), send).await? {
+        Ok(response) => Some(response),
+        Err(error) => {
+            error!("Error getting response: {error}");
+            None
         }
     }
 }
@@ -826,12 +1486,8 @@ fn request(context: &CliContext, client: &Client, login: &Login, target: &str) -
 fn report_phase(context: &CliContext, phase: String) {
     if let Some(observer) = &context.progress {
         observer(crate::DownloadProgress {
-            completed: 0,
-            failed: 0,
-            skipped: 0,
-            total: 0,
-            downloaded_amount: 0.0,
             phase: Some(phase),
+            ..Default::default()
         });
     }
 }
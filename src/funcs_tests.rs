@@ -1,6 +1,7 @@
 use super::*;
+use crate::runtime::block_on;
 use crate::tracker::Tracker;
-use crate::type_defs::api_defs::{Alternates, File as ApiFile, Sample, Tags};
+use crate::type_defs::api_defs::{Alternates, File as ApiFile, PoolData, Sample, Tags};
 
 fn dummy_post(id: u64) -> Post {
     Post {
@@ -15,6 +16,8 @@ fn dummy_post(id: u64) -> Post {
         },
         tags: Tags {
             artist: vec!["someartist".into()],
+            character: vec![],
+            species: vec![],
             general: vec![],
         },
         sample: Sample {
@@ -25,6 +28,8 @@ fn dummy_post(id: u64) -> Post {
             },
         },
         description: None,
+        flags: Default::default(),
+        rating: "s".into(),
     }
 }
 
@@ -133,7 +138,15 @@ fn download_skips_already_downloaded_file() {
         api_key: String::new(),
     };
 
-    let result = download(&client, &login, vec![post], None, &false, dir.path(), None);
+    let result = block_on(download(
+        &client,
+        &login,
+        vec![post],
+        None,
+        &false,
+        dir.path(),
+        None,
+    ));
 
     assert_eq!(result.amount_finished, 0);
     assert_eq!(result.amount_failed, 0);
@@ -153,7 +166,7 @@ fn download_skips_tracked_post() {
         api_key: String::new(),
     };
 
-    let result = download(
+    let result = block_on(download(
         &client,
         &login,
         vec![post],
@@ -161,7 +174,7 @@ fn download_skips_tracked_post() {
         &false,
         dir.path(),
         Some(&tracker),
-    );
+    ));
 
     assert_eq!(result.amount_finished, 0);
     assert_eq!(result.amount_failed, 0);
@@ -183,7 +196,7 @@ fn download_records_existing_file_in_tracker() {
         api_key: String::new(),
     };
 
-    let result = download(
+    let result = block_on(download(
         &client,
         &login,
         vec![post],
@@ -191,8 +204,151 @@ fn download_records_existing_file_in_tracker() {
         &false,
         dir.path(),
         Some(&tracker),
-    );
+    ));
 
     assert_eq!(result.amount_skipped, 1);
     assert!(tracker.contains(123));
 }
+
+#[test]
+fn download_tells_deleted_posts_from_hidden_ones() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut deleted = dummy_post(1);
+    deleted.file.url = None;
+    deleted.flags.deleted = true;
+    let mut hidden = dummy_post(2);
+    hidden.file.url = None;
+
+    let client = crate::commands::get_client();
+    let login = Login {
+        username: String::new(),
+        api_key: String::new(),
+    };
+
+    let result = block_on(download(
+        &client,
+        &login,
+        vec![deleted, hidden],
+        None,
+        &false,
+        dir.path(),
+        None,
+    ));
+
+    assert_eq!(result.amount_failed, 2);
+    let kinds = result
+        .records
+        .iter()
+        .map(|r| r.error.as_ref().expect("error").kind)
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, vec![FailureKind::Deleted, FailureKind::Blacklisted]);
+    assert!(kinds[0].is_permanent());
+    assert!(!kinds[1].is_permanent());
+}
+
+#[test]
+fn verify_md5_removes_files_that_do_not_match() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("file.jpg");
+    std::fs::write(&path, b"hello").expect("write");
+    let finished = || DownloadStatus {
+        finished: true,
+        downloaded_bytes: 5.0,
+        error: None,
+    };
+
+    let stat = block_on(verify_md5(
+        finished(),
+        &path,
+        Some("5D41402ABC4B2A76B9719D911017C592"),
+    ));
+    assert!(stat.finished);
+    assert!(path.exists());
+
+    let stat = block_on(verify_md5(finished(), &path, Some("0123456789abcdef")));
+    assert!(!stat.finished);
+    assert_eq!(stat.into_error().kind, FailureKind::Md5Mismatch);
+    assert!(!path.exists());
+}
+
+fn dummy_pool(id: u64, name: &str) -> PoolData {
+    PoolData {
+        id,
+        name: name.into(),
+        description: None,
+        post_ids: vec![],
+        post_count: 0,
+        category: "series".into(),
+        is_active: true,
+    }
+}
+
+#[test]
+fn linked_pool_ids_finds_unique_references_in_order() {
+    let description = "Previous: pool #12\nNext: Pool #345, see also pool #12 and pool #abc";
+    assert_eq!(linked_pool_ids(description), vec![12, 345]);
+    assert!(linked_pool_ids("no links here").is_empty());
+}
+
+#[test]
+fn pool_dir_name_sanitizes_and_falls_back_to_id() {
+    assert_eq!(
+        pool_dir_name(&dummy_pool(1, "Cloud_Jumping")),
+        "Cloud Jumping"
+    );
+    assert_eq!(pool_dir_name(&dummy_pool(2, "What?_A/B:_C.")), "What AB C");
+    assert_eq!(pool_dir_name(&dummy_pool(3, "???")), "pool-3");
+}
+
+#[test]
+fn json_list_accepts_arrays_and_empty_objects() {
+    let aliases: Vec<crate::type_defs::api_defs::TagAlias> = json_list(
+        serde_json::json!([{"antecedent_name": "old", "consequent_name": "new", "status": "active"}]),
+        "tag_aliases",
+    );
+    assert_eq!(aliases[0].antecedent_name, "old");
+    let empty: Vec<crate::type_defs::api_defs::TagAlias> =
+        json_list(serde_json::json!({"tag_aliases": []}), "tag_aliases");
+    assert!(empty.is_empty());
+}
+
+fn artist(id: u64, name: &str, other_names: &[&str]) -> ArtistData {
+    ArtistData {
+        id,
+        name: name.into(),
+        other_names: other_names.iter().map(|name| name.to_string()).collect(),
+        urls: vec![],
+    }
+}
+
+#[test]
+fn pick_artist_prefers_the_exact_name() {
+    let found = [
+        artist(1, "someone_else", &["someartist"]),
+        artist(2, "someartist", &[]),
+    ];
+    assert_eq!(pick_artist(&found, "someartist").map(|a| a.id), Ok(2));
+}
+
+#[test]
+fn pick_artist_takes_a_single_other_name_match() {
+    let found = [
+        artist(1, "someone_else", &["Some Artist"]),
+        artist(2, "someartist_fan", &[]),
+    ];
+    assert_eq!(pick_artist(&found, "some_artist").map(|a| a.id), Ok(1));
+}
+
+#[test]
+fn pick_artist_lists_candidates_when_unsure() {
+    let found = [
+        artist(1, "someartist_fan", &[]),
+        artist(2, "someone_else", &[]),
+    ];
+    let error = pick_artist(&found, "someartist").unwrap_err();
+    assert!(error.contains("someartist_fan, someone_else"), "{error}");
+    assert_eq!(
+        pick_artist(&[], "someartist").unwrap_err(),
+        "No artist found for 'someartist'."
+    );
+}
@@ -0,0 +1,333 @@
+//! A static HTML gallery of a download directory, for browsing a big
+//! download quickly: one `index.html` with a thumbnail grid, filters by tag
+//! and rating, and a link from each file to its post page. It needs nothing
+//! but the files next to it; thumbnails are kept in a `.e-cli-thumbs` folder.
+//!
+//! Posts' tags and ratings come from run manifests (see [`crate::manifest`]),
+//! or, for files they don't cover, from the `ComicInfo.xml` in the file's
+//! folder (see [`crate::comic_info`]).
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use image::codecs::jpeg::JpegEncoder;
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use tracing::warn;
+
+use crate::DownloadRecord;
+use crate::comic_info::{self, ComicInfo, escape};
+
+/// The gallery page, written into the download directory.
+pub const INDEX: &str = "index.html";
+
+/// The folder in the download directory that thumbnails are kept in, at the
+/// same relative paths as the files they show.
+pub const THUMBS_DIR: &str = ".e-cli-thumbs";
+
+/// Thumbnails fit in a square of this many pixels.
+const THUMB_SIZE: u32 = 320;
+
+/// Files that are shown in the gallery, by extension.
+const MEDIA: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "avif", "webm", "mp4", "swf",
+];
+
+/// Files that get a thumbnail; the rest are shown by their extension.
+const THUMBNAILED: &[&str] = &["jpg", "jpeg", "png", "gif", "webp"];
+
+/// A downloaded file in the gallery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// The file's path, relative to the download directory.
+    pub path: PathBuf,
+    /// The post it was downloaded from, read from the file name.
+    pub post_id: Option<u64>,
+    /// `s`, `q` or `e`, or empty if it's not known.
+    pub rating: String,
+    pub tags: Vec<String>,
+    /// The thumbnail's path relative to the download directory, once it's
+    /// been made.
+    pub thumbnail: Option<PathBuf>,
+}
+
+/// The post ID in a downloaded file's name: the number after its last `-`,
+/// e.g. 123 for `0012-artist-123.png`.
+pub fn post_id(name: &str) -> Option<u64> {
+    let (stem, _) = name.rsplit_once('.')?;
+    let (_, id) = stem.rsplit_once('-')?;
+    id.parse().ok()
+}
+
+/// The downloaded files under `dir`, sorted by path, with the tags and
+/// rating of their posts from `records` or their folder's `ComicInfo.xml`.
+/// e-cli's own files and folders (named with a leading `.`) and unfinished
+/// `.part` downloads are left out.
+pub fn items(dir: &Path, records: &[DownloadRecord]) -> io::Result<Vec<Item>> {
+    let records = records
+        .iter()
+        .filter(|record| !record.tags.is_empty() || !record.rating.is_empty())
+        .map(|record| (record.post_id, record))
+        .collect::<HashMap<_, _>>();
+    let mut sidecars: HashMap<PathBuf, Option<ComicInfo>> = HashMap::new();
+
+    let mut items = Vec::new();
+    let mut folders = vec![dir.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        for entry in fs::read_dir(&folder)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                folders.push(entry.path());
+                continue;
+            }
+            if !has_extension(&name, MEDIA) {
+                continue;
+            }
+
+            let post_id = post_id(&name);
+            let (rating, tags) = match post_id.and_then(|id| records.get(&id)) {
+                Some(record) => (record.rating.clone(), record.tags.clone()),
+                None => match sidecars
+                    .entry(folder.clone())
+                    .or_insert_with(|| comic_info::read(&folder))
+                {
+                    Some(info) => (
+                        rating_of(info.age_rating).to_owned(),
+                        info.artists.iter().chain(&info.tags).cloned().collect(),
+                    ),
+                    None => (String::new(), Vec::new()),
+                },
+            };
+            items.push(Item {
+                path: entry
+                    .path()
+                    .strip_prefix(dir)
+                    .map(Path::to_path_buf)
+                    .unwrap_or_else(|_| name.into()),
+                post_id,
+                rating,
+                tags,
+                thumbnail: None,
+            });
+        }
+    }
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(items)
+}
+
+/// The post rating a ComicInfo `AgeRating` stands for.
+fn rating_of(age_rating: &str) -> &'static str {
+    match age_rating {
+        "Adults Only 18+" => "e",
+        "Mature 17+" => "q",
+        "Everyone" => "s",
+        _ => "",
+    }
+}
+
+/// Whether `name` is a file that gets a thumbnail.
+pub(crate) fn thumbnailed(name: &str) -> bool {
+    has_extension(name, THUMBNAILED)
+}
+
+fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, extension)| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Makes the thumbnails of `items` on the rayon pool, keeping ones that are
+/// newer than their file. A file that can't be read is warned about and
+/// shown without one.
+pub fn write_thumbnails(dir: &Path, items: &mut [Item], progress: &ProgressBar) {
+    items.par_iter_mut().for_each(|item| {
+        let name = item.path.file_name().unwrap_or_default().to_string_lossy();
+        if thumbnailed(&name) {
+            let thumbnail = thumbnail_path(&item.path);
+            match write_thumbnail(&dir.join(&item.path), &dir.join(&thumbnail)) {
+                Ok(()) => item.thumbnail = Some(thumbnail),
+                Err(e) => warn!("No thumbnail for {}: {e}", item.path.display()),
+            }
+        }
+        progress.inc(1);
+    });
+}
+
+/// Where the thumbnail of the file at `path` (relative to the download
+/// directory) is kept, relative to the download directory.
+pub(crate) fn thumbnail_path(path: &Path) -> PathBuf {
+    let mut thumbnail = Path::new(THUMBS_DIR).join(path).into_os_string();
+    thumbnail.push(".jpg");
+    thumbnail.into()
+}
+
+/// Writes the thumbnail of `original` to `thumbnail`, unless one newer than
+/// the file is already there.
+pub(crate) fn write_thumbnail(original: &Path, thumbnail: &Path) -> Result<(), String> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    if let (Ok(thumbnail), Ok(original)) = (modified(thumbnail), modified(original))
+        && thumbnail >= original
+    {
+        return Ok(());
+    }
+
+    let image = image::open(original).map_err(|e| e.to_string())?;
+    let image = crate::export::flatten(image.thumbnail(THUMB_SIZE, THUMB_SIZE));
+    let mut encoded = Vec::new();
+    image
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, 80))
+        .map_err(|e| e.to_string())?;
+    if let Some(parent) = thumbnail.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    crate::state::write_atomic(thumbnail, encoded).map_err(|e| e.to_string())
+}
+
+/// The gallery page for `items`, titled `title`, linking posts on
+/// `api_source`.
+pub fn render(title: &str, items: &[Item], api_source: &str) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<header>\n\
+         <h1>{title}</h1>\n\
+         <input id=\"tags\" type=\"search\" placeholder=\"Tags, e.g. dragon -sketch\" autofocus>\n",
+        title = escape(title)
+    );
+    for (rating, label) in [
+        ("s", "Safe"),
+        ("q", "Questionable"),
+        ("e", "Explicit"),
+        ("", "Unrated"),
+    ] {
+        html.push_str(&format!(
+            "<label><input type=\"checkbox\" class=\"rating\" value=\"{rating}\" checked> {label}</label>\n"
+        ));
+    }
+    html.push_str("<span id=\"count\"></span>\n</header>\n<main>\n");
+
+    for item in items {
+        let href = url_path(&item.path);
+        let name = escape(&item.path.file_name().unwrap_or_default().to_string_lossy());
+        html.push_str(&format!(
+            "<figure class=\"item\" data-rating=\"{}\" data-tags=\"{}\">\n",
+            escape(&item.rating),
+            escape(&item.tags.join(" ").to_lowercase())
+        ));
+        match &item.thumbnail {
+            Some(thumbnail) => html.push_str(&format!(
+                "<a href=\"{href}\"><img src=\"{}\" alt=\"{name}\" title=\"{name}\" loading=\"lazy\"></a>\n",
+                url_path(thumbnail)
+            )),
+            None => {
+                let extension = item.path.extension().unwrap_or_default().to_string_lossy();
+                html.push_str(&format!(
+                    "<a class=\"file\" href=\"{href}\" title=\"{name}\">{}</a>\n",
+                    escape(&extension.to_uppercase())
+                ));
+            }
+        }
+        match item.post_id {
+            Some(id) => html.push_str(&format!(
+                "<figcaption><a href=\"https://{api_source}/posts/{id}\">#{id}</a></figcaption>\n"
+            )),
+            None => html.push_str(&format!("<figcaption>{name}</figcaption>\n")),
+        }
+        html.push_str("</figure>\n");
+    }
+    html.push_str(&format!(
+        "</main>\n<script>{SCRIPT}</script>\n</body>\n</html>\n"
+    ));
+    html
+}
+
+/// `path` as a relative URL, with each segment percent-encoded.
+fn url_path(path: &Path) -> String {
+    let segments = path.iter().map(|segment| {
+        let mut encoded = String::new();
+        for byte in segment.to_string_lossy().bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{byte:02X}"));
+            }
+        }
+        encoded
+    });
+    segments.collect::<Vec<_>>().join("/")
+}
+
+/// Makes the thumbnails for the files under `dir` and writes the gallery
+/// page, [`INDEX`], into it. Returns the number of files in the gallery.
+pub fn write(
+    dir: &Path,
+    records: &[DownloadRecord],
+    api_source: &str,
+    progress: &ProgressBar,
+) -> Result<usize, String> {
+    let mut items =
+        items(dir, records).map_err(|e| format!("Failed to read {}: {e}", dir.display()))?;
+    progress.set_length(items.len() as u64);
+    write_thumbnails(dir, &mut items, progress);
+
+    let title = fs::canonicalize(dir)
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "e-cli".to_owned());
+    let out = dir.join(INDEX);
+    crate::state::write_atomic(&out, render(&title, &items, api_source))
+        .map_err(|e| format!("Failed to write {}: {e}", out.display()))?;
+    Ok(items.len())
+}
+
+const STYLE: &str = "
+body { margin: 0; font-family: sans-serif; background: #1f2330; color: #e8e8e8; }
+header { position: sticky; top: 0; display: flex; flex-wrap: wrap; gap: 0.5em 1em;
+  align-items: center; padding: 0.75em 1em; background: #152f56; }
+h1 { margin: 0; font-size: 1.2em; }
+#tags { flex: 1; min-width: 12em; padding: 0.3em; }
+main { display: grid; grid-template-columns: repeat(auto-fill, minmax(180px, 1fr));
+  gap: 0.75em; padding: 1em; }
+.item { margin: 0; text-align: center; }
+.item[hidden] { display: none; }
+.item img, .file { display: block; width: 100%; height: 180px; object-fit: contain;
+  background: #2b3144; }
+.file { line-height: 180px; color: #e8e8e8; text-decoration: none; font-weight: bold; }
+figcaption { padding: 0.25em; font-size: 0.85em; overflow-wrap: anywhere; }
+a { color: #f2ac08; }
+";
+
+const SCRIPT: &str = r#"
+const search = document.getElementById("tags");
+const ratings = [...document.querySelectorAll(".rating")];
+const items = [...document.querySelectorAll(".item")];
+const count = document.getElementById("count");
+function filter() {
+  const terms = search.value.toLowerCase().split(/\s+/).filter(Boolean);
+  const shown = new Set(ratings.filter(r => r.checked).map(r => r.value));
+  let visible = 0;
+  for (const item of items) {
+    const tags = new Set(item.dataset.tags.split(" "));
+    const match = shown.has(item.dataset.rating) && terms.every(term =>
+      term.startsWith("-") ? !tags.has(term.slice(1)) : tags.has(term));
+    item.hidden = !match;
+    if (match) visible++;
+  }
+  count.textContent = visible + " of " + items.length;
+}
+search.addEventListener("input", filter);
+ratings.forEach(rating => rating.addEventListener("change", filter));
+filter();
+"#;
+
+#[cfg(test)]
+#[path = "gallery_tests.rs"]
+mod tests;
@@ -0,0 +1,129 @@
+use super::*;
+use crate::RecordStatus;
+use image::{ImageFormat, Rgb, RgbImage};
+
+fn record(post_id: u64, rating: &str, tags: &[&str]) -> DownloadRecord {
+    DownloadRecord {
+        post_id,
+        source_url: None,
+        md5: None,
+        artist: "someartist".into(),
+        rating: rating.into(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        extension: "png".into(),
+        local_filename: None,
+        status: RecordStatus::Completed,
+        bytes: 0,
+        error: None,
+        derived: None,
+    }
+}
+
+/// A download directory with a loose image and video, a pool folder with a
+/// `ComicInfo.xml`, and e-cli's own files.
+fn library() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = dir.path().join("Cloud Jumping");
+    fs::create_dir(&pool).expect("mkdir");
+    for path in [
+        dir.path().join("someartist-1.png"),
+        pool.join("0001-someartist-3.png"),
+    ] {
+        RgbImage::from_pixel(640, 320, Rgb([200, 40, 40]))
+            .save_with_format(path, ImageFormat::Png)
+            .expect("write png");
+    }
+    let info = ComicInfo {
+        title: "Cloud Jumping".into(),
+        artists: vec!["someartist".into()],
+        tags: vec!["clouds".into()],
+        age_rating: "Mature 17+",
+        ..Default::default()
+    };
+    fs::write(pool.join(comic_info::FILE_NAME), info.to_xml()).expect("write");
+    fs::write(dir.path().join("someartist-2.webm"), "video").expect("write");
+    fs::write(dir.path().join("someartist-4.png.part"), "partial").expect("write");
+    fs::write(dir.path().join(".e-cli-md5.json"), "{}").expect("write");
+    dir
+}
+
+#[test]
+fn post_ids_come_from_file_names() {
+    assert_eq!(post_id("0012-artist-123.png"), Some(123));
+    assert_eq!(post_id("some-artist-45.webm"), Some(45));
+    assert_eq!(post_id("cover.png"), None);
+    assert_eq!(post_id("artist-12"), None);
+}
+
+#[test]
+fn items_take_metadata_from_manifests_then_comic_info() {
+    let dir = library();
+    let items = items(dir.path(), &[record(1, "e", &["someartist", "dragon"])]).expect("items");
+
+    let paths = items
+        .iter()
+        .map(|item| item.path.to_string_lossy().replace('\\', "/"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            "Cloud Jumping/0001-someartist-3.png",
+            "someartist-1.png",
+            "someartist-2.webm"
+        ]
+    );
+    assert_eq!(items[0].post_id, Some(3));
+    assert_eq!(items[0].rating, "q");
+    assert_eq!(items[0].tags, vec!["someartist", "clouds"]);
+    assert_eq!(items[1].rating, "e");
+    assert_eq!(items[1].tags, vec!["someartist", "dragon"]);
+    assert_eq!(items[2].rating, "");
+    assert!(items[2].tags.is_empty());
+}
+
+#[test]
+fn gallery_links_files_thumbnails_and_posts() {
+    let dir = library();
+    let count = write(
+        dir.path(),
+        &[record(1, "s", &["dragon"])],
+        "e926.net",
+        &ProgressBar::hidden(),
+    )
+    .expect("write gallery");
+    assert_eq!(count, 3);
+
+    let thumbnail = dir
+        .path()
+        .join(THUMBS_DIR)
+        .join("Cloud Jumping")
+        .join("0001-someartist-3.png.jpg");
+    assert_eq!(
+        image::image_dimensions(&thumbnail).expect("thumbnail"),
+        (THUMB_SIZE, THUMB_SIZE / 2)
+    );
+    assert!(
+        !dir.path()
+            .join(THUMBS_DIR)
+            .join("someartist-2.webm.jpg")
+            .exists()
+    );
+
+    let html = fs::read_to_string(dir.path().join(INDEX)).expect("read index");
+    assert!(html.contains("href=\"Cloud%20Jumping/0001-someartist-3.png\""));
+    assert!(html.contains("src=\".e-cli-thumbs/Cloud%20Jumping/0001-someartist-3.png.jpg\""));
+    assert!(html.contains("href=\"https://e926.net/posts/1\""));
+    assert!(html.contains("data-rating=\"s\" data-tags=\"dragon\""));
+    assert!(html.contains(">WEBM</a>"));
+}
+
+#[test]
+fn thumbnails_are_kept_until_their_file_changes() {
+    let dir = library();
+    write(dir.path(), &[], "e926.net", &ProgressBar::hidden()).expect("write gallery");
+    let thumbnail = dir.path().join(THUMBS_DIR).join("someartist-1.png.jpg");
+    fs::write(&thumbnail, "kept").expect("write");
+
+    write(dir.path(), &[], "e926.net", &ProgressBar::hidden()).expect("write again");
+    assert_eq!(fs::read_to_string(&thumbnail).expect("read"), "kept");
+}
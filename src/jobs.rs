@@ -0,0 +1,519 @@
+//! Downloads started in the background, from the local web interface (see
+//! [`crate::server`]) or the control API (see [`crate::daemon`]). They go
+//! through the same [`commands`] functions as the CLI, one at a time, and
+//! hold the download directory's [`RunLock`] while they run, like any other
+//! run.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::config::{Config, GlobalConfig, PresetConfig};
+use crate::credentials;
+use crate::runtime;
+use crate::state::RunLock;
+use crate::tracker::Tracker;
+use crate::{CliContext, DownloadProgress, DownloadStatistics, Login, commands, funcs};
+
+/// Called with the statistics of every job that ran, once it's done.
+pub type FinishedObserver = Arc<dyn Fn(&DownloadStatistics) + Send + Sync>;
+
+/// How many [`JobEvent`]s [`Jobs::subscribe`]rs may fall behind by before
+/// they miss some.
+const UPDATES: usize = 1024;
+
+/// A download to run, as posted to the server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobRequest {
+    /// A user's favourites, like `d-favs`.
+    Favs {
+        username: String,
+        #[serde(default)]
+        tags: String,
+        #[serde(default = "default_count")]
+        count: u32,
+        #[serde(default)]
+        random: bool,
+        pages: Option<i64>,
+    },
+    /// A tag search, like `d-tags`. Fetches one page unless `pages` says
+    /// otherwise.
+    Tags {
+        tags: String,
+        #[serde(default = "default_count")]
+        count: u32,
+        #[serde(default)]
+        random: bool,
+        pages: Option<i64>,
+    },
+    /// Pools by ID or name, like `d-pool`.
+    Pool {
+        pools: Vec<String>,
+        #[serde(default)]
+        series: bool,
+    },
+    /// A preset from `config.toml`, like `preset`. It downloads into the
+    /// jobs' directory with their tracking file, and its own `dir` and
+    /// `track_file` are left out. It signs in with its `account`, if it has
+    /// one, and with the jobs' login otherwise.
+    Preset {
+        name: String,
+        count: Option<u32>,
+        #[serde(default)]
+        random: bool,
+    },
+}
+
+fn default_count() -> u32 {
+    50
+}
+
+impl JobRequest {
+    /// Checks the request the way [`crate::cli::validate_args`] checks the
+    /// matching command.
+    pub fn validate(&self) -> Result<(), String> {
+        let count = match self {
+            JobRequest::Favs {
+                username, count, ..
+            } => {
+                if username.trim().is_empty() {
+                    return Err("A username is required.".to_owned());
+                }
+                Some(*count)
+            }
+            JobRequest::Tags { tags, count, .. } => {
+                if tags.trim().is_empty() {
+                    return Err("Tags are required.".to_owned());
+                }
+                Some(*count)
+            }
+            JobRequest::Pool { pools, .. } => {
+                if pools.iter().all(|pool| pool.trim().is_empty()) {
+                    return Err("A pool ID or name is required.".to_owned());
+                }
+                None
+            }
+            JobRequest::Preset { name, count, .. } => {
+                if name.trim().is_empty() {
+                    return Err("A preset name is required.".to_owned());
+                }
+                *count
+            }
+        };
+        if let Some(count) = count
+            && !(1..=250).contains(&count)
+        {
+            return Err(format!("The count must be between 1 and 250, not {count}."));
+        }
+        Ok(())
+    }
+
+    /// A short description for job lists, e.g. `tags "dragon"`.
+    pub fn describe(&self) -> String {
+        match self {
+            JobRequest::Favs { username, .. } => format!("favourites of {username}"),
+            JobRequest::Tags { tags, .. } => format!("tags \"{tags}\""),
+            JobRequest::Pool { pools, .. } => format!("pools {}", pools.join(", ")),
+            JobRequest::Preset { name, .. } => format!("preset {name}"),
+        }
+    }
+}
+
+/// Where a job is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for the job before it to finish.
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+/// A job and its progress, as listed by [`Jobs::list`] and sent to
+/// [`Jobs::subscribe`]rs whenever it changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub description: String,
+    pub state: JobState,
+    pub completed: i64,
+    pub failed: i64,
+    pub skipped: i64,
+    pub total: usize,
+    pub transferred_bytes: u64,
+    /// What the job is doing, e.g. fetching pages.
+    pub phase: Option<String>,
+    /// Why the job failed.
+    pub error: Option<String>,
+}
+
+/// A progress report of a running job, as its download made it.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobProgress {
+    /// The ID of the job.
+    pub job: u64,
+    #[serde(flatten)]
+    pub progress: DownloadProgress,
+}
+
+/// What [`Jobs::subscribe`]rs are sent.
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    /// A job was queued or changed.
+    Job(JobInfo),
+    /// A running job reported progress, before the [`JobEvent::Job`] it
+    /// brings about.
+    Progress(Box<JobProgress>),
+}
+
+struct Job {
+    info: JobInfo,
+    cancel: CancellationToken,
+}
+
+/// Runs [`JobRequest`]s in the background, in the order they were started.
+pub struct Jobs {
+    /// The settings every job runs with; its cancellation token, if any,
+    /// cancels all jobs.
+    context: CliContext,
+    login: Login,
+    dir: PathBuf,
+    tracker: Option<Arc<Tracker>>,
+    finished: Option<FinishedObserver>,
+    /// The presets [`JobRequest::Preset`] names, from `config.toml`.
+    presets: RwLock<HashMap<String, PresetConfig>>,
+    /// The settings the accounts of presets are looked up with (see
+    /// [`credentials::load`]).
+    global: RwLock<GlobalConfig>,
+    jobs: Mutex<Vec<Job>>,
+    /// The threads of the jobs not yet joined, see [`Jobs::stop`].
+    threads: Mutex<Vec<thread::JoinHandle<()>>>,
+    updates: broadcast::Sender<JobEvent>,
+    /// Held by the running job, so jobs run one at a time.
+    turn: tokio::sync::Mutex<()>,
+}
+
+impl Jobs {
+    /// Runs jobs into `dir` with the settings and login of `context` and
+    /// `login`, recording downloaded posts in `tracker`. `finished` is told
+    /// about every job that ran.
+    pub fn new(
+        context: CliContext,
+        login: Login,
+        dir: PathBuf,
+        tracker: Option<Arc<Tracker>>,
+        finished: Option<FinishedObserver>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            context,
+            login,
+            dir,
+            tracker,
+            finished,
+            presets: RwLock::default(),
+            global: RwLock::default(),
+            jobs: Mutex::new(Vec::new()),
+            threads: Mutex::new(Vec::new()),
+            updates: broadcast::channel(UPDATES).0,
+            turn: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Takes the presets that [`JobRequest::Preset`]s run, and what their
+    /// accounts are looked up with, from `config`, e.g. once `config.toml`
+    /// has been changed. Jobs already started keep their presets.
+    pub fn set_config(&self, config: &Config) {
+        *self.presets.write().unwrap() = config.presets.clone();
+        *self.global.write().unwrap() = config.global.clone();
+    }
+
+    /// The state of every job each time it changes, and every progress
+    /// report of the running one, from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.updates.subscribe()
+    }
+
+    /// Queues `request` and returns its job ID.
+    pub fn start(self: &Arc<Self>, request: JobRequest) -> Result<u64, String> {
+        request.validate()?;
+        let preset = match &request {
+            JobRequest::Preset { name, .. } => Some(self.preset(name)?),
+            _ => None,
+        };
+        let cancel = match &self.context.cancel {
+            Some(cancel) => cancel.child_token(),
+            None => CancellationToken::new(),
+        };
+        let info = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let info = JobInfo {
+                id: jobs.len() as u64 + 1,
+                description: request.describe(),
+                state: JobState::Queued,
+                completed: 0,
+                failed: 0,
+                skipped: 0,
+                total: 0,
+                transferred_bytes: 0,
+                phase: None,
+                error: None,
+            };
+            jobs.push(Job {
+                info: info.clone(),
+                cancel: cancel.clone(),
+            });
+            info
+        };
+        let id = info.id;
+        let _ = self.updates.send(JobEvent::Job(info));
+        info!("Queued job {id}: {}.", request.describe());
+        // On a thread of its own, like the TUI's downloads: the download
+        // futures borrow too much to be spawned onto the runtime.
+        let jobs = Arc::clone(self);
+        let thread =
+            thread::spawn(move || runtime::block_on(jobs.run(id, request, preset, cancel)));
+        let mut threads = self.threads.lock().unwrap();
+        threads.retain(|thread| !thread.is_finished());
+        threads.push(thread);
+        Ok(id)
+    }
+
+    /// The preset `name`, if it's one that can run.
+    fn preset(&self, name: &str) -> Result<PresetConfig, String> {
+        let presets = self.presets.read().unwrap();
+        let preset = presets
+            .get(name.trim())
+            .ok_or_else(|| format!("Unknown preset '{name}'."))?;
+        if preset.source.as_deref() == Some("set") && preset.set.is_none() {
+            return Err(format!(
+                "Preset '{name}' uses source = \"set\" but has no set configured."
+            ));
+        }
+        Ok(preset.clone())
+    }
+
+    /// Every job started so far, oldest first.
+    pub fn list(&self) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter().map(|job| job.info.clone()).collect()
+    }
+
+    /// Cancels job `id`, if it's still queued or running. Returns whether
+    /// there was such a job.
+    pub fn cancel(&self, id: u64) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.iter().find(|job| job.info.id == id) {
+            Some(job) if matches!(job.info.state, JobState::Queued | JobState::Running) => {
+                job.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancels every job still queued or running and waits for them to stop,
+    /// so none is cut off mid-transfer when the program exits. Blocks.
+    pub fn stop(&self) {
+        for job in self.jobs.lock().unwrap().iter() {
+            job.cancel.cancel();
+        }
+        let threads = std::mem::take(&mut *self.threads.lock().unwrap());
+        for thread in threads {
+            if thread.join().is_err() {
+                error!("A job's thread panicked.");
+            }
+        }
+    }
+
+    /// Passes `progress` of job `id` on to subscribers and counts it in the
+    /// job's [`JobInfo`].
+    pub(crate) fn report(&self, id: u64, progress: DownloadProgress) {
+        let _ = self.updates.send(JobEvent::Progress(Box::new(JobProgress {
+            job: id,
+            progress: progress.clone(),
+        })));
+        self.update(id, |info| {
+            info.completed = progress.completed;
+            info.failed = progress.failed;
+            info.skipped = progress.skipped;
+            info.total = progress.total;
+            info.transferred_bytes = progress.transferred_bytes;
+            if progress.phase.is_some() {
+                info.phase = progress.phase;
+            }
+        })
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut JobInfo)) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.iter_mut().find(|job| job.info.id == id) {
+            update(&mut job.info);
+            // Nobody listening is fine.
+            let _ = self.updates.send(JobEvent::Job(job.info.clone()));
+        }
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        id: u64,
+        request: JobRequest,
+        preset: Option<PresetConfig>,
+        cancel: CancellationToken,
+    ) {
+        let _turn = self.turn.lock().await;
+        if cancel.is_cancelled() {
+            return self.update(id, |info| info.state = JobState::Cancelled);
+        }
+        let login = match preset.as_ref().and_then(|preset| preset.account.clone()) {
+            Some(account) => {
+                let global = self.global.read().unwrap().clone();
+                // May run the credential command.
+                let login =
+                    tokio::task::spawn_blocking(move || credentials::load(&global, Some(&account)))
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()));
+                match login {
+                    Ok(login) => login.unwrap_or_default(),
+                    Err(e) => return self.fail(id, e),
+                }
+            }
+            None => self.login.clone(),
+        };
+        let dir = self.dir.clone();
+        let lock = tokio::task::spawn_blocking(move || {
+            funcs::ensure_dl_dir(&dir);
+            RunLock::acquire(&dir, false)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+        let _lock = match lock {
+            Ok(lock) => lock,
+            Err(e) => return self.fail(id, e),
+        };
+        self.update(id, |info| info.state = JobState::Running);
+
+        let jobs = Arc::clone(&self);
+        let progress = move |progress: DownloadProgress| jobs.report(id, progress);
+        let pages = match &request {
+            JobRequest::Favs { pages, .. } => *pages,
+            JobRequest::Tags { pages, .. } => Some(pages.unwrap_or(1)),
+            JobRequest::Pool { .. } => None,
+            JobRequest::Preset { .. } => preset.as_ref().and_then(|preset| preset.pages),
+        };
+        let setting = |setting: fn(&PresetConfig) -> Option<bool>, default: bool| {
+            preset.as_ref().and_then(setting).unwrap_or(default)
+        };
+        let context = CliContext {
+            pages: pages.unwrap_or(self.context.pages),
+            nsfw: setting(|preset| preset.nsfw, self.context.nsfw),
+            lower_quality: setting(|preset| preset.lower_quality, self.context.lower_quality),
+            file_bars: false,
+            cancel: Some(cancel.clone()),
+            progress: Some(Arc::new(progress)),
+            ..self.context.clone()
+        };
+        let mp = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+        let tracker = self.tracker.as_deref();
+
+        let stats = match &request {
+            JobRequest::Favs {
+                username,
+                tags,
+                count,
+                random,
+                ..
+            } => {
+                commands::download_favourites(
+                    &context,
+                    &login,
+                    username.trim(),
+                    count,
+                    random,
+                    tags,
+                    &mp,
+                    &self.dir,
+                    tracker,
+                )
+                .await
+            }
+            JobRequest::Tags {
+                tags,
+                count,
+                random,
+                ..
+            } => {
+                commands::download_search(
+                    &context, &login, tags, count, random, &mp, &self.dir, tracker,
+                )
+                .await
+            }
+            JobRequest::Pool { pools, series } => {
+                match commands::resolve_pools(&context, &login, pools, *series).await {
+                    Ok(pools) => {
+                        commands::download_pools(
+                            &context, &login, &pools, None, &mp, &self.dir, tracker,
+                        )
+                        .await
+                    }
+                    Err(e) => return self.fail(id, e),
+                }
+            }
+            JobRequest::Preset { count, random, .. } => {
+                let preset = preset.unwrap_or_default();
+                commands::download_preset(
+                    &context,
+                    &login,
+                    &preset,
+                    &count.or(preset.count).unwrap_or(5),
+                    &(*random || preset.random.unwrap_or(false)),
+                    &mp,
+                    &self.dir,
+                    tracker,
+                )
+                .await
+            }
+        };
+
+        self.update(id, |info| {
+            info.completed = stats.completed;
+            info.failed = stats.failed;
+            info.skipped = stats.skipped;
+            info.total = stats.total;
+            info.phase = None;
+            info.state = if cancel.is_cancelled() {
+                JobState::Cancelled
+            } else {
+                JobState::Finished
+            };
+        });
+        info!(
+            "Job {id} done: {} downloaded, {} failed, {} skipped.",
+            stats.completed, stats.failed, stats.skipped
+        );
+        if let Some(finished) = &self.finished {
+            finished(&stats);
+        }
+    }
+
+    fn fail(&self, id: u64, e: String) {
+        error!("Job {id} failed: {e}");
+        self.update(id, |info| {
+            info.state = JobState::Failed;
+            info.error = Some(e);
+        });
+    }
+}
+
+#[cfg(test)]
+#[path = "jobs_tests.rs"]
+mod tests;
@@ -0,0 +1,245 @@
+use super::*;
+use crate::{DownloadEvent, SkipReason};
+
+fn request(json: &str) -> JobRequest {
+    serde_json::from_str(json).expect("valid job request")
+}
+
+#[test]
+fn requests_fill_in_defaults() {
+    assert_eq!(
+        request(r#"{"kind": "tags", "tags": "dragon"}"#),
+        JobRequest::Tags {
+            tags: "dragon".into(),
+            count: 50,
+            random: false,
+            pages: None,
+        }
+    );
+    assert_eq!(
+        request(r#"{"kind": "pool", "pools": ["123", "Cloud Jumping"]}"#),
+        JobRequest::Pool {
+            pools: vec!["123".into(), "Cloud Jumping".into()],
+            series: false,
+        }
+    );
+}
+
+#[test]
+fn unknown_kinds_are_rejected() {
+    assert!(serde_json::from_str::<JobRequest>(r#"{"kind": "artist"}"#).is_err());
+}
+
+#[test]
+fn validate_checks_required_fields_and_count() {
+    assert!(
+        request(r#"{"kind": "favs", "username": " "}"#)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        request(r#"{"kind": "tags", "tags": ""}"#)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        request(r#"{"kind": "pool", "pools": [""]}"#)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        request(r#"{"kind": "tags", "tags": "dragon", "count": 0}"#)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        request(r#"{"kind": "tags", "tags": "dragon", "count": 251}"#)
+            .validate()
+            .is_err()
+    );
+    assert!(
+        request(r#"{"kind": "favs", "username": "someone", "count": 250}"#)
+            .validate()
+            .is_ok()
+    );
+}
+
+#[test]
+fn describe_names_the_download() {
+    assert_eq!(
+        request(r#"{"kind": "favs", "username": "someone"}"#).describe(),
+        "favourites of someone"
+    );
+    assert_eq!(
+        request(r#"{"kind": "tags", "tags": "dragon solo"}"#).describe(),
+        "tags \"dragon solo\""
+    );
+}
+
+/// Jobs whose runs are cancelled before they start, so they never reach the
+/// network.
+fn context() -> CliContext {
+    CliContext {
+        verbose: false,
+        nsfw: false,
+        lower_quality: false,
+        pages: 1,
+        file_bars: false,
+        num_threads: 1,
+        retries: 0,
+        duplicate_index: None,
+        cancel: Some(CancellationToken::new()),
+        rate_limit: None,
+        budget: None,
+        disk: None,
+        postprocess: None,
+        progress: None,
+    }
+}
+
+fn cancelled_jobs(dir: &std::path::Path) -> Arc<Jobs> {
+    let context = context();
+    context.cancel.as_ref().unwrap().cancel();
+    Jobs::new(context, Login::default(), dir.to_path_buf(), None, None)
+}
+
+#[test]
+fn presets_must_be_known_to_start() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jobs = cancelled_jobs(dir.path());
+    let preset = || request(r#"{"kind": "preset", "name": "art"}"#);
+    assert_eq!(
+        jobs.start(preset()),
+        Err("Unknown preset 'art'.".to_owned())
+    );
+
+    jobs.set_config(&Config {
+        presets: HashMap::from([(
+            "art".to_owned(),
+            PresetConfig {
+                tags: Some("dragon".into()),
+                ..Default::default()
+            },
+        )]),
+        ..Default::default()
+    });
+    assert_eq!(jobs.start(preset()), Ok(1));
+    assert_eq!(jobs.list()[0].description, "preset art");
+}
+
+#[test]
+fn subscribers_follow_a_job_to_the_end() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jobs = cancelled_jobs(dir.path());
+    let mut updates = jobs.subscribe();
+    let id = jobs
+        .start(request(r#"{"kind": "tags", "tags": "dragon"}"#))
+        .expect("start");
+
+    let state = |event| match event {
+        Ok(JobEvent::Job(info)) => (info.id, info.state),
+        other => panic!("expected a job event, got {other:?}"),
+    };
+    assert_eq!(state(updates.blocking_recv()), (id, JobState::Queued));
+    assert_eq!(state(updates.blocking_recv()), (id, JobState::Cancelled));
+    assert!(!jobs.cancel(id));
+}
+
+#[test]
+fn stop_waits_for_jobs_to_end() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jobs = cancelled_jobs(dir.path());
+    for _ in 0..3 {
+        jobs.start(request(r#"{"kind": "tags", "tags": "dragon"}"#))
+            .expect("start");
+    }
+    jobs.stop();
+    assert!(
+        jobs.list()
+            .iter()
+            .all(|info| info.state == JobState::Cancelled)
+    );
+}
+
+#[test]
+fn progress_reaches_subscribers_with_the_job_id() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jobs = cancelled_jobs(dir.path());
+    let id = jobs
+        .start(request(r#"{"kind": "tags", "tags": "dragon"}"#))
+        .expect("start");
+    jobs.stop();
+    let mut updates = jobs.subscribe();
+    jobs.report(
+        id,
+        DownloadProgress {
+            skipped: 1,
+            total: 2,
+            event: Some(DownloadEvent::PostSkipped {
+                post_id: 7,
+                reason: SkipReason::Tracked,
+            }),
+            ..Default::default()
+        },
+    );
+
+    let progress = match updates.blocking_recv() {
+        Ok(JobEvent::Progress(progress)) => progress,
+        other => panic!("expected progress, got {other:?}"),
+    };
+    assert_eq!(
+        serde_json::to_value(&progress).expect("json")["event"],
+        serde_json::json!({"kind": "post_skipped", "post_id": 7, "reason": "tracked"})
+    );
+    assert_eq!(progress.job, id);
+    match updates.blocking_recv() {
+        Ok(JobEvent::Job(info)) => assert_eq!((info.skipped, info.total), (1, 2)),
+        other => panic!("expected a job event, got {other:?}"),
+    }
+}
+
+#[test]
+fn preset_jobs_fail_without_their_account() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let jobs = Jobs::new(
+        context(),
+        Login::default(),
+        dir.path().to_path_buf(),
+        None,
+        None,
+    );
+    let mut config = Config::default();
+    config.global.credential_command = Some("exit 3".into());
+    config.presets.insert(
+        "team".into(),
+        PresetConfig {
+            tags: Some("dragon".into()),
+            account: Some("e-cli-test-missing-account".into()),
+            ..Default::default()
+        },
+    );
+    jobs.set_config(&config);
+    let mut updates = jobs.subscribe();
+    let id = jobs
+        .start(request(r#"{"kind": "preset", "name": "team"}"#))
+        .expect("start");
+
+    let failed = loop {
+        match updates.blocking_recv() {
+            Ok(JobEvent::Job(info)) if info.state == JobState::Failed => break info,
+            Ok(JobEvent::Job(info)) => assert_ne!(info.state, JobState::Finished),
+            Ok(_) => {}
+            Err(e) => panic!("{e}"),
+        }
+    };
+    assert_eq!(failed.id, id);
+    assert!(
+        failed
+            .error
+            .as_deref()
+            .is_some_and(|e| e.contains("credential_command")),
+        "{:?}",
+        failed.error
+    );
+    jobs.stop();
+}
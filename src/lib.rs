@@ -6,21 +6,44 @@
 //! here is usable directly by another Rust program (e.g. a backend service or GUI)
 //! without going through a subprocess. Start with [`commands`] for the high-level
 //! operations (`download_favourites`, `download_search`, `download_pool`,
-//! `zip_downloads`); [`funcs`] holds the lower-level HTTP/filesystem building
-//! blocks those are made of, and [`tracker`] the optional record of
-//! already-downloaded posts.
+//! `zip_downloads`); [`downloader`] runs the actual concurrent download for all
+//! of them, [`funcs`] holds the lower-level HTTP/filesystem building blocks
+//! those are made of, and [`tracker`] the optional record of already-downloaded
+//! posts.
+//!
+//! The download operations are `async` and run on tokio. Synchronous callers
+//! can use the same operations through [`blocking`], which runs them on a
+//! shared runtime (see [`runtime`]).
 
+pub mod archive;
+pub mod blocking;
+pub mod budget;
 pub mod cli;
+pub mod comic_info;
 pub mod commands;
 pub mod config;
+pub mod credentials;
+#[cfg(feature = "server")]
+pub mod daemon;
+pub mod downloader;
 pub mod duplicate;
+pub mod export;
 pub mod failure_manifest;
 pub mod funcs;
+pub mod gallery;
+pub mod jobs;
 pub mod manifest;
+pub mod output;
+pub mod postprocess;
+pub mod runtime;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod state;
 pub mod tracker;
 pub mod type_defs;
 pub mod update;
 
+pub use downloader::Downloader;
 pub use tracker::Tracker;
 
 /// The `User-Agent` header sent with every HTTP request, e.g. `e-cli/0.4.3`.
@@ -39,14 +62,37 @@ pub struct DownloadStatistics {
     /// the target file already existed on disk, or the post was recorded in
     /// the tracking file (see [`tracker::Tracker`]).
     pub skipped: i64,
-    /// Total number of posts considered (`completed + failed + skipped`).
+    /// Number of posts not attempted because a download budget was reached
+    /// or disk space ran low (see [`budget::Budget`] and
+    /// [`budget::DiskReserve`]).
+    pub remaining: i64,
+    /// Number of posts left undone because the download was cancelled (see
+    /// [`CliContext::cancel`]).
+    pub cancelled: i64,
+    /// Total number of posts considered
+    /// (`completed + failed + skipped + remaining + cancelled`).
     pub total: usize,
     /// Total bytes written across all successfully downloaded files.
     pub downloaded_amount: f64,
     pub records: Vec<DownloadRecord>,
 }
 
-#[derive(Clone, Debug)]
+impl DownloadStatistics {
+    /// Adds `other`'s counts and records to `self`, for operations made up of
+    /// several downloads (e.g. [`commands::download_pools`]).
+    pub fn merge(&mut self, other: DownloadStatistics) {
+        self.completed += other.completed;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.remaining += other.remaining;
+        self.cancelled += other.cancelled;
+        self.total += other.total;
+        self.downloaded_amount += other.downloaded_amount;
+        self.records.extend(other.records);
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
 pub struct DownloadProgress {
     pub completed: i64,
     pub failed: i64,
@@ -54,6 +100,215 @@ pub struct DownloadProgress {
     pub total: usize,
     pub downloaded_amount: f64,
     pub phase: Option<String>,
+    /// Bytes received so far in this run, counting files still in flight
+    /// (unlike `downloaded_amount`, which only counts finished files).
+    pub transferred_bytes: u64,
+    /// What prompted this report, if it was a single page or post. The
+    /// counters alongside it are the ones current when it happened.
+    pub event: Option<DownloadEvent>,
+    /// The full record of the post, on reports of a post's outcome
+    /// ([`DownloadEvent::PostCompleted`], `PostSkipped` and `PostFailed`).
+    pub record: Option<DownloadRecord>,
+}
+
+/// Something that happened to a single page or post during a download,
+/// delivered in [`DownloadProgress::event`].
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum DownloadEvent {
+    /// A page of `posts` posts arrived from the API; `page` counts from 1.
+    PageFetched {
+        page: usize,
+        posts: usize,
+    },
+    /// The post was taken up for download, once a download slot was free.
+    PostQueued {
+        post_id: u64,
+    },
+    /// The first response for the post's file arrived.
+    PostStarted {
+        post_id: u64,
+    },
+    /// More of a file was written. Sent at most every
+    /// [`downloader::BYTE_REPORT_INTERVAL`] across the whole run.
+    BytesProgressed(FileProgress),
+    /// A transfer failed and will be tried again after `delay`.
+    RetryScheduled {
+        post_id: u64,
+        /// The retry about to happen, counting from 1.
+        attempt: u32,
+        reason: String,
+        #[serde(rename = "delay_ms", serialize_with = "as_millis")]
+        delay: std::time::Duration,
+    },
+    PostCompleted {
+        post_id: u64,
+        file_name: String,
+        bytes: u64,
+    },
+    PostSkipped {
+        post_id: u64,
+        reason: SkipReason,
+    },
+    PostFailed {
+        post_id: u64,
+        error: DownloadError,
+    },
+}
+
+fn as_millis<S: serde::Serializer>(
+    delay: &std::time::Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(delay.as_millis() as u64)
+}
+
+/// Why a post wasn't downloaded, in [`DownloadEvent::PostSkipped`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// The post is recorded in the tracking file.
+    Tracked,
+    /// Its file already exists in the output directory.
+    Exists,
+    /// A file with the same MD5 was already downloaded.
+    Duplicate,
+    /// The site filtered the post out: it's blacklisted, so its file is
+    /// hidden from requests without a login. Its record says why, and
+    /// `retry-failed --login` picks it up.
+    Filtered,
+    /// The download budget was reached first; the post is left for later.
+    BudgetReached,
+    /// Free disk space ran below the reserve first; the post is left for
+    /// later.
+    LowDiskSpace,
+    /// The download was cancelled first; the post is left for later.
+    Cancelled,
+}
+
+/// What became of a post, in [`DownloadRecord::status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Completed,
+    Failed,
+    /// Tracked already, or its file exists.
+    Skipped,
+    /// A file with the same MD5 was already downloaded.
+    Duplicate,
+    /// Left for a later run by `--max-bytes`/`--max-files` or `--min-free`.
+    Remaining,
+    Cancelled,
+}
+
+/// Why a post failed or was left undone, in [`DownloadError::kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum FailureKind {
+    /// The server answered with a non-success status.
+    Http {
+        status: u16,
+    },
+    /// The request or transfer timed out.
+    Timeout,
+    /// The connection couldn't be made, or dropped mid-transfer.
+    ConnectionReset,
+    /// Writing the file failed.
+    Io,
+    /// The downloaded file's MD5 didn't match the post's; the file was
+    /// removed.
+    Md5Mismatch,
+    /// The post was deleted, so it has no file to download.
+    Deleted,
+    /// The post has no file URL without logging in (blacklisted for
+    /// anonymous requests).
+    Blacklisted,
+    BudgetReached,
+    LowDiskSpace,
+    Cancelled,
+    /// Anything else, including failures read from older manifests.
+    Unknown,
+}
+
+impl FailureKind {
+    /// Whether retrying can never succeed: the post is deleted, or its file
+    /// is gone from the server.
+    pub fn is_permanent(self) -> bool {
+        matches!(
+            self,
+            FailureKind::Deleted | FailureKind::Http { status: 404 | 410 }
+        )
+    }
+}
+
+/// A classified failure, with the message that was logged for it.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(from = "ErrorRepr")]
+pub struct DownloadError {
+    #[serde(flatten)]
+    pub kind: FailureKind,
+    pub message: String,
+}
+
+impl DownloadError {
+    pub fn new(kind: FailureKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// How a [`DownloadError`] is read: manifests written before failures were
+/// classified hold just the message.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum ErrorRepr {
+    Classified {
+        #[serde(flatten)]
+        kind: FailureKind,
+        message: String,
+    },
+    Message(String),
+}
+
+impl From<ErrorRepr> for DownloadError {
+    fn from(repr: ErrorRepr) -> Self {
+        match repr {
+            ErrorRepr::Classified { kind, message } => Self { kind, message },
+            ErrorRepr::Message(message) => {
+                let kind = match message.as_str() {
+                    "download budget reached" => FailureKind::BudgetReached,
+                    budget::LOW_SPACE_ERROR => FailureKind::LowDiskSpace,
+                    "download cancelled" => FailureKind::Cancelled,
+                    _ => FailureKind::Unknown,
+                };
+                Self { kind, message }
+            }
+        }
+    }
+}
+
+/// Progress of a single file transfer, as reported in
+/// [`DownloadEvent::BytesProgressed`].
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct FileProgress {
+    pub post_id: u64,
+    /// The file name being written.
+    pub name: String,
+    /// Bytes of the file on disk so far.
+    pub bytes: u64,
+    /// The full file size, from the response or the post's metadata.
+    pub total: Option<u64>,
 }
 
 pub type ProgressObserver = std::sync::Arc<dyn Fn(DownloadProgress) + Send + Sync>;
@@ -64,15 +319,25 @@ pub struct DownloadRecord {
     pub source_url: Option<String>,
     pub md5: Option<String>,
     pub artist: String,
+    /// The post's rating: `s`afe, `q`uestionable or `e`xplicit.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub rating: String,
+    /// The post's tags (see [`type_defs::api_defs::Tags::all`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub extension: String,
     pub local_filename: Option<String>,
-    pub status: String,
+    pub status: RecordStatus,
     pub bytes: u64,
-    pub error: Option<String>,
+    pub error: Option<DownloadError>,
+    /// The converted copy of the file (see [`postprocess`]), if one was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derived: Option<String>,
 }
 
 /// Request-scoped settings shared by every download operation: which API variant
 /// to hit, how many pages/threads to use, and whether to prefer lower-quality media.
+#[derive(Clone)]
 pub struct CliContext {
     /// Whether verbose logging is enabled.
     pub verbose: bool,
@@ -83,13 +348,30 @@ pub struct CliContext {
     pub lower_quality: bool,
     /// Number of pages to fetch: `-1` means "all pages", `> 0` means that many pages.
     pub pages: i64,
-    /// Number of threads to use for parallel downloads (expected to be `1..=10`;
-    /// see [`cli::validate_args`] for the CLI-level bound).
+    /// Show a progress bar for each file being downloaded, alongside the
+    /// overall bars.
+    pub file_bars: bool,
+    /// Number of downloads to run at once (see [`cli::validate_args`] for the
+    /// CLI-level bound). These are concurrent transfers, not OS threads.
     pub num_threads: usize,
     pub retries: u32,
     pub duplicate_index: Option<std::sync::Arc<duplicate::DuplicateIndex>>,
-    /// Cooperative cancellation requested by an interactive frontend.
-    pub cancel: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
+    /// Cancellation requested by the user (Ctrl+C in the CLI, or a frontend).
+    /// Cancelling stops in-flight requests and transfers where they stand,
+    /// keeping partial files as `.part` to resume, and records the posts not
+    /// yet done as `cancelled`.
+    pub cancel: Option<tokio_util::sync::CancellationToken>,
+    /// Caps the combined speed of all transfers (`--limit-rate`).
+    pub rate_limit: Option<std::sync::Arc<budget::RateLimiter>>,
+    /// Stops the run once enough has been downloaded (`--max-bytes`,
+    /// `--max-files`).
+    pub budget: Option<std::sync::Arc<budget::Budget>>,
+    /// Free space to keep on the destination disk (`--min-free`); checked
+    /// against the run's estimated size before it starts, and before every
+    /// download.
+    pub disk: Option<std::sync::Arc<budget::DiskReserve>>,
+    /// Makes compact copies of downloaded images (`--convert`).
+    pub postprocess: Option<std::sync::Arc<postprocess::PostProcessor>>,
     pub progress: Option<ProgressObserver>,
 }
 
@@ -102,7 +384,15 @@ impl CliContext {
 
 /// Optional API credentials. An empty `username`/`api_key` means unauthenticated
 /// requests (see [`funcs::send_request`]).
+#[derive(Clone, Default)]
 pub struct Login {
     pub username: String,
     pub api_key: String,
 }
+
+impl Login {
+    /// Whether requests are sent with these credentials.
+    pub fn is_authenticated(&self) -> bool {
+        !self.username.is_empty() && !self.api_key.is_empty()
+    }
+}
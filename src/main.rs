@@ -9,13 +9,22 @@ use std::{
 
 use clap::Parser;
 use e_cli::{
-    CliContext, DownloadStatistics, Login, Tracker,
+    CliContext, DownloadStatistics, Downloader, FailureKind, Login, Tracker,
+    blocking::{self, download_favourites, download_pools, download_search, download_set},
+    budget,
     cli::{self, Commands},
-    commands::{self, download_favourites, download_pool, download_search},
-    config, funcs, update,
+    commands, config, credentials,
+    failure_manifest::{FailureClass, failure_kind},
+    funcs,
+    output::{self, OutputEvent},
+    postprocess::PostProcessor,
+    runtime::{block_on, runtime},
+    state::RunLock,
+    update,
 };
-use indicatif::MultiProgress;
-use tracing::{Level, error, info, span};
+use indicatif::{MultiProgress, ProgressDrawTarget};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, error, info, span, warn};
 use tracing_subscriber::{
     EnvFilter, Layer, fmt, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt,
 };
@@ -25,7 +34,7 @@ mod tui;
 
 #[cfg(not(feature = "tui"))]
 mod tui {
-    pub fn run() -> Result<(), String> {
+    pub fn run(_login: e_cli::Login) -> Result<(), String> {
         Err("The TUI is not included in this build. Rebuild with --features tui.".into())
     }
 }
@@ -55,9 +64,14 @@ impl<'a> MakeWriter<'a> for ProgressWriter {
 
 fn main() {
     let mut args = cli::Args::parse();
+    let json = args.output == cli::OutputFormat::Json;
 
     if matches!(&args.command, Some(Commands::Tui)) {
-        if let Err(e) = tui::run() {
+        let login = config::path()
+            .and_then(|path| config::load(&path))
+            .and_then(|config| credentials::load(&config.global, args.account.as_deref()))
+            .map(Option::unwrap_or_default);
+        if let Err(e) = login.and_then(tui::run) {
             eprintln!("{e}");
             process::exit(1);
         }
@@ -73,7 +87,7 @@ fn main() {
     }
 
     if matches!(&args.command, Some(Commands::CheckUpdate)) {
-        check_update_cmd();
+        check_update_cmd(json);
         return;
     }
 
@@ -99,28 +113,13 @@ fn main() {
         return;
     }
 
-    let context = CliContext {
-        verbose: args.verbose,
-        nsfw: args.nsfw,
-        lower_quality: args.lower_quality,
-        pages: args.pages.unwrap_or(-1),
-        num_threads: args.num_threads.unwrap_or(5),
-        retries: args.retries,
-        duplicate_index: if args.dry_run {
-            None
-        } else {
-            let path = args.duplicate_index.clone().unwrap_or_else(|| {
-                Path::new(args.dir.as_deref().unwrap_or(cli::DL_DIR)).join(".e-cli-md5.json")
-            });
-            match e_cli::duplicate::DuplicateIndex::load(&path) {
-                Ok(index) => Some(Arc::new(index)),
-                Err(e) => return error!("Failed to open duplicate index {}: {e}", path.display()),
-            }
-        },
-        cancel: None,
-        progress: None,
+    // In JSON mode stdout is for events only, and the bars would just be
+    // noise among the logs on stderr.
+    let mp = if json {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
     };
-    let mp = MultiProgress::new();
     let progress_writer = ProgressWriter(mp.clone());
     if args.verbose && !args.dry_run {
         let logging = fmt::layer()
@@ -147,60 +146,129 @@ fn main() {
         tracing_subscriber::registry().with(logging).init();
     }
 
-    let mut username = String::new();
-    let mut api_key = String::new();
-    if args.login {
-        let client = commands::get_client();
-
-        info!("Sign-In via inputing your username and api_key.");
-        info!("This info isn't sent anywhere. Only when the cli runs.");
-        info!("Username: ");
-        io::stdin()
-            .read_line(&mut username)
-            .expect("Error getting user input.");
-        username = username.trim().to_owned();
-        info!("Api Key: ");
-        io::stdin()
-            .read_line(&mut api_key)
-            .expect("Error getting user input.");
-        api_key = api_key.trim().to_owned();
-        info!("Testing if valid...");
-        let resp = client
-            .get(format!(
-                "https://{}/posts.json?tags=&limit=5",
-                context.api_source()
-            ))
-            .basic_auth(&username, Some(api_key.clone()))
-            .send()
-            .expect("Error getting Auth response.");
-        match resp.error_for_status() {
-            Ok(_) => {
-                info!("Sign-in Passed! Continuing...")
-            }
-            Err(err) => {
-                return error!(
-                    "The credentials provided aren't valid, or something else happened. Err: {err}"
-                );
+    let dl_dir = Path::new(args.dir.as_deref().unwrap_or(cli::DL_DIR));
+    let downloads = matches!(
+        &args.command,
+        Some(
+            Commands::DFavs { .. }
+                | Commands::DUploads { .. }
+                | Commands::DTags { .. }
+                | Commands::DPool { .. }
+                | Commands::DSet { .. }
+                | Commands::DArtist { .. }
+                | Commands::Preset { .. }
+        )
+    );
+    // Hold the download directory for the whole run, from before the
+    // duplicate index is loaded, so a concurrent run can't interleave its
+    // writes to the state files in it.
+    let _lock = if !args.dry_run
+        && (downloads || matches!(&args.command, Some(Commands::RetryFailed { .. })))
+    {
+        // Created here, before the tracking file is opened, since users
+        // commonly keep the tracking file inside the download directory.
+        funcs::ensure_dl_dir(dl_dir);
+        match RunLock::acquire(dl_dir, args.wait) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                error!("{e}");
+                process::exit(1);
             }
         }
-    }
-    let login = Login { username, api_key };
+    } else {
+        None
+    };
 
-    let dl_dir = Path::new(args.dir.as_deref().unwrap_or(cli::DL_DIR));
+    let cancel = CancellationToken::new();
+    let context = CliContext {
+        verbose: args.verbose,
+        nsfw: args.nsfw,
+        lower_quality: args.lower_quality,
+        pages: args.pages.unwrap_or(-1),
+        file_bars: args.file_bars,
+        num_threads: args.num_threads.unwrap_or(5),
+        retries: args.retries,
+        duplicate_index: if args.dry_run {
+            None
+        } else {
+            let path = args.duplicate_index.clone().unwrap_or_else(|| {
+                Path::new(args.dir.as_deref().unwrap_or(cli::DL_DIR)).join(".e-cli-md5.json")
+            });
+            match e_cli::duplicate::DuplicateIndex::load(&path) {
+                Ok(index) => Some(Arc::new(index)),
+                Err(e) => return error!("Failed to open duplicate index {}: {e}", path.display()),
+            }
+        },
+        cancel: Some(cancel.clone()),
+        rate_limit: args
+            .limit_rate
+            .map(|rate| Arc::new(budget::RateLimiter::new(rate))),
+        budget: (args.max_bytes.is_some() || args.max_files.is_some())
+            .then(|| Arc::new(budget::Budget::new(args.max_bytes, args.max_files))),
+        disk: args.min_free.map(|reserve| {
+            Arc::new(budget::DiskReserve::new(
+                dl_dir,
+                reserve,
+                args.allow_low_space,
+            ))
+        }),
+        postprocess: args.convert.map(|format| {
+            let target = args.convert_dir.clone().unwrap_or_else(|| {
+                let mut name = dl_dir.file_name().unwrap_or_default().to_owned();
+                name.push("-converted");
+                dl_dir.with_file_name(name)
+            });
+            Arc::new(PostProcessor::new(
+                format,
+                args.quality,
+                args.max_dimensions,
+                dl_dir,
+                &target,
+            ))
+        }),
+        progress: json.then(|| Arc::new(output::print_progress) as e_cli::ProgressObserver),
+    };
+    if matches!(&args.command, Some(Commands::Login)) {
+        return login_cmd(&context, args.account.as_deref());
+    }
+    if matches!(&args.command, Some(Commands::Logout)) {
+        return logout_cmd(args.account.as_deref());
+    }
+    let login = match credentials::load(&file_config.global, args.account.as_deref()) {
+        Ok(Some(login)) => login,
+        Ok(None) if args.login => {
+            let login = match prompt_login() {
+                Ok(login) => login,
+                Err(e) => return error!("Error getting user input: {e}"),
+            };
+            info!("Testing if valid...");
+            let client = commands::get_client();
+            if let Err(e) = block_on(funcs::validate_login(&context, &client, &login)) {
+                return error!("{e}");
+            }
+            info!("Sign-in Passed! Continuing...");
+            login
+        }
+        Ok(None) => Login::default(),
+        Err(e) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    };
 
     if args.dry_run {
         dry_run_cmd(&args, &file_config, &context, &login, dl_dir);
         return;
     }
 
-    // Create the download directory up front (before the tracking file is
-    // opened), since users commonly keep the tracking file inside the
-    // download directory or next to it.
-    if matches!(
-        &args.command,
-        Some(Commands::DFavs { .. } | Commands::DTags { .. } | Commands::DPool { .. })
-    ) {
-        funcs::ensure_dl_dir(dl_dir);
+    if downloads && json {
+        output::print(&OutputEvent::Plan {
+            destination: dl_dir,
+            dry_run: false,
+            posts: None,
+            skipped: None,
+            bytes: None,
+        });
     }
 
     let tracker = match &args.track_file {
@@ -216,6 +284,13 @@ fn main() {
         None => None,
     };
 
+    if !matches!(
+        &args.command,
+        Some(Commands::ClearDl | Commands::Zip { .. } | Commands::Gallery { .. })
+    ) {
+        cancel_on_ctrl_c(cancel.clone());
+    }
+
     #[allow(unused_mut)]
     let mut download_stats;
     let fn_start = Instant::now();
@@ -226,10 +301,17 @@ fn main() {
         Some(Commands::Config) => return,
         Some(Commands::Tui) => return,
         Some(Commands::CheckUpdate) => return,
+        Some(Commands::Login | Commands::Logout) => return,
         Some(Commands::ClearDl) => {
             if !dl_dir.exists() {
                 return info!("Nothing to clean... Exiting!");
             }
+            // Only to check no run is using the directory; released before
+            // deleting it, as an open lock file can't be deleted on Windows.
+            if let Err(e) = RunLock::acquire(dl_dir, args.wait) {
+                error!("{e}");
+                process::exit(1);
+            }
 
             fs::remove_dir_all(dl_dir).expect("Err");
             return info!(
@@ -255,6 +337,26 @@ fn main() {
                 tracker.as_ref(),
             );
         }
+        Some(Commands::DUploads {
+            username,
+            id,
+            count,
+            random,
+            tags,
+        }) => {
+            download_stats = blocking::download_uploads(
+                &context,
+                &login,
+                username.as_deref().expect("validated username"),
+                id,
+                &count.unwrap_or(5),
+                random,
+                tags.as_deref().unwrap_or_default(),
+                &mp,
+                dl_dir,
+                tracker.as_ref(),
+            );
+        }
         Some(Commands::DTags {
             tags,
             count,
@@ -271,26 +373,117 @@ fn main() {
                 tracker.as_ref(),
             );
         }
-        Some(Commands::DPool { pool_id }) => {
-            download_stats = download_pool(
+        Some(Commands::DPool {
+            pools,
+            series,
+            archive,
+        }) => {
+            let pools = match blocking::resolve_pools(&context, &login, pools, *series) {
+                Ok(pools) => pools,
+                Err(e) => return error!("{e}"),
+            };
+            download_stats = download_pools(
+                &context,
+                &login,
+                &pools,
+                *archive,
+                &mp,
+                dl_dir,
+                tracker.as_ref(),
+            );
+        }
+        Some(Commands::DArtist { name, count, urls }) => {
+            download_stats = blocking::download_artist(
                 &context,
                 &login,
-                &pool_id.expect("validated pool ID"),
+                name,
+                &count.unwrap_or(250),
+                urls,
                 &mp,
                 dl_dir,
                 tracker.as_ref(),
             );
         }
-        Some(Commands::Zip { name, format }) => {
-            if !commands::zip_downloads(
+        Some(Commands::Pools {
+            command: cli::PoolsCommand::Search { query, count },
+        }) => {
+            pools_search_cmd(&context, &login, query, count, json);
+            return;
+        }
+        Some(Commands::DSet { set, indexed }) => {
+            download_stats = download_set(
+                &context,
+                &login,
+                set.as_deref().expect("validated set"),
+                indexed,
+                &mp,
                 dl_dir,
+                tracker.as_ref(),
+            );
+        }
+        Some(Commands::Zip {
+            name,
+            format,
+            to,
+            store,
+        }) => {
+            let format = format.unwrap_or(cli::ArchiveFormat::Zip);
+            let out = commands::archive_path(
                 name.as_deref().expect("validated archive name"),
-                format.unwrap_or(cli::ArchiveFormat::Zip),
-            ) {
+                format,
+                to.as_deref(),
+            );
+            if !commands::zip_downloads(dl_dir, &out, format, *store, &mp) {
                 error!("Failed to package {} into an archive.", dl_dir.display());
             }
             return;
         }
+        Some(Commands::Gallery { manifests }) => {
+            if !commands::make_gallery(dl_dir, manifests, context.api_source(), &mp) {
+                process::exit(1);
+            }
+            return;
+        }
+        Some(Commands::Serve {
+            port,
+            bind,
+            manifests,
+            token_file,
+        }) => {
+            if let Err(e) = read_token(token_file.as_deref()).and_then(|token| {
+                serve_cmd(
+                    std::net::SocketAddr::new(*bind, *port),
+                    context,
+                    login,
+                    dl_dir,
+                    manifests,
+                    tracker.map(Arc::new),
+                    &file_config,
+                    token,
+                )
+            }) {
+                error!("{e}");
+                process::exit(1);
+            }
+            return;
+        }
+        Some(Commands::Daemon { listen, token_file }) => {
+            if let Err(e) = read_token(token_file.as_deref()).and_then(|token| {
+                daemon_cmd(
+                    *listen,
+                    context,
+                    login,
+                    dl_dir,
+                    tracker.map(Arc::new),
+                    &config_path,
+                    token,
+                )
+            }) {
+                error!("{e}");
+                process::exit(1);
+            }
+            return;
+        }
         Some(Commands::Preset {
             name,
             count,
@@ -300,11 +493,13 @@ fn main() {
                 Some(preset) => preset,
                 None => return error!("Unknown preset '{name}'."),
             };
-            let tags = preset.tags.as_deref().unwrap_or_default();
-            download_stats = download_search(
+            if preset.source.as_deref() == Some("set") && preset.set.is_none() {
+                return error!("Preset '{name}' uses source = \"set\" but has no set configured.");
+            }
+            download_stats = blocking::download_preset(
                 &context,
                 &login,
-                tags,
+                preset,
                 &count.or(preset.count).unwrap_or(5),
                 &(*random || preset.random.unwrap_or(false)),
                 &mp,
@@ -312,17 +507,72 @@ fn main() {
                 tracker.as_ref(),
             );
         }
-        Some(Commands::RetryFailed) => {
+        Some(Commands::RetryFailed { only, exclude }) => {
             let path = args
                 .failure_manifest
                 .clone()
                 .unwrap_or_else(|| dl_dir.join(".e-cli-failed.json"));
-            let manifest = match e_cli::failure_manifest::FailureManifest::load(&path) {
+            let mut manifest = match e_cli::failure_manifest::FailureManifest::load(&path) {
                 Ok(manifest) => manifest,
                 Err(e) => return error!("{e}"),
             };
+            // Retry as the account the failures came from, unless
+            // --account picks another.
+            let login = match manifest
+                .account
+                .as_deref()
+                .filter(|_| args.account.is_none())
+            {
+                Some(account) => match credentials::load(&file_config.global, Some(account)) {
+                    Ok(login) => login.unwrap_or_default(),
+                    Err(e) => {
+                        error!("{e}");
+                        process::exit(1);
+                    }
+                },
+                None => login.clone(),
+            };
+            if args.account.is_some() {
+                manifest.account = args.account.clone();
+            }
+            report_permanent(&manifest.take_permanent());
+            let mut exclude = exclude.clone();
+            if !login.is_authenticated() {
+                let blacklisted = FailureClass::Kind(FailureKind::Blacklisted);
+                let hidden = manifest
+                    .records
+                    .iter()
+                    .filter(|record| blacklisted.matches(failure_kind(record)))
+                    .count();
+                if hidden > 0 {
+                    warn!("{hidden} posts are hidden without a login; pass --login to retry them.");
+                }
+                exclude.push(blacklisted);
+            }
+            let retrying = manifest.take_selected(only, &exclude);
             let retry_dir = manifest.destination.clone();
             funcs::ensure_dl_dir(&retry_dir);
+            let same_dir = fs::canonicalize(&retry_dir).ok() == fs::canonicalize(dl_dir).ok();
+            let _retry_lock = if same_dir {
+                None
+            } else {
+                match RunLock::acquire(&retry_dir, args.wait) {
+                    Ok(lock) => Some(lock),
+                    Err(e) => {
+                        error!("{e}");
+                        process::exit(1);
+                    }
+                }
+            };
+            if json {
+                output::print(&OutputEvent::Plan {
+                    destination: &retry_dir,
+                    dry_run: false,
+                    posts: Some(retrying.len()),
+                    skipped: None,
+                    bytes: None,
+                });
+            }
             let retry_duplicate_path = args
                 .duplicate_index
                 .clone()
@@ -335,45 +585,60 @@ fn main() {
                 nsfw: manifest.api_source == "e621.net",
                 lower_quality: manifest.lower_quality,
                 pages: context.pages,
+                file_bars: context.file_bars,
                 num_threads: context.num_threads,
                 retries: manifest.retries,
                 duplicate_index: retry_duplicate,
-                cancel: None,
-                progress: None,
+                cancel: context.cancel.clone(),
+                rate_limit: context.rate_limit.clone(),
+                budget: context.budget.clone(),
+                disk: args.min_free.map(|reserve| {
+                    Arc::new(budget::DiskReserve::new(
+                        &retry_dir,
+                        reserve,
+                        args.allow_low_space,
+                    ))
+                }),
+                postprocess: context.postprocess.clone(),
+                progress: context.progress.clone(),
             };
             let client = commands::get_client();
-            let ids = manifest
-                .records
+            let ids = retrying
                 .iter()
                 .map(|record| record.post_id)
                 .collect::<Vec<_>>();
-            let posts = funcs::get_post_data(&retry_context, &client, &login, &ids);
-            download_stats = funcs::download_with_options(
-                &client,
-                &login,
-                posts,
-                None,
-                &manifest.lower_quality,
-                &retry_dir,
-                tracker.as_ref(),
-                funcs::DownloadOptions {
-                    retries: manifest.retries,
-                    duplicate_index: context.duplicate_index.as_deref(),
-                    cancel: None,
-                },
-            )
-            .into_statistics(ids.len());
+            download_stats = block_on(async {
+                let posts = funcs::get_post_data(&retry_context, &client, &login, &ids).await;
+                Downloader::new(
+                    &retry_context,
+                    &login,
+                    &client,
+                    &mp,
+                    &retry_dir,
+                    tracker.as_ref(),
+                )
+                .run(posts.into_iter().map(|post| (None, post)), ids.len())
+                .await
+            });
+            // The records left out of the retry stay in the manifest.
             if let Some(updated) = e_cli::failure_manifest::FailureManifest::from_statistics(
                 retry_context.api_source(),
+                manifest.account.as_deref(),
                 &retry_dir,
                 manifest.lower_quality,
                 manifest.retries,
                 &download_stats,
             ) {
-                if let Err(e) = updated.save(&path) {
+                manifest.records.extend(updated.records);
+            }
+            manifest.restore_unattempted(retrying, &download_stats);
+            report_permanent(&manifest.take_permanent());
+            if !manifest.records.is_empty() {
+                if let Err(e) = manifest.save(&path) {
                     error!("{e}");
                 }
-            } else if let Err(e) = fs::remove_file(&path)
+            } else if !cancel.is_cancelled()
+                && let Err(e) = fs::remove_file(&path)
                 && e.kind() != io::ErrorKind::NotFound
             {
                 error!("Failed to remove {}: {e}", path.display());
@@ -391,9 +656,10 @@ fn main() {
         .failure_manifest
         .clone()
         .unwrap_or_else(|| dl_dir.join(".e-cli-failed.json"));
-    if !matches!(&args.command, Some(Commands::RetryFailed))
+    if !matches!(&args.command, Some(Commands::RetryFailed { .. }))
         && let Some(manifest) = e_cli::failure_manifest::FailureManifest::from_statistics(
             context.api_source(),
+            args.account.as_deref(),
             dl_dir,
             context.lower_quality,
             context.retries,
@@ -403,16 +669,216 @@ fn main() {
     {
         error!("{e}");
     }
-    if !matches!(&args.command, Some(Commands::RetryFailed)) && download_stats.failed == 0 {
+    if !matches!(&args.command, Some(Commands::RetryFailed { .. }))
+        && download_stats.failed == 0
+        && download_stats.remaining == 0
+        && download_stats.cancelled == 0
+    {
         let _ = fs::remove_file(&failure_path);
     }
     let failed = download_stats.failed;
-    finish(download_stats, fn_start);
+    let remaining = download_stats.remaining;
+    let low_space = download_stats.records.iter().any(|record| {
+        record
+            .error
+            .as_ref()
+            .is_some_and(|error| error.kind == FailureKind::LowDiskSpace)
+    });
+    let cancelled = download_stats.cancelled;
+    finish(download_stats, fn_start, json);
+    if cancel.is_cancelled() {
+        info!("Cancelled; {cancelled} posts were left for later. Run retry-failed to continue.");
+        process::exit(cli::EXIT_CANCELLED);
+    }
+    if low_space {
+        error!(
+            "Not enough free disk space; {remaining} posts were left for later. Free up space and run retry-failed to continue."
+        );
+        process::exit(cli::EXIT_LOW_SPACE);
+    }
+    if remaining > 0 {
+        info!(
+            "Download budget reached; {remaining} posts were left for later. Run retry-failed to continue."
+        );
+        process::exit(cli::EXIT_BUDGET_REACHED);
+    }
     if failed > 0 {
         process::exit(1);
     }
 }
 
+/// Asks for a username and API key on stderr, reading them from stdin.
+fn prompt_login() -> io::Result<Login> {
+    let read = |prompt: &str| -> io::Result<String> {
+        eprint!("{prompt}");
+        io::stderr().flush()?;
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+        Ok(line.trim().to_owned())
+    };
+    eprintln!("Sign in with your username and API key. They're only sent to the API.");
+    let username = read("Username: ")?;
+    let api_key = read("API key: ")?;
+    Ok(Login { username, api_key })
+}
+
+fn login_cmd(context: &CliContext, account: Option<&str>) {
+    let path = match credentials::path() {
+        Ok(path) => path,
+        Err(e) => return error!("{e}"),
+    };
+    let login = match prompt_login() {
+        Ok(login) => login,
+        Err(e) => return error!("Error getting user input: {e}"),
+    };
+    info!("Testing if valid...");
+    let client = commands::get_client();
+    if let Err(e) = block_on(funcs::validate_login(context, &client, &login)) {
+        error!("{e}");
+        process::exit(1);
+    }
+    if let Err(e) = credentials::save(&path, account, &login) {
+        error!("{e}");
+        process::exit(1);
+    }
+    let saved_as = account.map_or_else(
+        || "the default account".to_owned(),
+        |a| format!("account '{a}'"),
+    );
+    info!(
+        "Signed in as {}; saved as {saved_as} in {}.",
+        login.username,
+        path.display()
+    );
+}
+
+fn logout_cmd(account: Option<&str>) {
+    let removed = credentials::path().and_then(|path| credentials::remove(&path, account));
+    match (removed, account) {
+        (Ok(true), Some(account)) => info!("Removed the stored account '{account}'."),
+        (Ok(true), None) => info!("Removed the stored credentials."),
+        (Ok(false), Some(account)) => info!("No account named '{account}' was stored."),
+        (Ok(false), None) => info!("No credentials were stored."),
+        (Err(e), _) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    }
+    if account.is_none() && std::env::var_os(credentials::USERNAME_VAR).is_some() {
+        warn!(
+            "{} is still set in the environment, so runs keep signing in with it.",
+            credentials::USERNAME_VAR
+        );
+    }
+}
+
+/// Logs the posts a retry can never download, which are dropped from the
+/// failure manifest.
+fn report_permanent(records: &[e_cli::DownloadRecord]) {
+    for record in records {
+        if let Some(error) = &record.error {
+            warn!(
+                "Post {} can never be downloaded ({error}); removing it from the failure manifest.",
+                record.post_id
+            );
+        }
+    }
+}
+
+/// Cancels `cancel` on the first Ctrl+C, so downloads stop and the manifests
+/// record what was left; a second Ctrl+C exits immediately.
+fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    runtime().spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("Cancelling... Press Ctrl+C again to quit immediately.");
+        cancel.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            process::exit(cli::EXIT_CANCELLED);
+        }
+    });
+}
+
+#[cfg(feature = "server")]
+#[allow(clippy::too_many_arguments)]
+fn serve_cmd(
+    addr: std::net::SocketAddr,
+    context: CliContext,
+    login: Login,
+    dir: &Path,
+    manifests: &[std::path::PathBuf],
+    tracker: Option<Arc<Tracker>>,
+    config: &config::Config,
+    token: Option<String>,
+) -> Result<(), String> {
+    block_on(e_cli::server::serve(
+        addr, context, login, dir, manifests, tracker, config, token,
+    ))
+}
+
+#[cfg(not(feature = "server"))]
+#[allow(clippy::too_many_arguments)]
+fn serve_cmd(
+    _addr: std::net::SocketAddr,
+    _context: CliContext,
+    _login: Login,
+    _dir: &Path,
+    _manifests: &[std::path::PathBuf],
+    _tracker: Option<Arc<Tracker>>,
+    _config: &config::Config,
+    _token: Option<String>,
+) -> Result<(), String> {
+    Err("The web interface is not included in this build. Rebuild with --features server.".into())
+}
+
+/// The API token in the `--token-file` at `path`, if one was given.
+fn read_token(path: Option<&Path>) -> Result<Option<String>, String> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let token =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    match token.trim() {
+        "" => Err(format!("{} has no token in it.", path.display())),
+        token => Ok(Some(token.to_owned())),
+    }
+}
+
+#[cfg(feature = "server")]
+fn daemon_cmd(
+    addr: std::net::SocketAddr,
+    context: CliContext,
+    login: Login,
+    dir: &Path,
+    tracker: Option<Arc<Tracker>>,
+    config_path: &Path,
+    token: Option<String>,
+) -> Result<(), String> {
+    block_on(e_cli::daemon::run(
+        addr,
+        context,
+        login,
+        dir,
+        tracker,
+        config_path,
+        token,
+    ))
+}
+
+#[cfg(not(feature = "server"))]
+fn daemon_cmd(
+    _addr: std::net::SocketAddr,
+    _context: CliContext,
+    _login: Login,
+    _dir: &Path,
+    _tracker: Option<Arc<Tracker>>,
+    _config_path: &Path,
+    _token: Option<String>,
+) -> Result<(), String> {
+    Err("The control API is not included in this build. Rebuild with --features server.".into())
+}
+
 fn dry_run_cmd(
     args: &cli::Args,
     config: &config::Config,
@@ -420,8 +886,55 @@ fn dry_run_cmd(
     login: &Login,
     dir: &Path,
 ) {
+    let Some((total, bytes, skipped)) = block_on(dry_run_totals(args, config, context, login, dir))
+    else {
+        return;
+    };
+    if args.output == cli::OutputFormat::Json {
+        return output::print(&OutputEvent::Plan {
+            destination: dir,
+            dry_run: true,
+            posts: Some(total),
+            skipped: Some(skipped),
+            bytes: Some(bytes),
+        });
+    }
+    println!(
+        "Dry run: {total} posts, {skipped} skipped, estimated {} bytes ({:.2} MB).",
+        bytes,
+        bytes as f64 / 1024.0 / 1024.0
+    );
+    println!("Destination: {}", dir.display());
+    if let Ok(free) = budget::available_space(dir) {
+        println!(
+            "Free space: {:.2} MB{}",
+            free as f64 / 1024.0 / 1024.0,
+            if args
+                .min_free
+                .is_some_and(|reserve| free < bytes.saturating_add(reserve))
+            {
+                " (not enough to keep --min-free available)"
+            } else {
+                ""
+            }
+        );
+    }
+    println!("No files or local state were written.");
+}
+
+/// Fetches what a download command would fetch and returns its post count,
+/// estimated bytes and already-present posts, or `None` if there's nothing to
+/// report.
+async fn dry_run_totals(
+    args: &cli::Args,
+    config: &config::Config,
+    context: &e_cli::CliContext,
+    login: &Login,
+    dir: &Path,
+) -> Option<(usize, u64, usize)> {
+    let json = args.output == cli::OutputFormat::Json;
     let client = commands::get_client();
-    let (total, bytes, skipped) = match &args.command {
+    let totals = match &args.command {
         Some(Commands::DFavs {
             username,
             count,
@@ -437,7 +950,30 @@ fn dry_run_cmd(
                 tags.as_deref().unwrap_or_default(),
                 random,
                 &count.unwrap_or(5),
-            );
+            )
+            .await;
+            let posts = data.into_iter().flatten().collect::<Vec<_>>();
+            let (skipped, bytes) = dry_run_counts(&posts, dir);
+            (posts.len(), bytes, skipped)
+        }
+        Some(Commands::DUploads {
+            username,
+            id,
+            count,
+            random,
+            tags,
+        }) => {
+            let random = if *random { "order:random" } else { "" };
+            let data = funcs::get_pages(
+                context,
+                login,
+                &client,
+                &commands::uploader_query(username.as_deref().unwrap_or_default(), *id),
+                tags.as_deref().unwrap_or_default(),
+                random,
+                &count.unwrap_or(5),
+            )
+            .await;
             let posts = data.into_iter().flatten().collect::<Vec<_>>();
             let (skipped, bytes) = dry_run_counts(&posts, dir);
             (posts.len(), bytes, skipped)
@@ -456,15 +992,69 @@ fn dry_run_cmd(
                 tags.as_deref().unwrap_or_default(),
                 random,
                 &count.unwrap_or(5),
-            );
+            )
+            .await;
+            let posts = data.into_iter().flatten().collect::<Vec<_>>();
+            let (skipped, bytes) = dry_run_counts(&posts, dir);
+            (posts.len(), bytes, skipped)
+        }
+        Some(Commands::DPool { pools, series, .. }) => {
+            let pools = match commands::resolve_pools(context, login, pools, *series).await {
+                Ok(pools) => pools,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return None;
+                }
+            };
+            let (mut total, mut bytes, mut skipped) = (0, 0, 0);
+            for pool in pools {
+                let posts = funcs::get_post_data(context, &client, login, &pool.post_ids).await;
+                let pool_dir = dir.join(funcs::pool_dir_name(&pool));
+                if json {
+                    output::print(&OutputEvent::pool(
+                        &pool,
+                        posts.len() as u64,
+                        Some(&pool_dir),
+                    ));
+                } else {
+                    println!(
+                        "Pool {} '{}': {} posts into {}",
+                        pool.id,
+                        pool.display_name(),
+                        posts.len(),
+                        pool_dir.display()
+                    );
+                }
+                let (pool_skipped, pool_bytes) = dry_run_counts(&posts, &pool_dir);
+                total += posts.len();
+                bytes += pool_bytes;
+                skipped += pool_skipped;
+            }
+            (total, bytes, skipped)
+        }
+        Some(Commands::DArtist { name, count, .. }) => {
+            let tags = match commands::artist_tags(context, &client, login, name).await {
+                Ok((_, tags)) => tags,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return None;
+                }
+            };
+            if json {
+                output::print(&OutputEvent::ArtistTags { tags: &tags });
+            } else {
+                println!("Artist tags: {}", tags.join(", "));
+            }
+            let data =
+                funcs::get_pages_for_tags(context, login, &client, &tags, &count.unwrap_or(250))
+                    .await;
             let posts = data.into_iter().flatten().collect::<Vec<_>>();
             let (skipped, bytes) = dry_run_counts(&posts, dir);
             (posts.len(), bytes, skipped)
         }
-        Some(Commands::DPool { pool_id }) => {
-            let posts = funcs::get_pool(context, &client, login, &pool_id.unwrap_or_default())
-                .map(|pool| funcs::get_post_data(context, &client, login, &pool.post_ids))
-                .unwrap_or_default();
+        Some(Commands::DSet { set, .. }) => {
+            let posts =
+                set_posts(context, &client, login, set.as_deref().unwrap_or_default()).await;
             let (skipped, bytes) = dry_run_counts(&posts, dir);
             (posts.len(), bytes, skipped)
         }
@@ -475,34 +1065,54 @@ fn dry_run_cmd(
         }) => {
             let preset = match config.presets.get(name) {
                 Some(preset) => preset,
-                None => return,
+                None => return None,
             };
-            let data = funcs::get_pages(
-                context,
-                login,
-                &client,
-                "",
-                preset.tags.as_deref().unwrap_or_default(),
-                if *random || preset.random.unwrap_or(false) {
-                    "order:random"
-                } else {
-                    ""
-                },
-                &count.or(preset.count).unwrap_or(5),
-            );
-            let posts = data.into_iter().flatten().collect::<Vec<_>>();
-            let (skipped, bytes) = dry_run_counts(&posts, dir);
-            (posts.len(), bytes, skipped)
+            if preset.source.as_deref() == Some("set") {
+                let posts = set_posts(
+                    context,
+                    &client,
+                    login,
+                    preset.set.as_deref().unwrap_or_default(),
+                )
+                .await;
+                let (skipped, bytes) = dry_run_counts(&posts, dir);
+                (posts.len(), bytes, skipped)
+            } else {
+                let data = funcs::get_pages(
+                    context,
+                    login,
+                    &client,
+                    "",
+                    preset.tags.as_deref().unwrap_or_default(),
+                    if *random || preset.random.unwrap_or(false) {
+                        "order:random"
+                    } else {
+                        ""
+                    },
+                    &count.or(preset.count).unwrap_or(5),
+                )
+                .await;
+                let posts = data.into_iter().flatten().collect::<Vec<_>>();
+                let (skipped, bytes) = dry_run_counts(&posts, dir);
+                (posts.len(), bytes, skipped)
+            }
         }
-        _ => return,
+        _ => return None,
     };
-    println!(
-        "Dry run: {total} posts, {skipped} skipped, estimated {} bytes ({:.2} MB).",
-        bytes,
-        bytes as f64 / 1024.0 / 1024.0
-    );
-    println!("Destination: {}", dir.display());
-    println!("No files or local state were written.");
+    Some(totals)
+}
+
+/// The posts of a post set, or none if it can't be fetched.
+async fn set_posts(
+    context: &CliContext,
+    client: &reqwest::Client,
+    login: &Login,
+    set: &str,
+) -> Vec<e_cli::type_defs::api_defs::Post> {
+    match funcs::get_post_set(context, client, login, set).await {
+        Some(set) => funcs::get_post_data(context, client, login, &set.post_ids).await,
+        None => Vec::new(),
+    }
 }
 
 fn dry_run_counts(posts: &[e_cli::type_defs::api_defs::Post], dir: &Path) -> (usize, u64) {
@@ -525,9 +1135,41 @@ fn dry_run_counts(posts: &[e_cli::type_defs::api_defs::Post], dir: &Path) -> (us
     (skipped, bytes)
 }
 
-fn check_update_cmd() {
+fn pools_search_cmd(context: &CliContext, login: &Login, query: &str, count: &u32, json: bool) {
+    let client = commands::get_client();
+    let pools = block_on(funcs::search_pools(context, &client, login, query, count));
+    if json {
+        for pool in &pools {
+            output::print(&OutputEvent::pool(pool, pool.post_count, None));
+        }
+        return;
+    }
+    if pools.is_empty() {
+        return println!("No pools match '{query}'.");
+    }
+    println!(
+        "{:>8}  {:>6}  {:<10}  {:<8}  NAME",
+        "ID", "POSTS", "CATEGORY", "STATUS"
+    );
+    for pool in pools {
+        println!(
+            "{:>8}  {:>6}  {:<10}  {:<8}  {}",
+            pool.id,
+            pool.post_count,
+            pool.category,
+            if pool.is_active { "active" } else { "inactive" },
+            pool.display_name()
+        );
+    }
+}
+
+fn check_update_cmd(json: bool) {
     let current = env!("CARGO_PKG_VERSION");
     match update::check_update("Saniee/e-cli", current) {
+        Ok(latest) if json => output::print(&OutputEvent::Update {
+            current,
+            latest: latest.as_deref(),
+        }),
         Ok(Some(latest)) => {
             println!(
                 "A new version of e-cli is available: v{latest} (you're on v{current}).\n\
@@ -542,8 +1184,11 @@ fn check_update_cmd() {
     }
 }
 
-fn finish(statistics: DownloadStatistics, timer: Instant) {
+fn finish(statistics: DownloadStatistics, timer: Instant, json: bool) {
     let elapsed = timer.elapsed().as_secs_f64();
+    if json {
+        output::print(&OutputEvent::statistics(&statistics, elapsed));
+    }
     let speed = if elapsed > 0.0 {
         statistics.downloaded_amount / elapsed / 1024.0 / 1024.0
     } else {
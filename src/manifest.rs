@@ -1,19 +1,32 @@
 use std::fs;
 use std::path::Path;
 
-use crate::DownloadStatistics;
+use crate::{DownloadRecord, DownloadStatistics};
 
 pub fn write(path: &Path, statistics: &DownloadStatistics) -> Result<(), String> {
-    let content = serde_json::to_string_pretty(&statistics.records)
+    write_records(path, &statistics.records)
+}
+
+/// Writes `records` as a manifest, like [`write`].
+pub fn write_records(path: &Path, records: &[DownloadRecord]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(records)
         .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .map_err(|e| format!("Failed to create manifest directory: {e}"))?;
     }
-    fs::write(path, content)
+    crate::state::write_atomic(path, content)
         .map_err(|e| format!("Failed to write manifest {}: {e}", path.display()))
 }
 
+/// Reads back the records of a manifest written by [`write`].
+pub fn read(path: &Path) -> Result<Vec<DownloadRecord>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read manifest {}: {e}", path.display()))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse manifest {}: {e}", path.display()))
+}
+
 #[cfg(test)]
 #[path = "manifest_tests.rs"]
 mod tests;
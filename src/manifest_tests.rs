@@ -8,15 +8,22 @@ fn writes_json_records() {
             source_url: Some("https://example.invalid/1.jpg".into()),
             md5: Some("abc".into()),
             artist: "artist".into(),
+            rating: "s".into(),
+            tags: vec!["artist".into()],
             extension: "jpg".into(),
             local_filename: Some("artist-1.jpg".into()),
-            status: "completed".into(),
+            status: crate::RecordStatus::Completed,
             bytes: 12,
             error: None,
+            derived: None,
         }],
         ..Default::default()
     };
     crate::manifest::write(&path, &stats).expect("write");
-    let content = std::fs::read_to_string(path).expect("read");
+    let content = std::fs::read_to_string(&path).expect("read");
     assert!(content.contains("artist-1.jpg"));
+
+    let records = crate::manifest::read(&path).expect("read back");
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].tags, vec!["artist".to_owned()]);
 }
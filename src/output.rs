@@ -0,0 +1,123 @@
+//! Machine-readable output for `--output json`: one JSON object per line on
+//! stdout (JSON Lines), each naming what it reports in its `event` field.
+//! Logs and progress bars stay on stderr, so stdout carries only these.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::type_defs::api_defs::PoolData;
+use crate::{DownloadEvent, DownloadProgress, DownloadRecord, DownloadStatistics};
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum OutputEvent<'a> {
+    /// Where a command downloads to, sent before it starts. For a dry run,
+    /// also what it would download.
+    Plan {
+        destination: &'a Path,
+        dry_run: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        posts: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        skipped: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes: Option<u64>,
+    },
+    /// A pool matched by `pools search`, or planned by a `d-pool` dry run
+    /// (with the directory it would go into).
+    Pool {
+        id: u64,
+        name: String,
+        posts: u64,
+        category: &'a str,
+        active: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        destination: Option<&'a Path>,
+    },
+    /// The tags an artist is searched by.
+    ArtistTags { tags: &'a [String] },
+    /// A post's result, with the fields of its [`DownloadRecord`].
+    Post(&'a DownloadRecord),
+    /// A transfer that failed and will be tried again.
+    Retry {
+        post_id: u64,
+        attempt: u32,
+        reason: &'a str,
+        delay_ms: u64,
+    },
+    /// The final counts of a download command.
+    Statistics {
+        completed: i64,
+        failed: i64,
+        skipped: i64,
+        remaining: i64,
+        cancelled: i64,
+        total: usize,
+        downloaded_bytes: u64,
+        elapsed_secs: f64,
+    },
+    /// The result of `check-update`; `latest` is set if there is a newer
+    /// release.
+    Update {
+        current: &'a str,
+        latest: Option<&'a str>,
+    },
+}
+
+impl<'a> OutputEvent<'a> {
+    pub fn pool(pool: &'a PoolData, posts: u64, destination: Option<&'a Path>) -> Self {
+        OutputEvent::Pool {
+            id: pool.id,
+            name: pool.display_name(),
+            posts,
+            category: &pool.category,
+            active: pool.is_active,
+            destination,
+        }
+    }
+
+    pub fn statistics(statistics: &DownloadStatistics, elapsed_secs: f64) -> Self {
+        OutputEvent::Statistics {
+            completed: statistics.completed,
+            failed: statistics.failed,
+            skipped: statistics.skipped,
+            remaining: statistics.remaining,
+            cancelled: statistics.cancelled,
+            total: statistics.total,
+            downloaded_bytes: statistics.downloaded_amount as u64,
+            elapsed_secs,
+        }
+    }
+}
+
+/// Writes `event` to stdout as one line of JSON.
+pub fn print(event: &OutputEvent) {
+    let line = serde_json::to_string(event).expect("Output events always serialize");
+    let _ = writeln!(io::stdout().lock(), "{line}");
+}
+
+/// A [`crate::ProgressObserver`] body that prints post results and retries.
+pub fn print_progress(progress: DownloadProgress) {
+    if let Some(record) = &progress.record {
+        print(&OutputEvent::Post(record));
+    } else if let Some(DownloadEvent::RetryScheduled {
+        post_id,
+        attempt,
+        reason,
+        delay,
+    }) = &progress.event
+    {
+        print(&OutputEvent::Retry {
+            post_id: *post_id,
+            attempt: *attempt,
+            reason,
+            delay_ms: delay.as_millis() as u64,
+        });
+    }
+}
+
+#[cfg(test)]
+#[path = "output_tests.rs"]
+mod tests;
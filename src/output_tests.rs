@@ -0,0 +1,61 @@
+use super::*;
+
+#[test]
+fn events_are_tagged_single_line_objects() {
+    let record = DownloadRecord {
+        post_id: 42,
+        source_url: None,
+        md5: None,
+        artist: "someartist".into(),
+        rating: "s".into(),
+        tags: vec!["someartist".into()],
+        extension: "png".into(),
+        local_filename: None,
+        status: crate::RecordStatus::Failed,
+        bytes: 0,
+        error: Some(crate::DownloadError::new(
+            crate::FailureKind::Deleted,
+            "post was deleted",
+        )),
+        derived: None,
+    };
+    let line = serde_json::to_string(&OutputEvent::Post(&record)).expect("serialize");
+    assert!(!line.contains('\n'));
+    let value: serde_json::Value = serde_json::from_str(&line).expect("parse");
+    assert_eq!(value["event"], "post");
+    assert_eq!(value["post_id"], 42);
+    assert_eq!(value["status"], "failed");
+    assert_eq!(
+        value["error"],
+        serde_json::json!({"kind": "deleted", "message": "post was deleted"})
+    );
+
+    let plan = OutputEvent::Plan {
+        destination: Path::new("dl"),
+        dry_run: false,
+        posts: None,
+        skipped: None,
+        bytes: None,
+    };
+    let value = serde_json::to_value(&plan).expect("serialize");
+    assert_eq!(
+        value,
+        serde_json::json!({"event": "plan", "destination": "dl", "dry_run": false})
+    );
+}
+
+#[test]
+fn statistics_report_whole_bytes() {
+    let stats = DownloadStatistics {
+        completed: 2,
+        failed: 1,
+        total: 3,
+        downloaded_amount: 2048.0,
+        ..Default::default()
+    };
+    let value = serde_json::to_value(OutputEvent::statistics(&stats, 1.5)).expect("serialize");
+    assert_eq!(value["event"], "statistics");
+    assert_eq!(value["downloaded_bytes"], 2048);
+    assert_eq!(value["remaining"], 0);
+    assert_eq!(value["elapsed_secs"], 1.5);
+}
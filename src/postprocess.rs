@@ -0,0 +1,170 @@
+//! Compact copies of downloaded images, for devices where the originals are
+//! too big: re-encoded as AVIF or JPEG, optionally scaled down, and
+//! without the originals' metadata (EXIF, color profiles, comments). The
+//! originals are kept as they are; copies go to a tree parallel to the
+//! download directory, at the same relative paths.
+//!
+//! Conversion runs on the rayon pool once a download has finished (see
+//! [`crate::funcs::download_with_options`]), and the copy is recorded in the
+//! post's [`crate::DownloadRecord::derived`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+
+use crate::cli::ConvertFormat;
+use crate::state;
+
+/// The encoder speed for AVIF, from 1 (slowest, smallest) to 10.
+const AVIF_SPEED: u8 = 6;
+
+/// Converts downloaded images into copies under `target_root`.
+#[derive(Debug, Clone)]
+pub struct PostProcessor {
+    format: ConvertFormat,
+    /// Encoder quality from 1 to 100.
+    quality: u8,
+    /// Images larger than this (width, height) are scaled down to fit,
+    /// keeping their aspect ratio.
+    max_dimensions: Option<(u32, u32)>,
+    source_root: PathBuf,
+    target_root: PathBuf,
+}
+
+impl PostProcessor {
+    /// Converts images downloaded under `source_root` into the same relative
+    /// paths under `target_root`.
+    pub fn new(
+        format: ConvertFormat,
+        quality: u8,
+        max_dimensions: Option<(u32, u32)>,
+        source_root: &Path,
+        target_root: &Path,
+    ) -> Self {
+        Self {
+            format,
+            quality: quality.clamp(1, 100),
+            max_dimensions,
+            source_root: source_root.to_path_buf(),
+            target_root: target_root.to_path_buf(),
+        }
+    }
+
+    /// Whether `original` is an image that gets a copy, by its extension.
+    /// Animations (GIFs) and videos are left alone.
+    pub fn handles(original: &Path) -> bool {
+        original
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                matches!(
+                    extension.to_ascii_lowercase().as_str(),
+                    "jpg" | "jpeg" | "png" | "webp"
+                )
+            })
+    }
+
+    /// Where the copy of `original` goes: its path relative to the source
+    /// root under the target root (or just its file name there, if it's not
+    /// under the source root), with the format's extension.
+    pub fn target(&self, original: &Path) -> PathBuf {
+        let relative = original
+            .strip_prefix(&self.source_root)
+            .ok()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| original.file_name().unwrap_or_default().into());
+        self.target_root
+            .join(relative)
+            .with_extension(self.format.extension())
+    }
+
+    /// Writes the copy of `original`, unless it's already there, and returns
+    /// its path. Blocks while encoding; see [`PostProcessor::process`].
+    pub fn convert(&self, original: &Path) -> Result<PathBuf, String> {
+        let target = self.target(original);
+        if target.exists() {
+            return Ok(target);
+        }
+        let error = |e: &dyn std::fmt::Display| format!("{}: {e}", original.display());
+
+        let mut decoder = ImageReader::open(original)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| error(&e))?
+            .into_decoder()
+            .map_err(|e| error(&e))?;
+        // The orientation is metadata too, so it's applied to the pixels
+        // rather than lost.
+        let orientation = decoder.orientation().map_err(|e| error(&e))?;
+        let mut image = DynamicImage::from_decoder(decoder).map_err(|e| error(&e))?;
+        image.apply_orientation(orientation);
+        if let Some((width, height)) = self.max_dimensions
+            && (image.width() > width || image.height() > height)
+        {
+            image = image.resize(width, height, FilterType::Lanczos3);
+        }
+
+        let encoded = self.encode(image).map_err(|e| error(&e))?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Could not create {}: {e}", parent.display()))?;
+        }
+        state::write_atomic(&target, encoded)
+            .map_err(|e| format!("Failed to write {}: {e}", target.display()))?;
+        Ok(target)
+    }
+
+    fn encode(&self, image: DynamicImage) -> image::ImageResult<Vec<u8>> {
+        let mut encoded = Vec::new();
+        // Encoders only take 8-bit images; JPEG has no transparency either.
+        let image = match (self.format, image.color().has_alpha()) {
+            (ConvertFormat::Jpeg, _) => DynamicImage::ImageRgb8(crate::export::flatten(image)),
+            (_, true) => DynamicImage::ImageRgba8(image.to_rgba8()),
+            (_, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        };
+        match self.format {
+            ConvertFormat::Avif => image.write_with_encoder(AvifEncoder::new_with_speed_quality(
+                &mut encoded,
+                AVIF_SPEED,
+                self.quality,
+            )),
+            ConvertFormat::Jpeg => {
+                image.write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, self.quality))
+            }
+        }?;
+        Ok(encoded)
+    }
+
+    /// [`PostProcessor::convert`] on the rayon pool, so encoding doesn't hold
+    /// up the downloads running on the async runtime.
+    pub async fn process(&self, original: PathBuf) -> Result<PathBuf, String> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let processor = self.clone();
+        rayon::spawn(move || {
+            let _ = tx.send(processor.convert(&original));
+        });
+        rx.await
+            .unwrap_or_else(|_| Err("the conversion was dropped".to_owned()))
+    }
+}
+
+/// Parses `--max-dimensions`: `WIDTHxHEIGHT`, e.g. `1080x1920`.
+pub fn parse_dimensions(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("'{value}' isn't WIDTHxHEIGHT, e.g. 1080x1920"))?;
+    let side = |side: &str| match side.trim().parse::<u32>() {
+        Ok(side) if side > 0 => Ok(side),
+        _ => Err(format!(
+            "'{side}' in '{value}' isn't a positive number of pixels"
+        )),
+    };
+    Ok((side(width)?, side(height)?))
+}
+
+#[cfg(test)]
+#[path = "postprocess_tests.rs"]
+mod tests;
@@ -0,0 +1,90 @@
+use super::*;
+use crate::runtime::block_on;
+use image::{ImageFormat, Rgba, RgbaImage};
+
+fn processor(format: ConvertFormat, dir: &Path) -> PostProcessor {
+    PostProcessor::new(
+        format,
+        80,
+        Some((8, 8)),
+        &dir.join("dl"),
+        &dir.join("dl-converted"),
+    )
+}
+
+/// A 16x4 half-transparent PNG in a pool folder under `dir/dl`.
+fn original(dir: &Path) -> PathBuf {
+    let path = dir
+        .join("dl")
+        .join("Cloud Jumping")
+        .join("0001-artist-1.png");
+    fs::create_dir_all(path.parent().unwrap()).expect("mkdir");
+    RgbaImage::from_pixel(16, 4, Rgba([0, 0, 0, 128]))
+        .save_with_format(&path, ImageFormat::Png)
+        .expect("write png");
+    path
+}
+
+#[test]
+fn dimensions_parse_as_width_by_height() {
+    assert_eq!(parse_dimensions("1080x1920"), Ok((1080, 1920)));
+    assert_eq!(parse_dimensions("640X480"), Ok((640, 480)));
+    assert!(parse_dimensions("1080").is_err());
+    assert!(parse_dimensions("0x100").is_err());
+}
+
+#[test]
+fn copies_mirror_the_download_tree() {
+    let dir = Path::new("dl-test");
+    let processor = processor(ConvertFormat::Jpeg, dir);
+    assert_eq!(
+        processor.target(&dir.join("dl").join("pool").join("0001-a-1.png")),
+        dir.join("dl-converted").join("pool").join("0001-a-1.jpg")
+    );
+    assert_eq!(
+        processor.target(Path::new("elsewhere/5.webp")),
+        dir.join("dl-converted").join("5.jpg")
+    );
+    assert!(PostProcessor::handles(Path::new("a.PNG")));
+    assert!(!PostProcessor::handles(Path::new("a.gif")));
+    assert!(!PostProcessor::handles(Path::new("a.webm")));
+}
+
+#[test]
+fn images_are_converted_and_scaled_down() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let original = original(dir.path());
+
+    for (format, expected) in [
+        (ConvertFormat::Jpeg, ImageFormat::Jpeg),
+        (ConvertFormat::Avif, ImageFormat::Avif),
+    ] {
+        let processor = processor(format, dir.path());
+        let copy = block_on(processor.process(original.clone())).expect("convert");
+        assert_eq!(copy, processor.target(&original));
+        let bytes = fs::read(&copy).expect("read copy");
+        assert_eq!(image::guess_format(&bytes).expect("format"), expected);
+        if expected != ImageFormat::Avif {
+            let image = image::load_from_memory(&bytes).expect("decode");
+            assert_eq!((image.width(), image.height()), (8, 2));
+        }
+    }
+    // The original is left as it was.
+    assert_eq!(
+        image::image_dimensions(&original).expect("original"),
+        (16, 4)
+    );
+}
+
+#[test]
+fn existing_copies_are_kept() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let original = original(dir.path());
+    let processor = processor(ConvertFormat::Jpeg, dir.path());
+    let target = processor.target(&original);
+    fs::create_dir_all(target.parent().unwrap()).expect("mkdir");
+    fs::write(&target, "kept").expect("write");
+
+    assert_eq!(processor.convert(&original), Ok(target.clone()));
+    assert_eq!(fs::read_to_string(&target).expect("read"), "kept");
+}
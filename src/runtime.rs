@@ -0,0 +1,65 @@
+//! The tokio runtime the download engine runs on.
+//!
+//! Everything in [`crate::funcs`], [`crate::commands`] and
+//! [`crate::downloader`] that touches the network is `async`. Callers without
+//! a runtime of their own (the CLI and the TUI) drive those futures with
+//! [`block_on`], usually through the wrappers in [`crate::blocking`].
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+use tokio::runtime::Runtime;
+use tokio_util::sync::CancellationToken;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// The process-wide multi-threaded runtime, started on first use.
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("e-cli-worker")
+            .build()
+            .expect("Error starting the async runtime")
+    })
+}
+
+/// Runs `future` to completion on [`runtime`], blocking the current thread.
+/// Must not be called from inside an async context.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
+
+/// Runs `future`, unless `cancel` fires first, in which case the future is
+/// dropped where it stands and `None` is returned.
+pub async fn cancellable<F: Future>(
+    cancel: Option<&CancellationToken>,
+    future: F,
+) -> Option<F::Output> {
+    match cancel {
+        Some(cancel) => tokio::select! {
+            biased;
+            _ = cancel.cancelled() => None,
+            output = future => Some(output),
+        },
+        None => Some(future.await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancellable_drops_the_future_once_cancelled() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = block_on(cancellable(Some(&cancel), std::future::pending::<()>()));
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn cancellable_runs_to_completion_without_a_token() {
+        assert_eq!(block_on(cancellable(None, async { 7 })), Some(7));
+    }
+}
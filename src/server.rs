@@ -0,0 +1,773 @@
+//! `e-cli serve`: a local web interface over the download directory. It
+//! searches the downloaded posts by tag, shows them, deletes them (keeping
+//! the tracking file and duplicate index in step), and starts new downloads
+//! as [`crate::jobs`].
+//!
+//! Tags and ratings come from the same places as the [`crate::gallery`]'s,
+//! plus `.e-cli-library.json`, where the server keeps the records of the
+//! downloads it ran. It listens on localhost unless told otherwise, and only
+//! answers requests addressed to the host it listens on, so other websites
+//! open in the same browser can't reach it. Given a token, it only deletes
+//! files and starts or cancels downloads for requests that bring it, and it
+//! needs one to listen on another than the loopback address.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use axum::body::Body;
+use axum::extract::{self, Query, Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::duplicate::DuplicateIndex;
+use crate::gallery::{self, Item};
+use crate::jobs::{JobInfo, JobRequest, Jobs};
+use crate::state::RunLock;
+use crate::tracker::Tracker;
+use crate::{CliContext, DownloadRecord, Login, manifest};
+
+/// The manifest the server keeps in the download directory, with the
+/// records of the downloads it ran.
+pub const LIBRARY: &str = ".e-cli-library.json";
+
+/// Search results are sent this many at a time, unless asked otherwise.
+const PAGE_SIZE: usize = 100;
+
+/// The downloaded files and what's known about them.
+pub struct Library {
+    dir: PathBuf,
+    records: RwLock<Vec<DownloadRecord>>,
+    tracker: Option<Arc<Tracker>>,
+    duplicate_index: Option<Arc<DuplicateIndex>>,
+}
+
+impl Library {
+    /// The library in `dir`, with the records of its [`LIBRARY`] file and
+    /// then of the run manifests at `manifests`.
+    pub fn load(
+        dir: &Path,
+        manifests: &[PathBuf],
+        tracker: Option<Arc<Tracker>>,
+        duplicate_index: Option<Arc<DuplicateIndex>>,
+    ) -> Result<Self, String> {
+        let mut records = Vec::new();
+        let library = dir.join(LIBRARY);
+        if library.exists() {
+            records.extend(manifest::read(&library)?);
+        }
+        for path in manifests {
+            records.extend(manifest::read(path)?);
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            records: RwLock::new(records),
+            tracker,
+            duplicate_index,
+        })
+    }
+
+    /// The files matching a tag search `query` (see [`matches`]), sorted by
+    /// path.
+    pub fn search(&self, query: &str) -> io::Result<Vec<Item>> {
+        let items = gallery::items(&self.dir, &self.records.read().unwrap())?;
+        Ok(items
+            .into_iter()
+            .filter(|item| matches(item, query))
+            .collect())
+    }
+
+    /// Remembers the tags and ratings of downloaded posts among `records`,
+    /// saving them to the [`LIBRARY`] file.
+    pub fn add_records(&self, records: &[DownloadRecord]) {
+        let mut known = self.records.write().unwrap();
+        let before = known.len();
+        known.extend(
+            records
+                .iter()
+                .filter(|record| record.local_filename.is_some())
+                .filter(|record| !record.tags.is_empty() || !record.rating.is_empty())
+                .cloned(),
+        );
+        if known.len() == before {
+            return;
+        }
+        *known = latest_records(&known);
+        if let Err(e) = manifest::write_records(&self.dir.join(LIBRARY), &known) {
+            warn!("{e}");
+        }
+    }
+
+    /// The file at `path`, relative to the download directory, if `path`
+    /// stays inside it and isn't one of e-cli's own files.
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        library_path(path).map(|path| self.dir.join(path))
+    }
+
+    /// Locks the download directory for [`Library::delete`], failing if a
+    /// run holds it.
+    pub fn lock(&self) -> Result<RunLock, String> {
+        RunLock::acquire(&self.dir, false)
+    }
+
+    /// Deletes the file at `path` (relative to the download directory) and
+    /// its thumbnail, and forgets its hash in the duplicate index, so the
+    /// post can be downloaded again. Its post is tracked, so later runs with
+    /// the tracking file skip it, unless `forget` is set, in which case it's
+    /// untracked so they download it again. Both are read again first, under
+    /// the directory's `lock`, so what runs added meanwhile is kept.
+    pub fn delete(&self, _lock: &RunLock, path: &str, forget: bool) -> Result<(), String> {
+        let relative = library_path(path).ok_or_else(|| format!("No such file: {path}"))?;
+        let full = self.dir.join(&relative);
+        if let Some(index) = &self.duplicate_index {
+            index
+                .reload()
+                .map_err(|e| format!("Failed to read the duplicate index: {e}"))?;
+        }
+        if let Some(tracker) = &self.tracker {
+            tracker.reload().map_err(|e| {
+                format!(
+                    "Failed to read tracking file {}: {e}",
+                    tracker.path().display()
+                )
+            })?;
+        }
+        fs::remove_file(&full).map_err(|e| format!("Failed to delete {path}: {e}"))?;
+        info!("Deleted {}.", full.display());
+        let _ = fs::remove_file(self.dir.join(gallery::thumbnail_path(&relative)));
+
+        let name = relative
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        if let Some(index) = &self.duplicate_index
+            && let Err(e) = index.remove(&name)
+        {
+            warn!("Failed to update the duplicate index: {e}");
+        }
+        if let (Some(tracker), Some(post_id)) = (&self.tracker, gallery::post_id(&name)) {
+            if !forget {
+                tracker.insert(post_id);
+            } else if let Err(e) = tracker.remove(post_id) {
+                warn!(
+                    "Failed to update tracking file {}: {e}",
+                    tracker.path().display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The latest of the records of each post in `records`, by post ID.
+fn latest_records(records: &[DownloadRecord]) -> Vec<DownloadRecord> {
+    let mut latest = BTreeMap::new();
+    for record in records {
+        latest.insert(record.post_id, record.clone());
+    }
+    latest.into_values().collect()
+}
+
+/// Whether `item` matches a tag search `query`: space-separated tags it must
+/// have, `-tag` for tags it mustn't, `tag*` for any tag starting with `tag`,
+/// and `rating:s` (`q`, `e`, or spelled out) for its rating.
+pub fn matches(item: &Item, query: &str) -> bool {
+    query.split_whitespace().all(|term| {
+        let term = term.to_lowercase();
+        let (negated, term) = match term.strip_prefix('-') {
+            Some(term) => (true, term),
+            None => (false, term.as_str()),
+        };
+        let found = match term.strip_prefix("rating:") {
+            Some(rating) => !item.rating.is_empty() && rating.starts_with(item.rating.as_str()),
+            None => match term.strip_suffix('*') {
+                Some(prefix) => item
+                    .tags
+                    .iter()
+                    .any(|tag| tag.to_lowercase().starts_with(prefix)),
+                None => item.tags.iter().any(|tag| tag.eq_ignore_ascii_case(term)),
+            },
+        };
+        found != negated
+    })
+}
+
+/// `path` as a path relative to the download directory, if it is one that
+/// stays inside it and doesn't lead into e-cli's own files (named with a
+/// leading `.`).
+fn library_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let inside = path.components().all(|component| match component {
+        Component::Normal(part) => !part.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+    (inside && path.components().next().is_some()).then(|| path.to_path_buf())
+}
+
+/// Whether a request's `Host` header names the address the server listens
+/// on: anything goes on an unspecified address (`0.0.0.0`), otherwise it
+/// must be that address or, on loopback, `localhost`. Turns away pages on
+/// other sites that make the browser send requests here (DNS rebinding).
+fn allowed_host(host: &str, bind: IpAddr) -> bool {
+    if bind.is_unspecified() {
+        return true;
+    }
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.parse::<IpAddr>().is_ok_and(|ip| ip == bind)
+        || (bind.is_loopback() && name.eq_ignore_ascii_case("localhost"))
+}
+
+/// What [`serve`] shares between requests.
+struct Server {
+    library: Arc<Library>,
+    jobs: Arc<Jobs>,
+    api_source: &'static str,
+    bind: IpAddr,
+}
+
+type Shared = Arc<Server>;
+pub(crate) type Failure = (StatusCode, String);
+
+/// Serves the web interface for the download directory `dir` at `addr`,
+/// until `context`'s cancellation token fires, then cancels the downloads
+/// still running and waits for them to stop. Downloads started from it run
+/// with `context` and `login`, recording posts in `tracker`; tags and
+/// ratings are also read from the run manifests at `manifests`. Preset
+/// downloads run the presets of `config`, with their accounts. Requests that change
+/// something must bring `token`, if there is one; there must be one to
+/// listen on another than the loopback address.
+#[allow(clippy::too_many_arguments)]
+pub async fn serve(
+    addr: SocketAddr,
+    context: CliContext,
+    login: Login,
+    dir: &Path,
+    manifests: &[PathBuf],
+    tracker: Option<Arc<Tracker>>,
+    config: &Config,
+    token: Option<String>,
+) -> Result<(), String> {
+    if token.is_none() && !addr.ip().is_loopback() {
+        return Err(format!(
+            "Listening on {} lets others on the network delete files and start downloads; pass --token-file to set the token they need.",
+            addr.ip()
+        ));
+    }
+    crate::funcs::ensure_dl_dir(dir);
+    let library = Arc::new(Library::load(
+        dir,
+        manifests,
+        tracker.clone(),
+        context.duplicate_index.clone(),
+    )?);
+    let finished = {
+        let library = Arc::clone(&library);
+        Arc::new(move |stats: &crate::DownloadStatistics| library.add_records(&stats.records))
+    };
+    let cancel = context.cancel.clone().unwrap_or_default();
+    let server = Arc::new(Server {
+        library,
+        api_source: context.api_source(),
+        jobs: Jobs::new(context, login, dir.to_path_buf(), tracker, Some(finished)),
+        bind: addr.ip(),
+    });
+    server.jobs.set_config(config);
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Failed to listen on {addr}: {e}"))?;
+    let addr = listener.local_addr().unwrap_or(addr);
+    info!(
+        "Serving {} at http://{addr}/ (Ctrl+C to stop).",
+        dir.display()
+    );
+    let jobs = Arc::clone(&server.jobs);
+    let served = axum::serve(listener, router(server, token.map(Arc::from)))
+        .with_graceful_shutdown(async move { cancel.cancelled().await })
+        .await
+        .map_err(|e| format!("The server stopped: {e}"));
+    let _ = tokio::task::spawn_blocking(move || jobs.stop()).await;
+    served
+}
+
+fn router(server: Shared, token: Option<Arc<str>>) -> Router {
+    let router = Router::new()
+        .route("/", get(|| async { Html(PAGE) }))
+        .route("/api/posts", get(search))
+        .route("/api/files/{*path}", delete(delete_file))
+        .route("/files/{*path}", get(file))
+        .route("/thumbs/{*path}", get(thumbnail))
+        .with_state(server.clone())
+        .merge(job_routes(Arc::clone(&server.jobs)));
+    let router = match token {
+        Some(token) => router.layer(middleware::from_fn_with_state(token, check_changes)),
+        None => router,
+    };
+    router.layer(middleware::from_fn_with_state(server.bind, check_host))
+}
+
+/// `/api/jobs`, to list, start and cancel [`Jobs`].
+pub(crate) fn job_routes(jobs: Arc<Jobs>) -> Router {
+    Router::new()
+        .route("/api/jobs", get(list_jobs).post(start_job))
+        .route("/api/jobs/{id}", delete(cancel_job))
+        .with_state(jobs)
+}
+
+/// Turns away requests that aren't addressed to `bind` (see
+/// [`allowed_host`]).
+pub(crate) async fn check_host(
+    State(bind): State<IpAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or_default();
+    if !allowed_host(host, bind) {
+        return (StatusCode::FORBIDDEN, "Unknown host.").into_response();
+    }
+    next.run(request).await
+}
+
+/// Turns away requests without `Authorization: Bearer <token>`.
+pub(crate) async fn check_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !same_token(given.trim(), &token) {
+        return (StatusCode::UNAUTHORIZED, "A valid API token is required.").into_response();
+    }
+    next.run(request).await
+}
+
+/// Like [`check_token`], but only for requests that change something: the
+/// files and downloads can be looked at without it.
+async fn check_changes(state: State<Arc<str>>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    check_token(state, request, next).await
+}
+
+/// Compares tokens in a time that doesn't depend on where they differ.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct PostView {
+    /// Relative to the download directory, with `/` separators.
+    path: String,
+    post_id: Option<u64>,
+    post_url: Option<String>,
+    rating: String,
+    tags: Vec<String>,
+    /// Whether `/thumbs/<path>` has a thumbnail of it.
+    thumbnail: bool,
+}
+
+#[derive(Serialize)]
+struct SearchResults {
+    total: usize,
+    posts: Vec<PostView>,
+}
+
+async fn search(
+    State(server): State<Shared>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResults>, Failure> {
+    let library = Arc::clone(&server.library);
+    let items = tokio::task::spawn_blocking(move || library.search(&query.q))
+        .await
+        .map_err(internal)?
+        .map_err(internal)?;
+    let posts = items
+        .iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(PAGE_SIZE))
+        .map(|item| {
+            let path = item
+                .path
+                .iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            PostView {
+                thumbnail: gallery::thumbnailed(&path),
+                path,
+                post_id: item.post_id,
+                post_url: item
+                    .post_id
+                    .map(|id| format!("https://{}/posts/{id}", server.api_source)),
+                rating: item.rating.clone(),
+                tags: item.tags.clone(),
+            }
+        })
+        .collect();
+    Ok(Json(SearchResults {
+        total: items.len(),
+        posts,
+    }))
+}
+
+async fn file(
+    State(server): State<Shared>,
+    extract::Path(path): extract::Path<String>,
+) -> Result<Response, Failure> {
+    let full = server.library.resolve(&path).ok_or_else(not_found)?;
+    let file = tokio::fs::File::open(&full)
+        .await
+        .map_err(|_| not_found())?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type(&path)),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+async fn thumbnail(
+    State(server): State<Shared>,
+    extract::Path(path): extract::Path<String>,
+) -> Result<Response, Failure> {
+    let full = server.library.resolve(&path).ok_or_else(not_found)?;
+    if !gallery::thumbnailed(&path) || !full.is_file() {
+        return Err(not_found());
+    }
+    let thumbnail = server
+        .library
+        .dir
+        .join(gallery::thumbnail_path(Path::new(&path)));
+    let bytes = tokio::task::spawn_blocking(move || {
+        gallery::write_thumbnail(&full, &thumbnail)?;
+        fs::read(&thumbnail).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(internal)?
+    .map_err(internal)?;
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], bytes).into_response())
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    #[serde(default)]
+    forget: bool,
+}
+
+async fn delete_file(
+    State(server): State<Shared>,
+    extract::Path(path): extract::Path<String>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<StatusCode, Failure> {
+    if !server
+        .library
+        .resolve(&path)
+        .is_some_and(|full| full.is_file())
+    {
+        return Err(not_found());
+    }
+    let library = Arc::clone(&server.library);
+    tokio::task::spawn_blocking(move || {
+        // Busy while a download runs into the directory, this one's or another.
+        let lock = library.lock().map_err(|e| (StatusCode::CONFLICT, e))?;
+        library.delete(&lock, &path, query.forget).map_err(internal)
+    })
+    .await
+    .map_err(internal)??;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_jobs(State(jobs): State<Arc<Jobs>>) -> Json<Vec<JobInfo>> {
+    Json(jobs.list())
+}
+
+#[derive(Serialize)]
+struct Started {
+    id: u64,
+}
+
+// `Json` only takes `application/json` bodies, which browsers won't send to
+// another site without asking it first, unlike form posts.
+async fn start_job(
+    State(jobs): State<Arc<Jobs>>,
+    Json(request): Json<JobRequest>,
+) -> Result<Json<Started>, Failure> {
+    let id = jobs
+        .start(request)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(Started { id }))
+}
+
+async fn cancel_job(
+    State(jobs): State<Arc<Jobs>>,
+    extract::Path(id): extract::Path<u64>,
+) -> StatusCode {
+    if jobs.cancel(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+fn not_found() -> Failure {
+    (StatusCode::NOT_FOUND, "No such file.".to_owned())
+}
+
+pub(crate) fn internal(e: impl std::fmt::Display) -> Failure {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// The `Content-Type` of a downloaded file, by its extension.
+fn content_type(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("webm") => "video/webm",
+        Some("mp4") => "video/mp4",
+        Some("swf") => "application/x-shockwave-flash",
+        _ => "application/octet-stream",
+    }
+}
+
+const PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>e-cli</title>
+<style>
+body { margin: 0; font-family: sans-serif; background: #1f2330; color: #e8e8e8; }
+header, #downloads { display: flex; flex-wrap: wrap; gap: 0.5em 1em; align-items: center;
+  padding: 0.75em 1em; }
+header { position: sticky; top: 0; z-index: 1; background: #152f56; }
+#downloads { background: #2b3144; }
+h1 { margin: 0; font-size: 1.2em; }
+form { display: flex; flex-wrap: wrap; gap: 0.5em; align-items: center; }
+#search { flex: 1; }
+#q { flex: 1; min-width: 12em; }
+#query { min-width: 16em; }
+#count { width: 5em; }
+input, select, button { padding: 0.3em; }
+#jobs { width: 100%; margin: 0; padding-left: 1.2em; font-size: 0.9em; }
+#status { margin: 1em 1em 0; }
+main { display: grid; grid-template-columns: repeat(auto-fill, minmax(180px, 1fr));
+  gap: 0.75em; padding: 1em; }
+figure { margin: 0; text-align: center; }
+figure img, .file { display: block; width: 100%; height: 180px; object-fit: contain;
+  background: #2b3144; }
+.file { line-height: 180px; color: #e8e8e8; text-decoration: none; font-weight: bold; }
+figcaption { display: flex; justify-content: space-between; align-items: center;
+  padding: 0.25em; font-size: 0.85em; }
+a { color: #f2ac08; }
+#more { display: block; margin: 0 auto 2em; }
+</style>
+</head>
+<body>
+<header>
+<h1>e-cli</h1>
+<form id="search">
+<input id="q" type="search" placeholder="Tags, e.g. dragon -sketch rating:s" autofocus>
+<button>Search</button>
+</form>
+<label title="Untrack deleted posts, so later runs with the tracking file download them again">
+<input type="checkbox" id="forget"> Download deleted posts again</label>
+</header>
+<section id="downloads">
+<form id="start">
+<select id="kind">
+<option value="tags">Tags</option>
+<option value="favs">Favourites of</option>
+<option value="pool">Pools</option>
+<option value="preset">Preset</option>
+</select>
+<input id="query" placeholder="Tags, a username, pool IDs or names (comma-separated), or a preset" required>
+<input id="count" type="number" min="1" max="250" value="50" title="Posts per page">
+<label><input type="checkbox" id="random"> Random</label>
+<button>Download</button>
+</form>
+<ul id="jobs"></ul>
+</section>
+<p id="status"></p>
+<main id="posts"></main>
+<button id="more" hidden>More</button>
+<script>
+const $ = id => document.getElementById(id);
+let offset = 0;
+
+async function request(url, options = {}) {
+  const token = sessionStorage.getItem("token");
+  const headers = { ...options.headers };
+  if (token) headers.Authorization = `Bearer ${token}`;
+  const response = await fetch(url, { ...options, headers });
+  if (response.status === 401) {
+    const given = prompt("Changes need the server's token (see --token-file):");
+    if (given) {
+      sessionStorage.setItem("token", given.trim());
+      return request(url, options);
+    }
+  }
+  if (!response.ok) throw new Error(await response.text() || response.statusText);
+  return response;
+}
+
+const encodePath = path => path.split("/").map(encodeURIComponent).join("/");
+
+async function search(more) {
+  if (!more) {
+    offset = 0;
+    $("posts").replaceChildren();
+  }
+  try {
+    const query = encodeURIComponent($("q").value);
+    const page = await (await request(`/api/posts?q=${query}&offset=${offset}`)).json();
+    $("posts").append(...page.posts.map(tile));
+    offset += page.posts.length;
+    $("status").textContent = `${page.total} files`;
+    $("more").hidden = offset >= page.total;
+  } catch (e) {
+    $("status").textContent = e.message;
+  }
+}
+
+function tile(post) {
+  const figure = document.createElement("figure");
+  figure.title = post.tags.join(" ");
+  const link = document.createElement("a");
+  link.href = "/files/" + encodePath(post.path);
+  link.target = "_blank";
+  if (post.thumbnail) {
+    const image = document.createElement("img");
+    image.loading = "lazy";
+    image.src = "/thumbs/" + encodePath(post.path);
+    image.alt = post.path;
+    link.append(image);
+  } else {
+    link.className = "file";
+    link.textContent = post.path.split(".").pop().toUpperCase();
+  }
+  const caption = document.createElement("figcaption");
+  const name = document.createElement("a");
+  name.textContent = post.post_id ? "#" + post.post_id : post.path.split("/").pop();
+  if (post.post_url) name.href = post.post_url;
+  const remove = document.createElement("button");
+  remove.textContent = "Delete";
+  remove.onclick = async () => {
+    if (!confirm(`Delete ${post.path}?`)) return;
+    try {
+      await request(`/api/files/${encodePath(post.path)}?forget=${$("forget").checked}`,
+        { method: "DELETE" });
+      figure.remove();
+    } catch (e) {
+      alert(e.message);
+    }
+  };
+  caption.append(name, remove);
+  figure.append(link, caption);
+  return figure;
+}
+
+async function refreshJobs() {
+  try {
+    const jobs = await (await request("/api/jobs")).json();
+    $("jobs").replaceChildren(...jobs.reverse().map(job => {
+      const item = document.createElement("li");
+      item.textContent = `#${job.id} ${job.description}: ${job.phase ?? job.state}, ` +
+        `${job.completed} downloaded, ${job.failed} failed, ${job.skipped} skipped of ${job.total}` +
+        (job.error ? ` (${job.error})` : "");
+      if (job.state === "queued" || job.state === "running") {
+        const cancel = document.createElement("button");
+        cancel.textContent = "Cancel";
+        cancel.onclick = () => request(`/api/jobs/${job.id}`, { method: "DELETE" })
+          .then(refreshJobs, e => alert(e.message));
+        item.append(" ", cancel);
+      }
+      return item;
+    }));
+  } catch (e) {}
+}
+
+$("search").onsubmit = event => {
+  event.preventDefault();
+  search(false);
+};
+$("more").onclick = () => search(true);
+$("start").onsubmit = async event => {
+  event.preventDefault();
+  const kind = $("kind").value;
+  const query = $("query").value.trim();
+  const count = Number($("count").value);
+  const random = $("random").checked;
+  const body = kind === "pool"
+    ? { kind, pools: query.split(",").map(pool => pool.trim()).filter(Boolean) }
+    : kind === "favs"
+      ? { kind, username: query, count, random }
+      : kind === "preset"
+        ? { kind, name: query, random }
+        : { kind, tags: query, count, random };
+  try {
+    await request("/api/jobs", {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify(body),
+    });
+    $("query").value = "";
+    refreshJobs();
+  } catch (e) {
+    alert(e.message);
+  }
+};
+setInterval(refreshJobs, 2000);
+refreshJobs();
+search(false);
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+#[path = "server_tests.rs"]
+mod tests;
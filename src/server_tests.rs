@@ -0,0 +1,299 @@
+use super::*;
+use crate::RecordStatus;
+
+fn item(rating: &str, tags: &[&str]) -> Item {
+    Item {
+        path: PathBuf::from("someartist-1.png"),
+        post_id: Some(1),
+        rating: rating.into(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        thumbnail: None,
+    }
+}
+
+fn record(post_id: u64, tags: &[&str]) -> DownloadRecord {
+    DownloadRecord {
+        post_id,
+        source_url: None,
+        md5: None,
+        artist: "someartist".into(),
+        rating: "s".into(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        extension: "png".into(),
+        local_filename: Some(format!("someartist-{post_id}.png")),
+        status: RecordStatus::Completed,
+        bytes: 0,
+        error: None,
+        derived: None,
+    }
+}
+
+#[test]
+fn matches_tags_negations_prefixes_and_ratings() {
+    let item = item("s", &["Dragon", "solo", "clouds"]);
+    assert!(matches(&item, ""));
+    assert!(matches(&item, "dragon solo"));
+    assert!(!matches(&item, "dragon wolf"));
+    assert!(matches(&item, "dragon -wolf"));
+    assert!(!matches(&item, "-solo"));
+    assert!(matches(&item, "cloud*"));
+    assert!(matches(&item, "rating:s"));
+    assert!(matches(&item, "rating:safe"));
+    assert!(!matches(&item, "rating:e"));
+    assert!(matches(&item, "-rating:e"));
+}
+
+#[test]
+fn unknown_ratings_match_no_rating() {
+    assert!(!matches(&item("", &[]), "rating:s"));
+}
+
+#[test]
+fn library_paths_stay_inside_the_download_directory() {
+    assert_eq!(
+        library_path("Cloud Jumping/0001-someartist-3.png"),
+        Some(PathBuf::from("Cloud Jumping/0001-someartist-3.png"))
+    );
+    assert_eq!(library_path(""), None);
+    assert_eq!(library_path("../secret.png"), None);
+    assert_eq!(library_path("a/../../secret.png"), None);
+    assert_eq!(library_path("/etc/passwd"), None);
+    assert_eq!(library_path(".e-cli-tracked.txt"), None);
+    assert_eq!(library_path(".e-cli-thumbs/someartist-1.jpg"), None);
+}
+
+#[test]
+fn only_the_bound_host_is_allowed() {
+    let localhost = IpAddr::from([127, 0, 0, 1]);
+    assert!(allowed_host("127.0.0.1:8080", localhost));
+    assert!(allowed_host("localhost:8080", localhost));
+    assert!(allowed_host("LOCALHOST", localhost));
+    assert!(!allowed_host("evil.example:8080", localhost));
+    assert!(!allowed_host("192.168.1.2:8080", localhost));
+
+    let lan = IpAddr::from([192, 168, 1, 2]);
+    assert!(allowed_host("192.168.1.2:8080", lan));
+    assert!(!allowed_host("localhost:8080", lan));
+
+    assert!(allowed_host("[::1]:8080", "::1".parse().unwrap()));
+    assert!(allowed_host("anything:8080", IpAddr::from([0, 0, 0, 0])));
+}
+
+/// A download directory with one image, tracked and in the duplicate index.
+fn downloaded() -> (tempfile::TempDir, Arc<Tracker>, Arc<DuplicateIndex>) {
+    let dir = tempfile::tempdir().expect("tempdir");
+    fs::write(dir.path().join("someartist-1.png"), b"png").expect("write");
+    let tracker = Arc::new(Tracker::load(&dir.path().join("tracked.txt")).expect("tracker"));
+    tracker.insert(1);
+    let index = Arc::new(DuplicateIndex::load(&dir.path().join("hashes.json")).expect("index"));
+    index.insert("abc", "someartist-1.png");
+    (dir, tracker, index)
+}
+
+#[test]
+fn delete_keeps_the_post_tracked() {
+    let (dir, tracker, index) = downloaded();
+    let library = Library::load(
+        dir.path(),
+        &[],
+        Some(Arc::clone(&tracker)),
+        Some(Arc::clone(&index)),
+    )
+    .expect("load");
+
+    library
+        .delete(&library.lock().expect("lock"), "someartist-1.png", false)
+        .expect("delete");
+    assert!(!dir.path().join("someartist-1.png").exists());
+    assert!(tracker.contains(1));
+    assert_eq!(index.contains("abc"), None);
+}
+
+#[test]
+fn delete_with_forget_untracks_the_post() {
+    let (dir, tracker, index) = downloaded();
+    let library =
+        Library::load(dir.path(), &[], Some(Arc::clone(&tracker)), Some(index)).expect("load");
+
+    library
+        .delete(&library.lock().expect("lock"), "someartist-1.png", true)
+        .expect("delete");
+    assert!(!tracker.contains(1));
+}
+
+#[test]
+fn delete_refuses_paths_outside_the_library() {
+    let (dir, _, _) = downloaded();
+    let library = Library::load(dir.path(), &[], None, None).expect("load");
+    assert!(
+        library
+            .delete(&library.lock().expect("lock"), "../someartist-1.png", false)
+            .is_err()
+    );
+    assert!(
+        library
+            .delete(&library.lock().expect("lock"), "missing-2.png", false)
+            .is_err()
+    );
+}
+
+#[test]
+fn added_records_are_searchable_after_a_reload() {
+    let (dir, _, _) = downloaded();
+    let library = Library::load(dir.path(), &[], None, None).expect("load");
+    assert!(library.search("dragon").expect("search").is_empty());
+
+    let mut failed = record(2, &["dragon"]);
+    failed.local_filename = None;
+    library.add_records(&[record(1, &["wolf"]), failed]);
+    library.add_records(&[record(1, &["dragon"])]);
+    assert_eq!(library.search("dragon").expect("search").len(), 1);
+
+    let library = Library::load(dir.path(), &[], None, None).expect("reload");
+    let found = library.search("dragon").expect("search");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].post_id, Some(1));
+    assert!(library.search("wolf").expect("search").is_empty());
+    assert_eq!(manifest::read(&dir.path().join(LIBRARY)).unwrap().len(), 1);
+}
+
+#[test]
+fn delete_keeps_what_other_runs_added_meanwhile() {
+    let (dir, tracker, index) = downloaded();
+    let library = Library::load(
+        dir.path(),
+        &[],
+        Some(Arc::clone(&tracker)),
+        Some(Arc::clone(&index)),
+    )
+    .expect("load");
+    // Another run, with its own view of the files.
+    let other = DuplicateIndex::load(&dir.path().join("hashes.json")).expect("index");
+    other.insert("def", "someartist-2.png");
+    let other = Tracker::load(&dir.path().join("tracked.txt")).expect("tracker");
+    other.insert(2);
+    other.insert(3);
+    other.remove(3).expect("untrack");
+
+    let lock = library.lock().expect("lock");
+    library
+        .delete(&lock, "someartist-1.png", true)
+        .expect("delete");
+    let index = DuplicateIndex::load(&dir.path().join("hashes.json")).expect("reload");
+    assert_eq!(index.contains("abc"), None);
+    assert_eq!(index.contains("def"), Some("someartist-2.png".into()));
+    let tracker = Tracker::load(&dir.path().join("tracked.txt")).expect("reload");
+    assert!(!tracker.contains(1));
+    assert!(tracker.contains(2));
+}
+
+#[test]
+fn deleting_is_refused_while_a_run_holds_the_directory() {
+    let (dir, _, _) = downloaded();
+    let library = Library::load(dir.path(), &[], None, None).expect("load");
+    let _run = RunLock::acquire(dir.path(), false).expect("lock");
+    assert!(library.lock().is_err());
+}
+
+#[test]
+fn tokens_are_compared_whole() {
+    assert!(same_token("secret", "secret"));
+    assert!(!same_token("secre", "secret"));
+    assert!(!same_token("secrex", "secret"));
+    assert!(!same_token("", "secret"));
+}
+
+fn context() -> CliContext {
+    CliContext {
+        verbose: false,
+        nsfw: false,
+        lower_quality: false,
+        pages: 1,
+        file_bars: false,
+        num_threads: 1,
+        retries: 0,
+        duplicate_index: None,
+        cancel: None,
+        rate_limit: None,
+        budget: None,
+        disk: None,
+        postprocess: None,
+        progress: None,
+    }
+}
+
+#[test]
+fn changes_need_the_token_if_there_is_one() {
+    let (dir, _, _) = downloaded();
+    let cancel = tokio_util::sync::CancellationToken::new();
+    cancel.cancel();
+    let context = CliContext {
+        cancel: Some(cancel),
+        ..context()
+    };
+    let library = Arc::new(Library::load(dir.path(), &[], None, None).expect("load"));
+    crate::runtime::block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = Arc::new(Server {
+            library,
+            jobs: Jobs::new(context, Login::default(), dir.path().into(), None, None),
+            api_source: "e926.net",
+            bind: addr.ip(),
+        });
+        tokio::spawn(axum::serve(listener, router(server, Some("secret".into()))).into_future());
+        let url = format!("http://{addr}");
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{url}/api/posts"))
+            .send()
+            .await
+            .expect("search");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let response = client
+            .delete(format!("{url}/api/files/someartist-1.png"))
+            .send()
+            .await
+            .expect("delete");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let job = serde_json::json!({"kind": "tags", "tags": "dragon"});
+        let response = client
+            .post(format!("{url}/api/jobs"))
+            .bearer_auth("guess")
+            .json(&job)
+            .send()
+            .await
+            .expect("start");
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = client
+            .post(format!("{url}/api/jobs"))
+            .bearer_auth("secret")
+            .json(&job)
+            .send()
+            .await
+            .expect("start");
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    });
+    assert!(dir.path().join("someartist-1.png").exists());
+}
+
+#[test]
+fn other_addresses_need_a_token() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let context = context();
+    let error = crate::runtime::block_on(serve(
+        "0.0.0.0:0".parse().unwrap(),
+        context,
+        Login::default(),
+        dir.path(),
+        &[],
+        None,
+        &Config::default(),
+        None,
+    ))
+    .unwrap_err();
+    assert!(error.contains("--token-file"), "{error}");
+}
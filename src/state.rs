@@ -0,0 +1,124 @@
+//! Keeps the state files in a download directory (tracking file, duplicate
+//! index, manifests) consistent when several e-cli processes share it: a
+//! per-directory [`RunLock`], and [`write_atomic`] for files rewritten whole.
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+/// Name of the lock file a [`RunLock`] holds in its directory.
+pub const LOCK_FILE: &str = ".e-cli.lock";
+
+/// An advisory lock on a download directory, held for as long as this value
+/// lives. The lock file keeps the holder's PID, for the "busy" error.
+///
+/// The lock is released by the OS when the process exits, however it exits,
+/// so a leftover lock file never blocks later runs.
+pub struct RunLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl RunLock {
+    /// Locks `dir` (creating it if missing). If another process holds the
+    /// lock, fails with a "directory busy" error naming its PID, or with
+    /// `wait`, blocks until it's released.
+    pub fn acquire(dir: &Path, wait: bool) -> Result<Self, String> {
+        let path = dir.join(LOCK_FILE);
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        // Not truncated on open: until locked, the PID in it is the holder's.
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| format!("Failed to open lock file {}: {e}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = holder_pid(&mut file);
+                if !wait {
+                    return Err(format!(
+                        "Directory {} is busy (pid {holder}). Pass --wait to wait for that run to finish.",
+                        dir.display()
+                    ));
+                }
+                info!(
+                    "Waiting for the run in {} (pid {holder}) to finish...",
+                    dir.display()
+                );
+                file.lock()
+                    .map_err(|e| format!("Failed to lock {}: {e}", path.display()))?;
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(format!("Failed to lock {}: {e}", path.display()));
+            }
+        }
+        file.set_len(0)
+            .and_then(|()| file.rewind())
+            .and_then(|()| write!(file, "{}", std::process::id()))
+            .and_then(|()| file.flush())
+            .map_err(|e| format!("Failed to write lock file {}: {e}", path.display()))?;
+        Ok(Self { _file: file, path })
+    }
+
+    /// The path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The PID recorded in a lock file, or `?` if it can't be read.
+fn holder_pid(file: &mut File) -> String {
+    let mut content = String::new();
+    match file.read_to_string(&mut content) {
+        Ok(_) if !content.trim().is_empty() => content.trim().to_owned(),
+        _ => "?".to_owned(),
+    }
+}
+
+/// Replaces `path` with `contents` all at once: the data is written to a
+/// temporary file next to it, synced, then renamed over it, so a crash or a
+/// concurrent reader never sees a half-written file.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    replace(path, contents.as_ref(), OpenOptions::new())
+}
+
+/// [`write_atomic`] for secrets: on Unix, the file is created readable and
+/// writable only by the current user.
+pub fn write_private(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    replace(path, contents.as_ref(), options)
+}
+
+fn replace(path: &Path, contents: &[u8], mut options: OpenOptions) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other(format!("{} is not a file path", path.display())))?;
+    let mut temp_name = name.to_owned();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+    let result = options
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+#[path = "state_tests.rs"]
+mod tests;
@@ -0,0 +1,46 @@
+use super::*;
+
+#[test]
+fn second_lock_on_a_directory_reports_the_holder() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let lock = RunLock::acquire(dir.path(), false).expect("first lock");
+    assert_eq!(
+        fs::read_to_string(lock.path()).expect("read lock file"),
+        std::process::id().to_string()
+    );
+
+    let error = RunLock::acquire(dir.path(), false).err().expect("busy");
+    assert!(error.contains("is busy"), "{error}");
+    assert!(
+        error.contains(&format!("pid {}", std::process::id())),
+        "{error}"
+    );
+
+    drop(lock);
+    RunLock::acquire(dir.path(), false).expect("lock after release");
+}
+
+#[test]
+fn waiting_lock_is_granted_once_released() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let lock = RunLock::acquire(dir.path(), false).expect("first lock");
+    let path = dir.path().to_path_buf();
+    let waiter = std::thread::spawn(move || RunLock::acquire(&path, true).map(|_| ()));
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(!waiter.is_finished());
+    drop(lock);
+    waiter.join().expect("join").expect("lock after waiting");
+}
+
+#[test]
+fn write_atomic_replaces_the_file_without_leftovers() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("state.json");
+    fs::write(&path, "old contents that are longer").expect("write");
+
+    write_atomic(&path, "new").expect("write_atomic");
+
+    assert_eq!(fs::read_to_string(&path).expect("read"), "new");
+    let entries = fs::read_dir(dir.path()).expect("read_dir").count();
+    assert_eq!(entries, 1);
+}
@@ -14,7 +14,7 @@ use tracing::{debug, warn};
 /// Backed by a plain-text file with one post ID per line (unparseable lines
 /// are ignored on load). Every [`Tracker::insert`] is appended to the file
 /// immediately, so history survives an interrupted run. All methods are safe
-/// to call from multiple threads (as [`crate::commands`] does via rayon).
+/// to call from concurrent downloads (as [`crate::downloader`] runs them).
 pub struct Tracker {
     path: PathBuf,
     inner: Mutex<TrackerInner>,
@@ -29,22 +29,7 @@ impl Tracker {
     /// Opens (or creates) the tracking file at `path`, loading any post IDs
     /// it already contains. Fails if the file can't be read or created.
     pub fn load(path: &Path) -> io::Result<Self> {
-        let mut seen = HashSet::new();
-        if path.exists() {
-            let content = fs::read_to_string(path)?;
-            for line in content.lines() {
-                let line = line.trim();
-                if line.is_empty() {
-                    continue;
-                }
-                match line.parse::<u64>() {
-                    Ok(id) => {
-                        seen.insert(id);
-                    }
-                    Err(_) => debug!("Ignoring unparseable line '{line}' in tracking file."),
-                }
-            }
-        }
+        let seen = read_ids(path)?;
         let file = OpenOptions::new().create(true).append(true).open(path)?;
         debug!(
             "Loaded {} tracked post IDs from {}.",
@@ -57,6 +42,19 @@ impl Tracker {
         })
     }
 
+    /// Reads the tracking file again, taking in what other runs wrote to it
+    /// since it was loaded, e.g. before rewriting it (see
+    /// [`Tracker::remove`]).
+    pub fn reload(&self) -> io::Result<()> {
+        let seen = read_ids(&self.path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        *self.inner.lock().unwrap() = TrackerInner { seen, file };
+        Ok(())
+    }
+
     /// The path of the backing tracking file.
     pub fn path(&self) -> &Path {
         &self.path
@@ -92,6 +90,43 @@ impl Tracker {
             );
         }
     }
+
+    /// Forgets `post_id`, so it's downloaded again, rewriting the tracking
+    /// file without it. Returns whether it was tracked.
+    pub fn remove(&self, post_id: u64) -> io::Result<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.seen.remove(&post_id) {
+            return Ok(false);
+        }
+        let mut ids = inner.seen.iter().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        let content = ids.iter().map(|id| format!("{id}\n")).collect::<String>();
+        crate::state::write_atomic(&self.path, content)?;
+        // The old handle points at the replaced file.
+        inner.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(true)
+    }
+}
+
+/// The post IDs in the tracking file at `path`, if there is one.
+fn read_ids(path: &Path) -> io::Result<HashSet<u64>> {
+    let mut seen = HashSet::new();
+    if path.exists() {
+        let content = fs::read_to_string(path)?;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.parse::<u64>() {
+                Ok(id) => {
+                    seen.insert(id);
+                }
+                Err(_) => debug!("Ignoring unparseable line '{line}' in tracking file."),
+            }
+        }
+    }
+    Ok(seen)
 }
 
 #[cfg(test)]
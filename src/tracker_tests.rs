@@ -58,3 +58,23 @@ fn insert_duplicate_stays_single_in_file() {
     let content = fs::read_to_string(&path).expect("read");
     assert_eq!(content.lines().filter(|l| l.trim() == "42").count(), 1);
 }
+
+#[test]
+fn remove_untracks_and_keeps_appending() {
+    let base = tempfile::tempdir().expect("tempdir");
+    let path = base.path().join("tracked.txt");
+
+    let tracker = Tracker::load(&path).expect("load");
+    tracker.insert(42);
+    tracker.insert(7);
+    assert!(tracker.remove(42).expect("remove"));
+    assert!(!tracker.remove(42).expect("remove again"));
+    tracker.insert(9);
+    drop(tracker);
+
+    let tracker = Tracker::load(&path).expect("reload");
+    assert!(!tracker.contains(42));
+    assert!(tracker.contains(7));
+    assert!(tracker.contains(9));
+    assert_eq!(tracker.len(), 2);
+}
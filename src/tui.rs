@@ -2,10 +2,6 @@ use std::{
     io::{self, stdout},
     path::Path,
     sync::mpsc::{self, Receiver, Sender},
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-    },
     thread,
     time::Duration,
 };
@@ -15,7 +11,10 @@ use crossterm::{
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use e_cli::{CliContext, Login, Tracker, commands, config, duplicate::DuplicateIndex, funcs};
+use e_cli::{
+    CliContext, DownloadEvent, Login, Tracker, blocking, cli, config, credentials,
+    duplicate::DuplicateIndex, funcs, state::RunLock,
+};
 use ratatui::{
     Frame, Terminal,
     backend::CrosstermBackend,
@@ -24,6 +23,7 @@ use ratatui::{
     text::{Line, Span},
     widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Tabs, Wrap},
 };
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Source {
@@ -31,6 +31,7 @@ enum Source {
     Tags,
     Favourites,
     Pool,
+    Set,
     Preset,
 }
 
@@ -41,6 +42,7 @@ impl Source {
             Self::Tags => "Tags",
             Self::Favourites => "Favourites",
             Self::Pool => "Pool",
+            Self::Set => "Post set",
             Self::Preset => "Preset",
         }
     }
@@ -51,6 +53,7 @@ impl Source {
             Self::Tags => "Tags",
             Self::Favourites => "Favs",
             Self::Pool => "Pool",
+            Self::Set => "Set",
             Self::Preset => "Preset",
         }
     }
@@ -59,7 +62,7 @@ impl Source {
 #[derive(Debug)]
 enum WorkerMessage {
     Status(String),
-    Progress(e_cli::DownloadProgress),
+    Progress(Box<e_cli::DownloadProgress>),
     Finished(e_cli::DownloadStatistics),
     Failed(String),
 }
@@ -75,12 +78,14 @@ struct App {
     status: String,
     log: Vec<String>,
     rx: Option<Receiver<WorkerMessage>>,
-    cancel: Option<Arc<AtomicBool>>,
+    cancel: Option<CancellationToken>,
     config: config::Config,
+    /// Who downloads are signed in as, unless a preset names an account.
+    login: Login,
 }
 
 impl App {
-    fn new() -> Result<Self, String> {
+    fn new(login: Login) -> Result<Self, String> {
         let path = config::path()?;
         let config = config::load(&path)?;
         let global = &config.global;
@@ -109,6 +114,8 @@ impl App {
                     .as_ref()
                     .map(|path| path.to_string_lossy().into_owned())
                     .unwrap_or_default(),
+                config.d_set.set.clone().unwrap_or_default(),
+                config.d_set.indexed.unwrap_or(false).to_string(),
             ],
             selected: 0,
             editing: false,
@@ -120,6 +127,7 @@ impl App {
             rx: None,
             cancel: None,
             config,
+            login,
         })
     }
 
@@ -127,7 +135,7 @@ impl App {
         match index {
             0 => "Tags",
             1 => "Username",
-            2 => "Pool ID",
+            2 => "Pools",
             3 => "Posts/page",
             4 => "Pages",
             5 => "Directory",
@@ -138,7 +146,9 @@ impl App {
             10 => "NSFW API",
             11 => "Login",
             12 => "Lower quality",
-            _ => "Tracking file",
+            13 => "Tracking file",
+            14 => "Set",
+            _ => "Indexed",
         }
     }
 
@@ -148,17 +158,19 @@ impl App {
             Source::Tags => vec![0, 3, 4, 5, 6, 7],
             Source::Favourites => vec![1, 0, 3, 4, 5, 6, 7],
             Source::Pool => vec![2, 5, 6, 7],
+            Source::Set => vec![14, 15, 5, 6, 7],
             Source::Preset => vec![8, 3, 4, 5, 6, 7],
         }
     }
 
     fn change_source(&mut self, direction: i32) {
-        let next = (self.source as i32 + direction).rem_euclid(5);
+        let next = (self.source as i32 + direction).rem_euclid(6);
         self.source = match next {
             0 => Source::Global,
             1 => Source::Tags,
             2 => Source::Favourites,
             3 => Source::Pool,
+            4 => Source::Set,
             _ => Source::Preset,
         };
         self.selected = 0;
@@ -167,8 +179,8 @@ impl App {
     fn handle_key(&mut self, key: KeyEvent) {
         if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
             if let Some(cancel) = &self.cancel {
-                cancel.store(true, Ordering::Relaxed);
-                self.status = "Cancellation requested; finishing the current file...".to_owned();
+                cancel.cancel();
+                self.status = "Cancelling...".to_owned();
                 return;
             }
             self.should_quit = true;
@@ -188,17 +200,15 @@ impl App {
         match key.code {
             KeyCode::Char('q') if self.running => {
                 if let Some(cancel) = &self.cancel {
-                    cancel.store(true, Ordering::Relaxed);
-                    self.status =
-                        "Cancellation requested; finishing the current file...".to_owned();
+                    cancel.cancel();
+                    self.status = "Cancelling...".to_owned();
                 }
             }
             KeyCode::Char('q') => self.should_quit = true,
             KeyCode::Esc if self.running => {
                 if let Some(cancel) = &self.cancel {
-                    cancel.store(true, Ordering::Relaxed);
-                    self.status =
-                        "Cancellation requested; finishing the current file...".to_owned();
+                    cancel.cancel();
+                    self.status = "Cancelling...".to_owned();
                 }
             }
             KeyCode::Left => self.change_source(-1),
@@ -215,9 +225,7 @@ impl App {
                 }
             }
             KeyCode::Char('s') => self.save_config(),
-            KeyCode::Char(' ')
-                if self.source == Source::Global && (9..=12).contains(&self.selected) =>
-            {
+            KeyCode::Char(' ') if is_toggle(self.selected) => {
                 self.fields[self.selected] =
                     (!matches!(self.fields[self.selected].as_str(), "true")).to_string();
             }
@@ -260,7 +268,11 @@ impl App {
                 self.config.d_favs.tags = Some(self.fields[0].clone());
                 self.config.d_favs.count = self.fields[3].parse().ok();
             }
-            Source::Pool => self.config.d_pool.pool_id = self.fields[2].parse().ok(),
+            Source::Pool => self.config.d_pool.pool_id = self.fields[2].trim().parse().ok(),
+            Source::Set => {
+                self.config.d_set.set = Some(self.fields[14].clone());
+                self.config.d_set.indexed = self.fields[15].parse().ok();
+            }
             Source::Preset => {
                 if !self.fields[8].is_empty() {
                     self.config.presets.insert(
@@ -286,15 +298,16 @@ impl App {
         let source = self.source;
         let fields = self.fields.clone();
         let file_config = self.config.clone();
+        let login = self.login.clone();
         let (tx, rx) = mpsc::channel();
-        let cancel = Arc::new(AtomicBool::new(false));
+        let cancel = CancellationToken::new();
         self.rx = Some(rx);
         self.cancel = Some(cancel.clone());
         self.running = true;
         self.progress = 0;
         self.status = "Starting download worker...".to_owned();
         self.log.push("Starting download...".to_owned());
-        thread::spawn(move || run_download(source, fields, file_config, tx, cancel));
+        thread::spawn(move || run_download(source, fields, file_config, login, tx, cancel));
     }
 
     fn poll_worker(&mut self) {
@@ -323,8 +336,30 @@ impl App {
                     };
                     self.status = format!(
                         "{} of {} posts processed ({} bytes downloaded).",
-                        done, progress.total, progress.downloaded_amount as u64
+                        done, progress.total, progress.transferred_bytes
                     );
+                    match progress.event {
+                        Some(DownloadEvent::BytesProgressed(file)) => {
+                            self.status.push_str(&format!(" Fetching {}", file.name));
+                            if let Some(total) = file.total {
+                                self.status
+                                    .push_str(&format!(" ({}/{total} bytes)", file.bytes));
+                            }
+                        }
+                        Some(DownloadEvent::RetryScheduled {
+                            post_id,
+                            attempt,
+                            reason,
+                            delay,
+                        }) => self.log.push(format!(
+                            "Retrying post {post_id} (attempt {attempt}) in {:.1}s: {reason}",
+                            delay.as_secs_f64()
+                        )),
+                        Some(DownloadEvent::PostFailed { post_id, error, .. }) => {
+                            self.log.push(format!("Post {post_id} failed: {error}"))
+                        }
+                        _ => {}
+                    }
                 }
                 WorkerMessage::Finished(stats) => {
                     self.progress = 100;
@@ -356,14 +391,19 @@ fn run_download(
     source: Source,
     fields: Vec<String>,
     config: config::Config,
+    login: Login,
     tx: Sender<WorkerMessage>,
-    cancel: Arc<AtomicBool>,
+    cancel: CancellationToken,
 ) {
     let send = |message| {
         let _ = tx.send(message);
     };
     let dir = Path::new(&fields[5]);
     funcs::ensure_dl_dir(dir);
+    let _lock = match RunLock::acquire(dir, false) {
+        Ok(lock) => lock,
+        Err(error) => return send(WorkerMessage::Failed(error)),
+    };
     let duplicate_path = dir.join(".e-cli-md5.json");
     let duplicate_index = match DuplicateIndex::load(&duplicate_path) {
         Ok(index) => Some(std::sync::Arc::new(index)),
@@ -378,21 +418,22 @@ fn run_download(
         nsfw: fields[10].parse().unwrap_or(false),
         lower_quality: fields[12].parse().unwrap_or(false),
         pages: fields[4].parse().unwrap_or(1),
-        num_threads: fields[6].parse().unwrap_or(5).clamp(1, 10),
+        file_bars: false,
+        num_threads: fields[6].parse().unwrap_or(5).clamp(1, cli::MAX_DOWNLOADS),
         retries: fields[7].parse().unwrap_or(3),
         duplicate_index,
         cancel: Some(cancel.clone()),
+        rate_limit: None,
+        budget: None,
+        disk: None,
+        postprocess: None,
         progress: Some(std::sync::Arc::new({
             let tx = tx.clone();
             move |progress| {
-                let _ = tx.send(WorkerMessage::Progress(progress));
+                let _ = tx.send(WorkerMessage::Progress(Box::new(progress)));
             }
         })),
     };
-    let login = Login {
-        username: String::new(),
-        api_key: String::new(),
-    };
     let tracker_path = if fields[13].is_empty() {
         config.global.track_file.as_deref()
     } else {
@@ -417,7 +458,7 @@ fn run_download(
                 "Select a download source before starting.".to_owned(),
             ));
         }
-        Source::Tags => commands::download_search(
+        Source::Tags => blocking::download_search(
             &context,
             &login,
             &fields[0],
@@ -427,7 +468,7 @@ fn run_download(
             dir,
             tracker.as_ref(),
         ),
-        Source::Favourites => commands::download_favourites(
+        Source::Favourites => blocking::download_favourites(
             &context,
             &login,
             &fields[1],
@@ -438,10 +479,31 @@ fn run_download(
             dir,
             tracker.as_ref(),
         ),
-        Source::Pool => commands::download_pool(
+        Source::Pool => {
+            let queries = fields[2]
+                .split(',')
+                .map(str::trim)
+                .filter(|query| !query.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            match blocking::resolve_pools(&context, &login, &queries, false) {
+                Ok(pools) => blocking::download_pools(
+                    &context,
+                    &login,
+                    &pools,
+                    None,
+                    &mp,
+                    dir,
+                    tracker.as_ref(),
+                ),
+                Err(error) => return send(WorkerMessage::Failed(error)),
+            }
+        }
+        Source::Set => blocking::download_set(
             &context,
             &login,
-            &fields[2].parse().unwrap_or_default(),
+            &fields[14],
+            &fields[15].parse().unwrap_or(false),
             &mp,
             dir,
             tracker.as_ref(),
@@ -453,10 +515,17 @@ fn run_download(
                     fields[8]
                 )));
             };
-            commands::download_search(
+            let login = match preset.account.as_deref() {
+                Some(account) => match credentials::load(&config.global, Some(account)) {
+                    Ok(login) => login.unwrap_or_default(),
+                    Err(error) => return send(WorkerMessage::Failed(error)),
+                },
+                None => login,
+            };
+            blocking::download_preset(
                 &context,
                 &login,
-                preset.tags.as_deref().unwrap_or_default(),
+                preset,
                 &fields[3].parse().ok().or(preset.count).unwrap_or(5),
                 &preset.random.unwrap_or(false),
                 &mp,
@@ -465,15 +534,13 @@ fn run_download(
             )
         }
     };
-    if cancel.load(Ordering::Relaxed) {
-        send(WorkerMessage::Status(
-            "Cancelled after the current file.".to_owned(),
-        ));
+    if cancel.is_cancelled() {
+        send(WorkerMessage::Status("Cancelled.".to_owned()));
     }
     send(WorkerMessage::Finished(stats));
 }
 
-pub fn run() -> Result<(), String> {
+pub fn run(login: Login) -> Result<(), String> {
     enable_raw_mode().map_err(|e| format!("Could not enable raw terminal mode: {e}"))?;
     let mut out = stdout();
     execute!(out, EnterAlternateScreen)
@@ -481,15 +548,18 @@ pub fn run() -> Result<(), String> {
     let backend = CrosstermBackend::new(stdout());
     let mut terminal =
         Terminal::new(backend).map_err(|e| format!("Could not create terminal: {e}"))?;
-    let result = app_loop(&mut terminal);
+    let result = app_loop(&mut terminal, login);
     disable_raw_mode().map_err(|e| format!("Could not restore terminal mode: {e}"))?;
     execute!(stdout(), LeaveAlternateScreen)
         .map_err(|e| format!("Could not leave alternate screen: {e}"))?;
     result.map_err(|e| format!("TUI error: {e}"))
 }
 
-fn app_loop(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>) -> io::Result<()> {
-    let mut app = App::new().map_err(io::Error::other)?;
+fn app_loop(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    login: Login,
+) -> io::Result<()> {
+    let mut app = App::new(login).map_err(io::Error::other)?;
     while !app.should_quit {
         terminal.draw(|frame| draw(frame, &app))?;
         app.poll_worker();
@@ -523,6 +593,7 @@ fn draw(frame: &mut Frame, app: &App) {
         Source::Tags,
         Source::Favourites,
         Source::Pool,
+        Source::Set,
         Source::Preset,
     ]
     .into_iter()
@@ -655,7 +726,7 @@ fn field_hint(index: usize) -> &'static str {
     match index {
         0 => "Search expression used by the API.",
         1 => "Account whose favorites should be downloaded.",
-        2 => "Numeric pool identifier.",
+        2 => "Pool IDs or names, separated by commas.",
         3 => "Posts requested per API page.",
         4 => "Number of API pages to fetch.",
         5 => "Destination directory for downloaded files.",
@@ -666,17 +737,24 @@ fn field_hint(index: usize) -> &'static str {
         10 => "Use the e621.net API instead of e926.net.",
         11 => "Use configured credentials for API requests.",
         12 => "Prefer sample files when available.",
-        _ => "Optional file storing downloaded post IDs.",
+        13 => "Optional file storing downloaded post IDs.",
+        14 => "Numeric set ID or set shortname.",
+        _ => "Prefix files with their position in the set.",
     }
 }
 
+/// Fields holding a `true`/`false` value, flipped with Space instead of edited.
+fn is_toggle(index: usize) -> bool {
+    (9..=12).contains(&index) || index == 15
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn source_tabs_cycle() {
-        let mut app = App::new().expect("config path should be available");
+        let mut app = App::new(Login::default()).expect("config path should be available");
         app.change_source(1);
         assert_eq!(app.source, Source::Tags);
         app.change_source(-1);
@@ -685,7 +763,7 @@ mod tests {
 
     #[test]
     fn visible_fields_match_source() {
-        let mut app = App::new().expect("config path should be available");
+        let mut app = App::new(Login::default()).expect("config path should be available");
         app.source = Source::Tags;
         assert!(app.visible_fields().contains(&0));
         app.source = Source::Pool;
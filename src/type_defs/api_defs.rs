@@ -15,6 +15,17 @@ pub struct Post {
     pub sample: Sample,
     #[serde(default)]
     pub description: Option<String>,
+    #[serde(default)]
+    pub flags: Flags,
+    /// `s`afe, `q`uestionable or `e`xplicit.
+    #[serde(default)]
+    pub rating: String,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Flags {
+    #[serde(default)]
+    pub deleted: bool,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -35,6 +46,10 @@ pub struct File {
 pub struct Tags {
     pub artist: Vec<String>,
     #[serde(default)]
+    pub character: Vec<String>,
+    #[serde(default)]
+    pub species: Vec<String>,
+    #[serde(default)]
     pub general: Vec<String>,
 }
 
@@ -52,6 +67,15 @@ impl Tags {
             Ordering::Less => "unknown-artist".to_string(),
         }
     }
+
+    /// The artist, character, species and general tags, in that order.
+    pub fn all(&self) -> Vec<String> {
+        [&self.artist, &self.character, &self.species, &self.general]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -81,6 +105,52 @@ pub struct PoolData {
     pub description: Option<String>,
     pub post_ids: Vec<u64>,
     pub post_count: u64,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub is_active: bool,
+}
+
+impl PoolData {
+    /// The pool's name as shown on the site; the API stores spaces as `_`.
+    pub fn display_name(&self) -> String {
+        self.name.replace('_', " ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostSetData {
+    pub id: u64,
+    pub name: String,
+    pub shortname: String,
+    pub description: Option<String>,
+    pub post_ids: Vec<u64>,
+    pub post_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtistData {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub other_names: Vec<String>,
+    #[serde(default)]
+    pub urls: Vec<ArtistUrl>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtistUrl {
+    pub url: String,
+    #[serde(default)]
+    pub is_active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagAlias {
+    pub antecedent_name: String,
+    pub consequent_name: String,
+    #[serde(default)]
+    pub status: String,
 }
 
 #[cfg(test)]
@@ -90,6 +160,8 @@ mod tests {
     fn tags(artists: Vec<&str>) -> Tags {
         Tags {
             artist: artists.into_iter().map(String::from).collect(),
+            character: vec![],
+            species: vec![],
             general: vec![],
         }
     }
@@ -111,4 +183,26 @@ mod tests {
             "artist_a, artist_b, artist_c"
         );
     }
+
+    #[test]
+    fn pool_display_name_restores_spaces() {
+        let pool: PoolData = serde_json::from_str(
+            r#"{"id":1,"name":"Cloud_Jumping","description":null,"post_ids":[],
+                "post_count":0,"category":"series","is_active":false}"#,
+        )
+        .expect("pool json");
+        assert_eq!(pool.display_name(), "Cloud Jumping");
+        assert_eq!(pool.category, "series");
+    }
+
+    #[test]
+    fn post_set_deserializes_api_response() {
+        let set: PostSetData = serde_json::from_str(
+            r#"{"id":5,"name":"My Set","shortname":"my_set","description":"",
+                "is_public":true,"post_ids":[3,1,2],"post_count":3}"#,
+        )
+        .expect("set json");
+        assert_eq!(set.shortname, "my_set");
+        assert_eq!(set.post_ids, vec![3, 1, 2]);
+    }
 }